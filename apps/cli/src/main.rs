@@ -1,8 +1,19 @@
-use std::path::PathBuf;
-
-use clap::{crate_version, ArgAction, Parser};
-
-use rzip_lib::{self, RZipError, RZipExtractConfig};
+use std::{
+  env, fs,
+  num::NonZeroUsize,
+  path::{Path, PathBuf},
+  slice, thread,
+};
+
+use clap::{crate_version, ArgAction, Args, Parser, Subcommand};
+
+use rzip_lib::{
+  self,
+  preview::{self, ArchivePreview, PreviewKind},
+  ConflictPolicy, ExtractLimits, ExtractionImpact, ExtractionPlan, NameTemplate, OutputLayout,
+  OwnershipPolicy, RZipError, RZipExtractConfig, RZipWarning, SpecialEntryPolicy, TimestampPolicy,
+  UnsafePathPolicy,
+};
 
 /// RZip - A recursive unzipping tool. Input a path to a file or directory to
 /// recursively unzip, searching the results of each unzip operation for further
@@ -10,6 +21,59 @@ use rzip_lib::{self, RZipError, RZipExtractConfig};
 #[derive(Parser, Debug)]
 #[command(version = crate_version!(), about, long_about = None)]
 #[command(author = "Joseph W. Micheli, josephwmicheli@gmail.com")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct RZipCli {
+  #[command(subcommand)]
+  pub command: Option<RZipCommand>,
+
+  #[command(flatten)]
+  pub params: Option<RZipParams>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RZipCommand {
+  /// Plan extracting an archive or directory, showing what it would unpack and writing the plan
+  /// to apply later.
+  Plan(PlanParams),
+  /// Extract the archives in a plan, once it's checked that none have changed since planning.
+  Apply(ApplyParams),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct PlanParams {
+  /// Where to write the plan.
+  #[arg(long, short, default_value = "rzip_plan.json")]
+  pub out: PathBuf,
+
+  #[command(flatten)]
+  pub params: RZipParams,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ApplyParams {
+  /// The plan to apply.
+  pub plan_path: PathBuf,
+
+  /// Start without first checking that what the plan unpacks fits in the free space of each
+  /// filesystem. (default: false)
+  #[arg(long, action = ArgAction::SetTrue)]
+  pub force: bool,
+
+  /// A password to try on encrypted archives. Can be given several times, and is added to any in
+  /// the RZIP_PASSWORDS environment variable (one per line).
+  #[arg(long = "password")]
+  pub passwords: Vec<String>,
+
+  /// A file of passwords to try on encrypted archives, one per line.
+  #[arg(long)]
+  pub password_file: Option<PathBuf>,
+
+  /// How many archives to extract at once. Defaults to the number of cores.
+  #[arg(long, default_value_t = default_jobs())]
+  pub jobs: usize,
+}
+
+#[derive(Args, Debug, Default)]
 pub struct RZipParams {
   /// The path to an archive file or directory to unpack.
   pub target_path: PathBuf,
@@ -18,6 +82,11 @@ pub struct RZipParams {
   #[arg(long, action = ArgAction::SetTrue)]
   pub live: bool,
 
+  /// Start a live run without first checking that what it unpacks fits in the free space of each
+  /// filesystem. (default: false)
+  #[arg(long, action = ArgAction::SetTrue)]
+  pub force: bool,
+
   /// The directory to unpack files into, preserving directory structure. Defaults to the directory each archive is in.
   #[arg(long)]
   pub out_dir: Option<PathBuf>,
@@ -25,20 +94,208 @@ pub struct RZipParams {
   /// Delete archives after extracting them. (default: false)
   #[arg(long, action = ArgAction::SetTrue)]
   pub delete_archives: bool,
+
+  /// A password to try on encrypted archives. Can be given several times, and is added to any in
+  /// the RZIP_PASSWORDS environment variable (one per line).
+  #[arg(long = "password")]
+  pub passwords: Vec<String>,
+
+  /// A file of passwords to try on encrypted archives, one per line.
+  #[arg(long)]
+  pub password_file: Option<PathBuf>,
+
+  /// The most bytes a single archive may unpack to.
+  #[arg(long)]
+  pub max_archive_bytes: Option<u64>,
+
+  /// The most bytes the whole run may unpack, across every nested archive.
+  #[arg(long)]
+  pub max_run_bytes: Option<u64>,
+
+  /// The largest ratio of the bytes an archive unpacks to over the archive's own size.
+  #[arg(long)]
+  pub max_ratio: Option<u64>,
+
+  /// The most entries a single archive may contain.
+  #[arg(long)]
+  pub max_entries: Option<u64>,
+
+  /// How many levels of nested archives to unpack. Archives nested deeper are left packed.
+  #[arg(long)]
+  pub max_depth: Option<usize>,
+
+  /// What to do with entries that would unpack outside of their archive's output directory:
+  /// reject the archive, or skip the entries.
+  #[arg(long, default_value_t)]
+  pub unsafe_paths: UnsafePathPolicy,
+
+  /// What to do with symlinks, hard links, FIFOs and device nodes: allow, skip, materialize them as
+  /// regular files, or reject the archive.
+  #[arg(long, default_value_t)]
+  pub special_entries: SpecialEntryPolicy,
+
+  /// Give unpacked files the owners recorded in the archive. Only root can, so anyone else keeps
+  /// the files with a warning.
+  #[arg(long, action = ArgAction::SetTrue)]
+  pub preserve_ownership: bool,
+
+  /// The user id to give unpacked files, in place of whoever runs the extraction.
+  #[arg(long)]
+  pub uid: Option<u32>,
+
+  /// The group id to give unpacked files, in place of the current user's group.
+  #[arg(long)]
+  pub gid: Option<u32>,
+
+  /// Octal permission bits to clear from unpacked files, such as 022. Setuid, setgid and sticky
+  /// bits are always cleared.
+  #[arg(long, value_parser = parse_umask, default_value = "0")]
+  pub umask: u32,
+
+  /// Which timestamps unpacked files get: preserve the ones recorded in the archive, or now.
+  #[arg(long, default_value_t)]
+  pub timestamps: TimestampPolicy,
+
+  /// Restore the extended attributes recorded in tar archives, in the `user` namespace only.
+  /// (default: false)
+  #[arg(long, action = ArgAction::SetTrue)]
+  pub xattrs: bool,
+
+  /// Restore extended attributes in every namespace, including ones like `security.capability`
+  /// that can grant privileges. (default: false)
+  #[arg(long, action = ArgAction::SetTrue, requires = "xattrs")]
+  pub all_xattrs: bool,
+
+  /// What to do when an archive's output, or a file in it, is already there: overwrite, skip,
+  /// rename to `foo (1)`, or merge keeping the newer files.
+  #[arg(long, default_value_t)]
+  pub on_conflict: ConflictPolicy,
+
+  /// Move an archive's contents up a level when it holds a single top-level folder, avoiding
+  /// `foo/foo/`. (default: false)
+  #[arg(long, action = ArgAction::SetTrue)]
+  pub collapse_root: bool,
+
+  /// How to lay out what's unpacked: nested, mirroring the archives, or flat, moving every file
+  /// into one directory with an index of where each came from.
+  #[arg(long, default_value_t)]
+  pub layout: OutputLayout,
+
+  /// The name to give each archive's output, from the placeholders {stem}, {ext}, {date},
+  /// {hash8} and {parent}, such as `{stem}-{date}` or `{parent}/{stem}`.
+  #[arg(long, default_value_t)]
+  pub name_template: NameTemplate,
+
+  /// How many archives to extract at once, including archives nested in the same one. Defaults to
+  /// the number of cores.
+  #[arg(long, default_value_t = default_jobs())]
+  pub jobs: usize,
+
+  /// Unpack nested archives of up to this many bytes straight from memory, so that only the files
+  /// in them are written to disk.
+  #[arg(long)]
+  pub max_in_memory_bytes: Option<u64>,
 }
 
-impl From<RZipParams> for RZipExtractConfig {
-  fn from(value: RZipParams) -> Self {
-    Self {
+/// Environment variable holding passwords to try on encrypted archives, one per line.
+const PASSWORDS_ENV_VAR: &str = "RZIP_PASSWORDS";
+
+impl TryFrom<RZipParams> for RZipExtractConfig {
+  type Error = RZipError;
+
+  fn try_from(value: RZipParams) -> Result<Self, Self::Error> {
+    Ok(Self {
       target_path: value.target_path,
       out_dir: value.out_dir,
       delete_after_extracting: value.delete_archives,
-    }
+      passwords: collect_passwords(value.passwords, value.password_file.as_deref())?,
+      limits: ExtractLimits {
+        max_archive_bytes: value.max_archive_bytes,
+        max_run_bytes: value.max_run_bytes,
+        max_ratio: value.max_ratio,
+        max_entries: value.max_entries,
+      },
+      usage: Default::default(),
+      max_depth: value.max_depth,
+      unsafe_paths: value.unsafe_paths,
+      special_entries: value.special_entries,
+      ownership: match (value.uid, value.gid) {
+        (None, None) if value.preserve_ownership => OwnershipPolicy::Preserve,
+        (None, None) => OwnershipPolicy::Ignore,
+        (uid, gid) => OwnershipPolicy::Remap { uid, gid },
+      },
+      umask: value.umask,
+      timestamps: value.timestamps,
+      preserve_xattrs: value.xattrs,
+      all_xattrs: value.all_xattrs,
+      on_conflict: value.on_conflict,
+      collapse_single_root: value.collapse_root,
+      layout: value.layout,
+      name_template: value.name_template,
+      jobs: value.jobs,
+      max_in_memory_bytes: value.max_in_memory_bytes,
+      in_memory: Default::default(),
+    })
   }
 }
 
+/// One job per core, or a single job when the number of cores is unknown.
+fn default_jobs() -> usize {
+  thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Parse a umask given in octal.
+fn parse_umask(umask: &str) -> Result<u32, String> {
+  match u32::from_str_radix(umask, 8) {
+    Ok(umask) if umask <= 0o777 => Ok(umask),
+    _ => Err(format!(
+      "Invalid umask {umask}, expected octal digits up to 777"
+    )),
+  }
+}
+
+/// Gather the passwords to try, with those from flags first, then the environment, then the
+/// password file.
+fn collect_passwords(
+  mut passwords: Vec<String>,
+  password_file: Option<&Path>,
+) -> Result<Vec<String>, RZipError> {
+  if let Ok(env_passwords) = env::var(PASSWORDS_ENV_VAR) {
+    passwords.extend(parse_passwords(&env_passwords));
+  }
+  if let Some(password_file) = password_file {
+    passwords.extend(parse_passwords(&fs::read_to_string(password_file)?));
+  }
+
+  Ok(passwords)
+}
+
+/// Split a list of passwords into one per line, ignoring blank lines.
+fn parse_passwords(passwords: &str) -> impl Iterator<Item = String> + '_ {
+  passwords
+    .lines()
+    .filter(|password| !password.is_empty())
+    .map(|password| password.to_string())
+}
+
 fn main() {
-  let params = RZipParams::parse();
+  let cli = RZipCli::parse();
+  let params = match (cli.command, cli.params) {
+    (Some(RZipCommand::Plan(plan_params)), _) => {
+      if let Err(e) = handle_plan(plan_params) {
+        println!("Planning encountered error: {e}");
+      }
+      return;
+    }
+    (Some(RZipCommand::Apply(apply_params)), _) => {
+      if let Err(e) = handle_apply(apply_params) {
+        println!("Applying plan encountered error: {e}");
+      }
+      return;
+    }
+    (None, Some(params)) => params,
+    (None, None) => unreachable!("clap requires a target path without a command"),
+  };
 
   if !params.live {
     println!("Performing a dry-run. Set --live flag to execute unzip operation.");
@@ -89,25 +346,35 @@ fn handle_dir(params: RZipParams) -> Result<(), RZipError> {
     println!("Dry run operations (archive => output path):");
   }
 
-  // Perform extraction
-  let is_live = params.live;
-  let extract_config = params.into();
-  for item_path in archives {
-    let out_path = rzip_lib::get_out_path_for_archive(&item_path, &extract_config)?;
-    if is_live {
-      print!("{:?}... ", item_path);
-      // Live run logic
-      match rzip_lib::recursive_file_extract(&item_path, &out_path, &extract_config) {
-        Ok(_) => println!("Done."),
-        Err(e) => println!("Error: {e}"),
-      }
-    } else {
-      // Dry run (explains what it would have done)
-      println!("{} => {}", item_path.display(), out_path.display());
+  // Perform extraction, with archives that would share an output unpacking to their own
+  let (is_live, force) = (params.live, params.force);
+  let extract_config = params.try_into()?;
+  let out_paths = rzip_lib::get_out_paths_for_archives(&archives, &extract_config)?;
+  if is_live {
+    // Live run logic, reporting on each archive in order
+    if !force {
+      check_free_space(&archives, &out_paths, &extract_config)?;
     }
+    return rzip_lib::extract_archives(&archives, &out_paths, &extract_config, print_extraction);
   }
 
-  Ok(())
+  // Dry run (explains what it would have done)
+  let mut previews = vec![];
+  for (item_path, out_path) in archives.into_iter().zip(out_paths) {
+    let shared_out_path = rzip_lib::get_out_path_for_archive(&item_path, &extract_config)?;
+    if out_path == shared_out_path {
+      println!("{} => {}", item_path.display(), out_path.display());
+    } else {
+      println!(
+        "{} => {} (another archive also unpacks to {})",
+        item_path.display(),
+        out_path.display(),
+        shared_out_path.display()
+      );
+    }
+    previews.extend(print_preview_of(&item_path, &out_path, &extract_config));
+  }
+  print_impact(&previews)
 }
 
 /// The branch of the main execution sequence that handles an input
@@ -123,14 +390,24 @@ fn handle_file(params: RZipParams) -> Result<(), RZipError> {
     )));
   }
 
-  let is_live = params.live;
-  let extract_config: RZipExtractConfig = params.into();
+  let (is_live, force) = (params.live, params.force);
+  let extract_config: RZipExtractConfig = params.try_into()?;
   let out_path = rzip_lib::get_out_path_for_archive(&extract_config.target_path, &extract_config)?;
   if is_live {
     // Live run
+    if !force {
+      check_free_space(
+        slice::from_ref(&extract_config.target_path),
+        slice::from_ref(&out_path),
+        &extract_config,
+      )?;
+    }
     match rzip_lib::recursive_file_extract(&extract_config.target_path, &out_path, &extract_config)
     {
-      Ok(_) => println!("Successfully extracted archive"),
+      Ok(warnings) => {
+        println!("Successfully extracted archive");
+        print_warnings(&warnings);
+      }
       Err(e) => println!("Error extracting archive: {e}"),
     }
   } else {
@@ -141,11 +418,192 @@ fn handle_file(params: RZipParams) -> Result<(), RZipError> {
       extract_config.target_path.display(),
       out_path.display()
     );
+    let preview = print_preview_of(&extract_config.target_path, &out_path, &extract_config);
+    print_impact(&Vec::from_iter(preview))?;
+  }
+
+  Ok(())
+}
+
+/// Plan extracting the target path, showing what it would unpack as a dry run does, then write
+/// the plan for `rzip apply`.
+fn handle_plan(plan_params: PlanParams) -> Result<(), RZipError> {
+  if plan_params.params.live {
+    return Err(RZipError::RuntimeError(
+      "Plans are run with rzip apply rather than --live".to_string(),
+    ));
+  }
+  let extract_config: RZipExtractConfig = plan_params.params.try_into()?;
+  let plan = ExtractionPlan::new(&extract_config)?;
+
+  println!("Planned operations (archive => output path):");
+  let mut previews = vec![];
+  for archive in &plan.archives {
+    println!(
+      "{} => {}",
+      archive.path.display(),
+      archive.out_path.display()
+    );
+    previews.extend(print_preview_of(
+      &archive.path,
+      &archive.out_path,
+      &extract_config,
+    ));
+  }
+  print_impact(&previews)?;
+
+  fs::write(&plan_params.out, plan.to_json()?)?;
+  println!(
+    "Wrote the plan to {}, run it with rzip apply {0}",
+    plan_params.out.display()
+  );
+  Ok(())
+}
+
+/// Extract the archives in a plan, refusing to start if any of them changed since it was made.
+fn handle_apply(apply_params: ApplyParams) -> Result<(), RZipError> {
+  let plan = ExtractionPlan::from_json(&fs::read_to_string(&apply_params.plan_path)?)?;
+  plan.verify()?;
+  let extract_config = RZipExtractConfig {
+    passwords: collect_passwords(
+      apply_params.passwords,
+      apply_params.password_file.as_deref(),
+    )?,
+    jobs: apply_params.jobs,
+    ..plan.config
+  };
+
+  let (archives, out_paths): (Vec<_>, Vec<_>) = plan
+    .archives
+    .into_iter()
+    .map(|archive| (archive.path, archive.out_path))
+    .unzip();
+  if !apply_params.force {
+    check_free_space(&archives, &out_paths, &extract_config)?;
+  }
+  println!("Extracting {} archives...", archives.len());
+  rzip_lib::extract_archives(&archives, &out_paths, &extract_config, print_extraction)
+}
+
+/// Report how extracting an archive went, as part of a live run over several.
+fn print_extraction(path: &Path, result: Result<Vec<RZipWarning>, RZipError>) {
+  print!("{:?}... ", path);
+  match result {
+    Ok(warnings) => {
+      println!("Done.");
+      print_warnings(&warnings);
+    }
+    Err(e) => println!("Error: {e}"),
+  }
+}
+
+/// Check that what the archives would unpack fits on disk before a live run starts. Archives that
+/// can't be previewed are left out of the estimate with a warning, and for the run to report.
+fn check_free_space(
+  archives: &[PathBuf],
+  out_paths: &[PathBuf],
+  config: &RZipExtractConfig,
+) -> Result<(), RZipError> {
+  println!("Checking free space (skip with --force)...");
+  // Previews count towards the run limit, which the run itself starts afresh
+  let config = &RZipExtractConfig {
+    usage: Default::default(),
+    ..config.clone()
+  };
+  let mut previews = vec![];
+  for (path, out_path) in archives.iter().zip(out_paths) {
+    match preview::preview_recursive_extract(path, out_path, config) {
+      Ok(preview) => previews.push(preview),
+      Err(e) => println!("Warning: Left {path:?} out of the free space check: {e}"),
+    }
+  }
+  ExtractionImpact::estimate(&previews)?.check_free_space()
+}
+
+/// Print the tree that extracting an archive would produce, below the line naming it.
+fn print_preview_of(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Option<ArchivePreview> {
+  match preview::preview_recursive_extract(path, out_path, config) {
+    Ok(preview) => {
+      print_preview(&preview, 1);
+      Some(preview)
+    }
+    Err(e) => {
+      println!("  Error: {e}");
+      None
+    }
+  }
+}
+
+/// Print how much a run would unpack to each filesystem, and the files it would replace.
+fn print_impact(previews: &[ArchivePreview]) -> Result<(), RZipError> {
+  let impact = ExtractionImpact::estimate(previews)?;
+  println!("Would unpack {} bytes", impact.unpacked_bytes);
+  for disk in &impact.disks {
+    println!(
+      "  {} bytes to the filesystem holding {}, which has {} bytes free",
+      disk.required_bytes,
+      disk.path.display(),
+      disk.available_bytes
+    );
+  }
+  for overwritten in &impact.overwritten {
+    println!("Would overwrite {}", overwritten.display());
+  }
+  if let Err(e) = impact.check_free_space() {
+    println!("Warning: {e}. A live run won't start without --force.");
   }
 
   Ok(())
 }
 
+/// Print what an archive would unpack, indented by `depth` levels. Paths are shown relative to the
+/// archive's output.
+fn print_preview(preview: &ArchivePreview, depth: usize) {
+  let indent = "  ".repeat(depth);
+  let relative = |path: &Path| match path.strip_prefix(&preview.out_path) {
+    Ok(relative_path) if !relative_path.as_os_str().is_empty() => relative_path.to_path_buf(),
+    _ => PathBuf::from(path.file_name().unwrap_or_default()),
+  };
+
+  for entry in &preview.entries {
+    let path = relative(&entry.path);
+    match &entry.kind {
+      PreviewKind::Directory => println!("{indent}{}/", path.display()),
+      PreviewKind::File(size) => println!("{indent}{} ({size} bytes)", path.display()),
+      PreviewKind::Archive(size, nested) => {
+        println!(
+          "{indent}{} ({size} bytes) => {}",
+          path.display(),
+          relative(&nested.out_path).display()
+        );
+        print_preview(nested, depth + 1);
+      }
+      PreviewKind::Unlisted(size, reason) => println!(
+        "{indent}{} ({size} bytes, can't read its listing: {reason})",
+        path.display()
+      ),
+      PreviewKind::Index => println!("{indent}{} (index)", path.display()),
+    }
+  }
+  for warning in &preview.warnings {
+    println!("{indent}Warning: {warning}");
+  }
+  for deleted in &preview.deleted {
+    println!("{indent}Would delete {}", deleted.display());
+  }
+}
+
+/// Print the warnings an extraction finished with.
+fn print_warnings(warnings: &[RZipWarning]) {
+  for warning in warnings {
+    println!("Warning: {warning}");
+  }
+}
+
 #[cfg(test)]
 mod test {
   use std::{
@@ -172,6 +630,7 @@ mod test {
       live: false,
       out_dir: Some(out_path.clone()),
       delete_archives: false,
+      ..Default::default()
     };
     handle_dir(params).unwrap();
 
@@ -181,13 +640,14 @@ mod test {
       live: true,
       out_dir: Some(out_path.clone()),
       delete_archives: false,
+      ..Default::default()
     };
     handle_dir(params).unwrap();
 
     // Test expected files
     let packed_tar_gz_tar_gz = temp_dir.path().join("test_data/packed_tar_gz.tar.gz");
-    let packed_tar_dir = out_path.join("packed_tar_gz.tar");
-    let doc_tar_gz = out_path.join("packed_tar_gz.tar/doc_tar_gz.txt");
+    let packed_tar_dir = out_path.join("packed_tar_gz");
+    let doc_tar_gz = out_path.join("packed_tar_gz/doc_tar_gz.txt");
     assert!(packed_tar_gz_tar_gz.exists());
     assert!(packed_tar_dir.exists());
     assert!(doc_tar_gz.exists());
@@ -202,6 +662,7 @@ mod test {
       live: true,
       out_dir: None,
       delete_archives: false,
+      ..Default::default()
     };
     handle_dir(params).unwrap();
   }
@@ -218,6 +679,7 @@ mod test {
       live: false,
       out_dir: Some(out_path.clone()),
       delete_archives: false,
+      ..Default::default()
     };
     handle_file(params).unwrap();
 
@@ -227,13 +689,14 @@ mod test {
       live: true,
       out_dir: Some(out_path.clone()),
       delete_archives: false,
+      ..Default::default()
     };
     handle_file(params).unwrap();
 
     // Test expected files
     let packed_tar_gz_tar_gz = temp_dir.path().join("packed_tar_gz.tar.gz");
-    let packed_tar_dir = out_path.join("packed_tar_gz.tar");
-    let doc_tar_gz = out_path.join("packed_tar_gz.tar/doc_tar_gz.txt");
+    let packed_tar_dir = out_path.join("packed_tar_gz");
+    let doc_tar_gz = out_path.join("packed_tar_gz/doc_tar_gz.txt");
     assert!(packed_tar_gz_tar_gz.exists());
     assert!(packed_tar_dir.exists());
     assert!(doc_tar_gz.exists());
@@ -252,12 +715,64 @@ mod test {
       live: true,
       out_dir: None,
       delete_archives: false,
+      ..Default::default()
     };
     // The file isn't an archive so we will get an error
     let res = handle_file(params);
     assert!(res.is_err());
   }
 
+  #[test]
+  fn test_handle_file_with_password_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_root =
+      PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../lib/tests/data/encrypted");
+    let target_path = temp_dir.path().join("encrypted_zip.zip");
+    fs::copy(data_root.join("encrypted_zip.zip"), &target_path).unwrap();
+    let password_file = temp_dir.path().join("passwords.txt");
+    fs::write(&password_file, "wrong\n\nrzip\n").unwrap();
+
+    let params = RZipParams {
+      target_path,
+      live: true,
+      passwords: vec!["also_wrong".to_string()],
+      password_file: Some(password_file),
+      ..Default::default()
+    };
+    handle_file(params).unwrap();
+
+    // Test expected files
+    let doc = temp_dir.path().join("encrypted_zip/doc_encrypted_zip.txt");
+    assert!(doc.exists());
+  }
+
+  #[test]
+  fn test_plan_and_apply() {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = temp_dir.path().join("test_data");
+    fs::create_dir_all(&target_path).unwrap();
+    copy_tar_gz_data_to(&target_path);
+    let plan_path = temp_dir.path().join("plan.json");
+
+    let params = PlanParams {
+      out: plan_path.clone(),
+      params: RZipParams {
+        target_path: target_path.clone(),
+        collapse_root: true,
+        ..Default::default()
+      },
+    };
+    handle_plan(params).unwrap();
+    assert!(!target_path.join("packed_tar_gz").exists());
+
+    let params = ApplyParams {
+      plan_path,
+      ..Default::default()
+    };
+    handle_apply(params).unwrap();
+    assert!(target_path.join("packed_tar_gz/doc_tar_gz.txt").exists());
+  }
+
   fn get_individual_data_root() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../lib/tests/data/indiv")
   }
@@ -1,5 +1,9 @@
+use std::{io, path::PathBuf};
+
 use thiserror::Error;
 
+use crate::policy::SpecialEntryKind;
+
 #[derive(Error, Debug)]
 pub enum RZipError {
   #[error("Extration error: {0:?}")]
@@ -10,14 +14,154 @@ pub enum RZipError {
   RuntimeError(String),
   #[error("Unsupported archive: {0}")]
   UnsupportedArchive(String),
+  #[error(
+    "Multi-volume archive {} is missing volumes: {}",
+    .0.display(),
+    display_paths(.1)
+  )]
+  MissingVolumes(PathBuf, Vec<PathBuf>),
+  #[error("Archive {} is encrypted, no matching password", .0.display())]
+  Encrypted(PathBuf),
+  #[error("Archive {} exceeds a limit: {1}", .0.display())]
+  LimitExceeded(PathBuf, LimitViolation),
+  #[error(
+    "Archive {} has entry {1} that reaches outside the output directory",
+    .0.display()
+  )]
+  UnsafePath(PathBuf, String),
+  #[error("Archive {} has {2} {1}, which the policy rejects", .0.display())]
+  SpecialEntry(PathBuf, String, SpecialEntryKind),
+  #[error(
+    "Not enough free space for {}: {1} bytes would be unpacked to its filesystem, which has {2} \
+     bytes free",
+    .0.display()
+  )]
+  InsufficientSpace(PathBuf, u64, u64),
+  #[error("Extraction plan error: {0}")]
+  Plan(#[from] serde_json::Error),
+  #[error("Archive {} is missing or has changed since it was planned", .0.display())]
+  ArchiveChanged(PathBuf),
+}
+
+/// A problem that left part of a run undone without failing it.
+#[derive(Error, Debug)]
+pub enum RZipWarning {
+  #[error(
+    "Left {} packed, it's nested deeper than the maximum depth of {1}",
+    .0.display()
+  )]
+  MaxDepth(PathBuf, usize),
+  #[error(
+    "Left {} packed, it's a copy of {} which it's nested in",
+    .0.display(),
+    .1.display()
+  )]
+  ArchiveCycle(PathBuf, PathBuf),
+  #[error("Left {} packed, none of the backends could unpack it", .0.display())]
+  Undecodable(PathBuf),
+  #[error(
+    "Skipped entry {1} of {}, it reaches outside the output directory",
+    .0.display()
+  )]
+  UnsafePath(PathBuf, String),
+  #[error("Skipped {2} {1} of {}", .0.display())]
+  SpecialEntry(PathBuf, String, SpecialEntryKind),
+  #[error("Left {} packed, its output {} already exists", .0.display(), .1.display())]
+  OutputExists(PathBuf, PathBuf),
+  #[error("Skipped entry {1} of {}, keeping the file already there", .0.display())]
+  Conflict(PathBuf, String),
+  #[error("Skipped extended attribute {2} of entry {1} of {}: {3}", .0.display())]
+  Xattr(PathBuf, String, String, String),
+  #[error(
+    "Left the files of {} to the current user, only root can preserve their owners",
+    .0.display()
+  )]
+  KeptOwners(PathBuf),
+}
+
+/// A limit from [ExtractLimits](crate::limits::ExtractLimits) that unpacking an archive exceeded.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitViolation {
+  #[error("more than {0} uncompressed bytes in one archive")]
+  ArchiveBytes(u64),
+  #[error("more than {0} uncompressed bytes in this run")]
+  RunBytes(u64),
+  #[error("compression ratio above {0}")]
+  Ratio(u64),
+  #[error("more than {0} entries")]
+  Entries(u64),
 }
 
 #[derive(Error, Debug)]
 pub enum RZipProcessingError {
   #[error("Compress-tools error: {0}")]
-  CompressTools(#[from] compress_tools::Error),
+  CompressTools(compress_tools::Error),
   #[error("Seven-z error: {0}")]
-  SevenZ(#[from] sevenz_rust::Error),
+  SevenZ(sevenz_rust::Error),
+  #[error("Zip error: {0}")]
+  Zip(zip::result::ZipError),
+  #[error("Zstd error: {0}")]
+  Zstd(#[from] ruzstd::decoding::errors::FrameDecoderError),
   #[error("IO error occurred: {0}")]
-  Io(#[from] std::io::Error),
+  Io(std::io::Error),
+  #[error("Encrypted, no matching password")]
+  NoMatchingPassword,
+  #[error("Limit exceeded: {0}")]
+  LimitExceeded(#[from] LimitViolation),
+  #[error("Entry {0} reaches outside the output directory")]
+  UnsafePath(String),
+  #[error("Entry {0} has a type this backend can't unpack")]
+  UnsupportedEntry(String),
+  #[error("Entry {0} is a {1}, which the policy rejects")]
+  SpecialEntry(String, SpecialEntryKind),
+}
+
+// Limit violations raised inside a backend's reads or writes come back wrapped in its IO errors,
+// so each conversion unwraps them again.
+impl From<io::Error> for RZipProcessingError {
+  fn from(e: io::Error) -> Self {
+    match e
+      .get_ref()
+      .and_then(|inner| inner.downcast_ref::<LimitViolation>())
+    {
+      Some(violation) => Self::LimitExceeded(*violation),
+      None => Self::Io(e),
+    }
+  }
+}
+
+impl From<compress_tools::Error> for RZipProcessingError {
+  fn from(e: compress_tools::Error) -> Self {
+    match e {
+      compress_tools::Error::Io(e) => e.into(),
+      e => Self::CompressTools(e),
+    }
+  }
+}
+
+impl From<sevenz_rust::Error> for RZipProcessingError {
+  fn from(e: sevenz_rust::Error) -> Self {
+    match e {
+      sevenz_rust::Error::Io(e, _) => e.into(),
+      e => Self::SevenZ(e),
+    }
+  }
+}
+
+impl From<zip::result::ZipError> for RZipProcessingError {
+  fn from(e: zip::result::ZipError) -> Self {
+    match e {
+      zip::result::ZipError::Io(e) => e.into(),
+      e => Self::Zip(e),
+    }
+  }
+}
+
+/// Format a list of paths for an error message.
+fn display_paths(paths: &[PathBuf]) -> String {
+  paths
+    .iter()
+    .map(|path| path.display().to_string())
+    .collect::<Vec<_>>()
+    .join(", ")
 }
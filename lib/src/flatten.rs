@@ -0,0 +1,302 @@
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+use crate::{error::RZipWarning, policy, policy::SpecialEntryKind, unpack};
+
+/// The name of the index written to a flattened output. Each line holds a flattened file's name,
+/// then where it came from: the archive it was nested in at each level, from the outermost down,
+/// and finally its own path in the innermost archive. The fields are separated by tabs.
+pub const INDEX_FILE_NAME: &str = "rzip_index.tsv";
+
+/// An archive unpacked during a run, and where its output went.
+#[derive(Debug, Clone)]
+pub(crate) struct UnpackedArchive {
+  pub(crate) path: PathBuf,
+  pub(crate) out_path: PathBuf,
+  /// Each path that the archive's output was moved to, which is `out_path` itself unless the
+  /// output was merged into a directory already there.
+  pub(crate) placed: Vec<PathBuf>,
+}
+
+/// Move every file this run placed under `flat_path` that isn't an unpacked archive into
+/// `flat_path` itself, then remove what's left of the nesting and write an [INDEX_FILE_NAME].
+/// `unpacked` holds each archive that was unpacked, starting with the one unpacked to `flat_path`.
+/// Whatever was there before the run is left where it is.
+///
+/// Files are visited in path order, and ones whose names are taken are numbered as in
+/// `doc (1).txt`, so the same archive always flattens to the same names. Links are pointed at
+/// where the file they lead to is moved, and links to anything but a flattened file are left out
+/// with a warning.
+pub(crate) fn flatten_output(
+  flat_path: &Path,
+  unpacked: &[UnpackedArchive],
+) -> io::Result<Vec<RZipWarning>> {
+  // A single decompressed file has nothing to flatten
+  if !flat_path.is_dir() {
+    return Ok(vec![]);
+  }
+
+  // Paths placed inside of another placed path were unpacked along with it
+  let placed: Vec<&PathBuf> = unpacked
+    .iter()
+    .flat_map(|archive| &archive.placed)
+    .collect();
+  let placed_roots: Vec<&PathBuf> = placed
+    .iter()
+    .filter(|path| path.starts_with(flat_path))
+    .filter(|path| {
+      !placed
+        .iter()
+        .any(|other| other != *path && path.starts_with(other))
+    })
+    .copied()
+    .collect();
+  let mut files = vec![];
+  for root in &placed_roots {
+    match fs::symlink_metadata(root) {
+      Ok(metadata) if metadata.is_dir() => collect_files(root, unpacked, &mut files)?,
+      Ok(_) if !is_unpacked(root, unpacked) => files.push(root.to_path_buf()),
+      _ => (),
+    }
+  }
+  files.sort();
+
+  // Links are moved as links to their file's flat name, or left out when that can't be done
+  let mut warnings = vec![];
+  let mut link_targets = HashMap::new();
+  let mut left_out = HashSet::new();
+  let file_set: HashSet<PathBuf> = files.iter().cloned().collect();
+  for file in &files {
+    if !fs::symlink_metadata(file)?.is_symlink() {
+      continue;
+    }
+    match link_target(flat_path, file, &file_set)? {
+      Some(target) => {
+        link_targets.insert(file.clone(), target);
+      }
+      None => {
+        left_out.insert(file.clone());
+        let (archive, entry_name) = origin(file, unpacked);
+        warnings.push(RZipWarning::SpecialEntry(
+          archive,
+          entry_name,
+          SpecialEntryKind::Symlink,
+        ));
+      }
+    }
+  }
+  files.retain(|file| !left_out.contains(file));
+
+  // What was there before the run keeps its name
+  let is_fresh = placed_roots.iter().any(|root| *root == flat_path);
+  let mut kept = HashSet::new();
+  if !is_fresh {
+    for entry in fs::read_dir(flat_path)? {
+      let path = entry?.path();
+      if !placed_roots.contains(&&path) {
+        kept.insert(path);
+      }
+    }
+  }
+  let flat_files = flat_paths(flat_path, &files, kept);
+  let flat_names: HashMap<&Path, &Path> = files
+    .iter()
+    .map(PathBuf::as_path)
+    .zip(flat_files.iter().map(PathBuf::as_path))
+    .collect();
+
+  // Everything is moved aside before the nesting is removed, as a file could be in the way of a
+  // directory or the other way round
+  let moved_path = policy::available_path(&flat_path.join(".rzip_flat"), false);
+  fs::create_dir(&moved_path)?;
+  let mut index = vec![];
+  for (file, flat_file) in files.iter().zip(&flat_files) {
+    let flat_name = flat_file.file_name().unwrap_or_default().to_os_string();
+    match link_targets.get(file) {
+      // Everything ends up side by side, so a link only needs the name of its file
+      Some(target) => {
+        let target_name = flat_names[target.as_path()].file_name().unwrap_or_default();
+        unpack::create_symlink(&target_name.to_string_lossy(), &moved_path.join(&flat_name))?;
+        fs::remove_file(file)?;
+      }
+      None => fs::rename(file, moved_path.join(&flat_name))?,
+    }
+    index.push((flat_name, nesting_chain(file, unpacked)));
+  }
+
+  let nesting = match is_fresh {
+    true => fs::read_dir(flat_path)?
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect::<io::Result<Vec<_>>>()?,
+    false => placed_roots.into_iter().cloned().collect(),
+  };
+  for path in nesting {
+    match fs::symlink_metadata(&path) {
+      _ if path == moved_path => (),
+      Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+      Ok(_) => fs::remove_file(path)?,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+      Err(e) => return Err(e),
+    }
+  }
+  for (flat_name, _) in &index {
+    fs::rename(moved_path.join(flat_name), flat_path.join(flat_name))?;
+  }
+  fs::remove_dir(moved_path)?;
+
+  let mut index_file = io::BufWriter::new(fs::File::create(flat_path.join(INDEX_FILE_NAME))?);
+  for (flat_name, chain) in index {
+    writeln!(
+      index_file,
+      "{}\t{}",
+      flat_name.to_string_lossy(),
+      chain.join("\t")
+    )?;
+  }
+  index_file.flush()?;
+
+  Ok(warnings)
+}
+
+/// The regular file among `files` that the link at `link` leads to, following links in between,
+/// or `None` if it leads anywhere else. Targets are checked to stay inside of `flat_path` as they
+/// are when unpacked.
+fn link_target(
+  flat_path: &Path,
+  link: &Path,
+  files: &HashSet<PathBuf>,
+) -> io::Result<Option<PathBuf>> {
+  let mut path = link.to_path_buf();
+  let mut visited = HashSet::new();
+  while fs::symlink_metadata(&path)?.is_symlink() {
+    if !visited.insert(path.clone()) {
+      return Ok(None);
+    }
+    let target = fs::read_link(&path)?;
+    let Some(target_path) = target
+      .to_str()
+      .and_then(|target| policy::symlink_path(flat_path, &path, target))
+    else {
+      return Ok(None);
+    };
+    if !files.contains(&target_path) {
+      return Ok(None);
+    }
+    path = target_path;
+  }
+  // Flat names are plain file names, which only some platforms allow any bytes in
+  let is_named = path.file_name().is_some_and(|name| name.to_str().is_some());
+
+  Ok((fs::symlink_metadata(&path)?.is_file() && is_named).then_some(path))
+}
+
+/// The innermost archive that the file at `path` was unpacked from, and its path in that archive.
+fn origin(path: &Path, unpacked: &[UnpackedArchive]) -> (PathBuf, String) {
+  let chain = nesting_chain(path, unpacked);
+  let archive = innermost_archive(path, unpacked)
+    .map(|archive| archive.path.clone())
+    .unwrap_or_default();
+  (archive, chain.last().cloned().unwrap_or_default())
+}
+
+/// Where each of `files`, given in path order, ends up once flattened into `flat_path` alongside
+/// the `kept` paths already there. Names that are taken, including the index's, are numbered as in
+/// `doc (1).txt`.
+pub(crate) fn flat_paths(
+  flat_path: &Path,
+  files: &[PathBuf],
+  kept: HashSet<PathBuf>,
+) -> Vec<PathBuf> {
+  let mut taken = kept;
+  taken.insert(flat_path.join(INDEX_FILE_NAME));
+  files
+    .iter()
+    .map(|file| {
+      let mut flat_file = flat_path.join(file.file_name().unwrap_or_default());
+      if taken.contains(&flat_file) {
+        let numbered_file = policy::numbered_paths(&flat_file, true)
+          .find(|path| !taken.contains(path))
+          .unwrap();
+        flat_file = numbered_file;
+      }
+      taken.insert(flat_file.clone());
+      flat_file
+    })
+    .collect()
+}
+
+/// Collect every file under `path` that isn't one of the `unpacked` archives, in path order.
+fn collect_files(
+  path: &Path,
+  unpacked: &[UnpackedArchive],
+  files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+  let mut entries = fs::read_dir(path)?
+    .map(|entry| entry.map(|entry| entry.path()))
+    .collect::<io::Result<Vec<_>>>()?;
+  entries.sort();
+  for entry in entries {
+    if fs::symlink_metadata(&entry)?.is_dir() {
+      collect_files(&entry, unpacked, files)?;
+    } else if !is_unpacked(&entry, unpacked) {
+      files.push(entry);
+    }
+  }
+
+  Ok(())
+}
+
+/// Where the file at `path` came from: the name of the outermost archive, then the path of each
+/// archive in the one around it, then the file's own path in the innermost archive.
+fn nesting_chain(path: &Path, unpacked: &[UnpackedArchive]) -> Vec<String> {
+  let Some(UnpackedArchive {
+    path: archive,
+    out_path,
+    ..
+  }) = innermost_archive(path, unpacked)
+  else {
+    return vec![display_name(path)];
+  };
+
+  let mut chain = nesting_chain(archive, unpacked);
+  // A decompressed single file is its own output
+  chain.push(match path.strip_prefix(out_path) {
+    Ok(relative_path) if relative_path.as_os_str().is_empty() => display_name(path),
+    Ok(relative_path) => relative_path
+      .components()
+      .map(|component| component.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/"),
+    Err(_) => display_name(path),
+  });
+  chain
+}
+
+/// The archive that the file at `path` was unpacked from, which is the one with the deepest output
+/// that holds it.
+fn innermost_archive<'u>(
+  path: &Path,
+  unpacked: &'u [UnpackedArchive],
+) -> Option<&'u UnpackedArchive> {
+  unpacked
+    .iter()
+    .filter(|archive| path.starts_with(&archive.out_path))
+    .max_by_key(|archive| archive.out_path.components().count())
+}
+
+/// Whether the file at `path` is one of the `unpacked` archives.
+fn is_unpacked(path: &Path, unpacked: &[UnpackedArchive]) -> bool {
+  unpacked.iter().any(|archive| archive.path == path)
+}
+
+fn display_name(path: &Path) -> String {
+  path
+    .file_name()
+    .unwrap_or_default()
+    .to_string_lossy()
+    .into_owned()
+}
@@ -0,0 +1,456 @@
+use std::{
+  ffi::OsStr,
+  fs::File,
+  io::{self, BufReader, Read},
+  path::Path,
+};
+
+/// Extensions that tell compressed tarballs apart from other compressed files, and tarballs
+/// without the `ustar` magic apart from other files. Multi-part extensions are listed before the
+/// single extensions they end with.
+const ARCHIVE_EXTENSIONS: [(&str, ArchiveFormat); 17] = [
+  ("tar.gz", ArchiveFormat::TarGz),
+  ("tar.xz", ArchiveFormat::TarXz),
+  ("tar.bz2", ArchiveFormat::TarBz2),
+  ("tar.zst", ArchiveFormat::TarZst),
+  ("zip", ArchiveFormat::Zip),
+  ("7z", ArchiveFormat::SevenZ),
+  ("rar", ArchiveFormat::Rar),
+  ("tar", ArchiveFormat::Tar),
+  ("gz", ArchiveFormat::Gzip),
+  ("tgz", ArchiveFormat::TarGz),
+  ("xz", ArchiveFormat::Xz),
+  ("txz", ArchiveFormat::TarXz),
+  ("bz2", ArchiveFormat::Bzip2),
+  ("tbz", ArchiveFormat::TarBz2),
+  ("tbz2", ArchiveFormat::TarBz2),
+  ("zst", ArchiveFormat::Zstd),
+  ("tzst", ArchiveFormat::TarZst),
+];
+
+/// Zip-based document and package formats that we never treat as archives, even though their
+/// contents sniff as zip.
+const CONTAINER_EXTENSIONS: [&str; 28] = [
+  "docx", "docm", "xlsx", "xlsm", "pptx", "pptm", "odt", "ods", "odp", "odg", "epub", "jar", "war",
+  "ear", "aar", "apk", "aab", "ipa", "xpi", "whl", "nupkg", "vsix", "appx", "msix", "xps", "kmz",
+  "3mf", "usdz",
+];
+
+/// The number of bytes read from the start of a file when sniffing its format. This needs to
+/// cover a whole tar header.
+const SNIFF_LEN: usize = 512;
+
+/// Offset of the magic field in a tar header.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Offset and length of the checksum field in a tar header.
+const TAR_CHECKSUM_OFFSET: usize = 148;
+const TAR_CHECKSUM_LEN: usize = 8;
+
+/// The archive and compression formats that RZip knows how to unpack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+  Zip,
+  SevenZ,
+  Rar,
+  Tar,
+  TarGz,
+  TarXz,
+  TarBz2,
+  TarZst,
+  Gzip,
+  Xz,
+  Bzip2,
+  Zstd,
+}
+
+impl ArchiveFormat {
+  /// Identify a format from the leading bytes of a file.
+  pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+    if bytes.starts_with(b"PK\x03\x04")
+      || bytes.starts_with(b"PK\x05\x06")
+      || bytes.starts_with(b"PK\x07\x08")
+    {
+      Some(Self::Zip)
+    } else if bytes.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C]) {
+      Some(Self::SevenZ)
+    } else if bytes.starts_with(b"Rar!\x1A\x07") {
+      Some(Self::Rar)
+    } else if bytes.starts_with(&[0x1F, 0x8B]) {
+      Some(Self::Gzip)
+    } else if bytes.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+      Some(Self::Xz)
+    } else if bytes.len() >= 4 && bytes.starts_with(b"BZh") && (b'1'..=b'9').contains(&bytes[3]) {
+      Some(Self::Bzip2)
+    } else if bytes.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+      Some(Self::Zstd)
+    } else if bytes.len() >= TAR_MAGIC_OFFSET + 5
+      && &bytes[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5] == b"ustar"
+    {
+      Some(Self::Tar)
+    } else {
+      None
+    }
+  }
+
+  /// Identify a format from a file name, recognizing multi-part extensions such as `.tar.gz`.
+  pub fn from_file_name(file_name: &str) -> Option<Self> {
+    let file_name = file_name.to_ascii_lowercase();
+    ARCHIVE_EXTENSIONS
+      .iter()
+      .find(|(ext, _)| has_extension(&file_name, ext))
+      .map(|(_, format)| *format)
+  }
+
+  /// The format of the outermost layer of the file, which is what its magic bytes identify.
+  pub fn outer_format(self) -> Self {
+    match self {
+      Self::TarGz => Self::Gzip,
+      Self::TarXz => Self::Xz,
+      Self::TarBz2 => Self::Bzip2,
+      Self::TarZst => Self::Zstd,
+      format => format,
+    }
+  }
+
+  /// The tarball variant of a compression format.
+  fn as_tarball(self) -> Self {
+    match self {
+      Self::Gzip => Self::TarGz,
+      Self::Xz => Self::TarXz,
+      Self::Bzip2 => Self::TarBz2,
+      Self::Zstd => Self::TarZst,
+      format => format,
+    }
+  }
+
+  /// Whether this format is a compressed stream wrapping a single file, rather than an archive
+  /// of entries.
+  pub fn is_compressed_stream(self) -> bool {
+    matches!(self, Self::Gzip | Self::Xz | Self::Bzip2 | Self::Zstd)
+  }
+
+  /// Detect the format of the file at `path` from its contents, using its name to tell compressed
+  /// tarballs and old tarballs apart. Compressed tarballs are recognized by their name or, failing
+  /// that, by the tar header at the start of their decompressed contents.
+  pub fn detect(path: &Path) -> Result<Option<Self>, io::Error> {
+    let file_name = path.file_name().and_then(|file_name| file_name.to_str());
+    Self::detect_with(file_name, read_header(path)?, |format| {
+      read_decompressed_header(File::open(path)?, format)
+    })
+  }
+
+  /// Detect the format of a file named `file_name` whose contents are held in memory, as
+  /// [ArchiveFormat::detect] does for a file on disk.
+  pub fn detect_bytes(file_name: &str, bytes: &[u8]) -> Option<Self> {
+    let header = &bytes[..bytes.len().min(SNIFF_LEN)];
+    Self::detect_with(Some(file_name), header.to_vec(), |format| {
+      read_decompressed_header(bytes, format)
+    })
+    .unwrap_or_default()
+  }
+
+  /// Detect a format from a file's name and `header`, decompressing the start of the file with
+  /// `decompressed_header` to look for a tarball inside a compressed file.
+  fn detect_with(
+    file_name: Option<&str>,
+    header: Vec<u8>,
+    decompressed_header: impl FnOnce(Self) -> Result<Vec<u8>, io::Error>,
+  ) -> Result<Option<Self>, io::Error> {
+    let file_name = file_name.map(|file_name| file_name.to_ascii_lowercase());
+
+    // Zip-based document formats are files in their own right
+    if let Some(file_name) = &file_name {
+      if CONTAINER_EXTENSIONS
+        .iter()
+        .any(|ext| has_extension(file_name, ext))
+      {
+        return Ok(None);
+      }
+    }
+
+    let from_name = file_name.as_deref().and_then(Self::from_file_name);
+    let format = match (Self::from_magic(&header), from_name) {
+      (Some(from_magic), Some(from_name)) if from_name.outer_format() == from_magic => {
+        Some(from_name)
+      }
+      (Some(from_magic), _) => Some(from_magic),
+      // Every other format starts with its signature, so a name alone only identifies a tarball
+      // from before the `ustar` magic, whose header checksum vouches for it instead
+      (None, Some(Self::Tar)) if is_tar_header(&header) => Some(Self::Tar),
+      (None, _) => None,
+    };
+
+    // A compressed file that isn't named as a tarball might still contain one
+    if let Some(format) = format.filter(|format| format.is_compressed_stream()) {
+      let decompressed_header = decompressed_header(format)?;
+      if Self::from_magic(&decompressed_header) == Some(Self::Tar) {
+        return Ok(Some(format.as_tarball()));
+      }
+    }
+
+    Ok(format)
+  }
+}
+
+/// Get the name of an archive without its archive extension, so that `foo.tar.gz` yields `foo`.
+/// Falls back to [Path::file_stem] for names without a recognized multi-part extension.
+pub fn archive_stem(path: &Path) -> Option<&OsStr> {
+  let file_name = path.file_name()?;
+  if let Some(name) = file_name.to_str() {
+    let lowercase = name.to_ascii_lowercase();
+    let compound_ext = ARCHIVE_EXTENSIONS
+      .iter()
+      .map(|(ext, _)| *ext)
+      .filter(|ext| ext.contains('.'))
+      .find(|ext| has_extension(&lowercase, ext));
+    if let Some(ext) = compound_ext {
+      return Some(OsStr::new(&name[..name.len() - ext.len() - 1]));
+    }
+  }
+
+  path.file_stem()
+}
+
+/// Check whether a lowercase `file_name` ends with `.ext` and has something before it.
+fn has_extension(file_name: &str, ext: &str) -> bool {
+  file_name.len() > ext.len() + 1
+    && file_name.ends_with(ext)
+    && file_name.as_bytes()[file_name.len() - ext.len() - 1] == b'.'
+}
+
+/// Check whether `header` starts with a tar header whose checksum matches, which is all that
+/// identifies a tarball without the `ustar` magic. The checksum is the sum of the header's bytes,
+/// counting its own field as spaces, written in octal.
+fn is_tar_header(header: &[u8]) -> bool {
+  let Some(header) = header.get(..SNIFF_LEN) else {
+    return false;
+  };
+  let checksum_field = TAR_CHECKSUM_OFFSET..TAR_CHECKSUM_OFFSET + TAR_CHECKSUM_LEN;
+  let digits = header[checksum_field.clone()]
+    .iter()
+    .skip_while(|b| **b == b' ')
+    .take_while(|b| (b'0'..=b'7').contains(*b))
+    .map(|b| u32::from(b - b'0'))
+    .collect::<Vec<_>>();
+  if digits.is_empty() {
+    return false;
+  }
+  let checksum = digits
+    .iter()
+    .fold(0, |checksum, digit| checksum * 8 + digit);
+  let sum: u32 = header
+    .iter()
+    .enumerate()
+    .map(|(i, b)| match checksum_field.contains(&i) {
+      true => u32::from(b' '),
+      false => u32::from(*b),
+    })
+    .sum();
+  checksum == sum
+}
+
+/// Read up to [SNIFF_LEN] bytes from the start of the file at `path`.
+fn read_header(path: &Path) -> Result<Vec<u8>, io::Error> {
+  let file = File::open(path)?;
+  let mut header = Vec::with_capacity(SNIFF_LEN);
+  file.take(SNIFF_LEN as u64).read_to_end(&mut header)?;
+
+  Ok(header)
+}
+
+/// Decompress up to [SNIFF_LEN] bytes from the start of the compressed `file`. Only the outermost
+/// layer of compression is removed, so `foo.tar.gz.gz` yields the start of `foo.tar.gz`.
+fn read_decompressed_header(file: impl Read, format: ArchiveFormat) -> Result<Vec<u8>, io::Error> {
+  let mut header = Vec::with_capacity(SNIFF_LEN);
+
+  // A stream we can't decompress simply has no recognizable header, so errors are ignored
+  let _ = match format {
+    ArchiveFormat::Gzip => flate2::read::MultiGzDecoder::new(file)
+      .take(SNIFF_LEN as u64)
+      .read_to_end(&mut header),
+    ArchiveFormat::Bzip2 => bzip2::read::MultiBzDecoder::new(file)
+      .take(SNIFF_LEN as u64)
+      .read_to_end(&mut header),
+    ArchiveFormat::Zstd => match ruzstd::decoding::StreamingDecoder::new(file) {
+      Ok(decoder) => decoder.take(SNIFF_LEN as u64).read_to_end(&mut header),
+      Err(_) => Ok(0),
+    },
+    ArchiveFormat::Xz => lzma_rust2::XzReader::new(BufReader::new(file), true)
+      .take(SNIFF_LEN as u64)
+      .read_to_end(&mut header),
+    _ => Ok(0),
+  };
+
+  Ok(header)
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+  };
+
+  use tempfile::TempDir;
+
+  use super::*;
+
+  #[test]
+  fn test_from_magic() {
+    assert_eq!(
+      ArchiveFormat::from_magic(b"PK\x03\x04rest"),
+      Some(ArchiveFormat::Zip)
+    );
+    assert_eq!(
+      ArchiveFormat::from_magic(b"Rar!\x1A\x07\x00"),
+      Some(ArchiveFormat::Rar)
+    );
+    assert_eq!(
+      ArchiveFormat::from_magic(&[0x1F, 0x8B, 0x08]),
+      Some(ArchiveFormat::Gzip)
+    );
+    assert_eq!(
+      ArchiveFormat::from_magic(b"BZh91AY"),
+      Some(ArchiveFormat::Bzip2)
+    );
+    assert_eq!(
+      ArchiveFormat::from_magic(&[0x28, 0xB5, 0x2F, 0xFD, 0x64]),
+      Some(ArchiveFormat::Zstd)
+    );
+    assert_eq!(ArchiveFormat::from_magic(b"BZh plain text"), None);
+    assert_eq!(ArchiveFormat::from_magic(b"Meaningless data"), None);
+
+    let mut tar_header = vec![0u8; 512];
+    tar_header[257..262].copy_from_slice(b"ustar");
+    assert_eq!(
+      ArchiveFormat::from_magic(&tar_header),
+      Some(ArchiveFormat::Tar)
+    );
+  }
+
+  #[test]
+  fn test_detect_prefers_contents() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // Zip contents behind a misleading extension
+    let misnamed = temp_dir.path().join("download.txt");
+    let mut file = File::create(&misnamed).unwrap();
+    file.write_all(b"PK\x03\x04 zip data").unwrap();
+    assert_eq!(
+      ArchiveFormat::detect(&misnamed).unwrap(),
+      Some(ArchiveFormat::Zip)
+    );
+
+    // A tarball without the ustar magic is recognized by its name and header checksum
+    let mut header = [0; 512];
+    header[..7].copy_from_slice(b"old.txt");
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+    header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+    let old_tar = temp_dir.path().join("old.tar");
+    fs::write(&old_tar, header).unwrap();
+    assert_eq!(
+      ArchiveFormat::detect(&old_tar).unwrap(),
+      Some(ArchiveFormat::Tar)
+    );
+    let old_txt = temp_dir.path().join("old.txt");
+    fs::write(&old_txt, header).unwrap();
+    assert_eq!(ArchiveFormat::detect(&old_txt).unwrap(), None);
+
+    // Contents that don't match the name aren't trusted to be what the name says
+    for name in [
+      "notes.tar",
+      "notes.zip",
+      "notes.gz",
+      "notes.tar.xz",
+      "notes.tzst",
+    ] {
+      let plain_text = temp_dir.path().join(name);
+      fs::write(&plain_text, "Plain notes\n").unwrap();
+      assert_eq!(ArchiveFormat::detect(&plain_text).unwrap(), None, "{name}");
+    }
+
+    // Document and package formats are never archives
+    for name in ["report.docx", "app.ipa", "package.nupkg", "addon.xpi"] {
+      let document = temp_dir.path().join(name);
+      let mut file = File::create(&document).unwrap();
+      file.write_all(b"PK\x03\x04 zip data").unwrap();
+      assert_eq!(ArchiveFormat::detect(&document).unwrap(), None, "{name}");
+    }
+  }
+
+  #[test]
+  fn test_detect_compound_extension() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // Gzip contents named as a tarball
+    let tar_gz = temp_dir.path().join("logs.tar.gz");
+    let mut file = File::create(&tar_gz).unwrap();
+    file.write_all(&[0x1F, 0x8B, 0x08]).unwrap();
+    assert_eq!(
+      ArchiveFormat::detect(&tar_gz).unwrap(),
+      Some(ArchiveFormat::TarGz)
+    );
+
+    // Zip contents win over a tarball name
+    let tgz = temp_dir.path().join("logs.tgz");
+    let mut file = File::create(&tgz).unwrap();
+    file.write_all(b"PK\x03\x04 zip data").unwrap();
+    assert_eq!(
+      ArchiveFormat::detect(&tgz).unwrap(),
+      Some(ArchiveFormat::Zip)
+    );
+
+    // Bzip2 contents named with the short tarball extension
+    let tbz2 = temp_dir.path().join("vendor.tbz2");
+    let mut file = File::create(&tbz2).unwrap();
+    file.write_all(b"BZh91AY&SY").unwrap();
+    assert_eq!(
+      ArchiveFormat::detect(&tbz2).unwrap(),
+      Some(ArchiveFormat::TarBz2)
+    );
+  }
+
+  #[test]
+  fn test_detect_compressed_stream() {
+    let data_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/indiv");
+    let temp_dir = TempDir::new().unwrap();
+
+    // A compressed plain file
+    assert_eq!(
+      ArchiveFormat::detect(&data_root.join("doc_gz.txt.gz")).unwrap(),
+      Some(ArchiveFormat::Gzip)
+    );
+
+    // A compressed tarball without a tarball name
+    let tar_xz = temp_dir.path().join("release.xz");
+    fs::copy(data_root.join("packed_tar_xz.tar.xz"), &tar_xz).unwrap();
+    assert_eq!(
+      ArchiveFormat::detect(&tar_xz).unwrap(),
+      Some(ArchiveFormat::TarXz)
+    );
+  }
+
+  #[test]
+  fn test_archive_stem() {
+    assert_eq!(
+      archive_stem(Path::new("dir/packed_tar_gz.tar.gz")),
+      Some(OsStr::new("packed_tar_gz"))
+    );
+    assert_eq!(
+      archive_stem(Path::new("Release.TAR.XZ")),
+      Some(OsStr::new("Release"))
+    );
+    assert_eq!(
+      archive_stem(Path::new("packed_zip.zip")),
+      Some(OsStr::new("packed_zip"))
+    );
+    assert_eq!(
+      archive_stem(Path::new("build.tar.zst")),
+      Some(OsStr::new("build"))
+    );
+    assert_eq!(archive_stem(Path::new(".tar.gz")), Some(OsStr::new(".tar")));
+  }
+}
@@ -0,0 +1,126 @@
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use crate::{
+  error::RZipError,
+  preview::{ArchivePreview, PreviewKind},
+};
+
+/// Identifies the filesystem a path is on.
+#[cfg(unix)]
+type FilesystemId = u64;
+#[cfg(not(unix))]
+type FilesystemId = PathBuf;
+
+/// What a run would unpack to one filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+  /// The first output that would be unpacked to the filesystem.
+  pub path: PathBuf,
+  /// How many bytes would be unpacked to the filesystem.
+  pub required_bytes: u64,
+  /// How many bytes are free on the filesystem.
+  pub available_bytes: u64,
+}
+
+/// What extracting a set of archives would do to the disk, estimated from their previews.
+#[derive(Debug, Default)]
+pub struct ExtractionImpact {
+  /// Every byte that would be unpacked, including nested archives and what they unpack in turn.
+  pub unpacked_bytes: u64,
+  /// The files already there that would be replaced.
+  pub overwritten: Vec<PathBuf>,
+  /// The space needed and free on each filesystem that would be unpacked to.
+  pub disks: Vec<DiskUsage>,
+}
+
+impl ExtractionImpact {
+  /// Total what the `previews` would unpack to each filesystem. Replaced files and deleted
+  /// archives aren't counted as freeing space, as both are only removed once the new files are
+  /// there.
+  pub fn estimate(previews: &[ArchivePreview]) -> io::Result<Self> {
+    let mut impact = Self::default();
+    let mut filesystems = vec![];
+    for preview in previews {
+      impact.add_preview(preview, &mut filesystems)?;
+    }
+    impact.disks = filesystems.into_iter().map(|(_, disk)| disk).collect();
+
+    Ok(impact)
+  }
+
+  /// Check that every filesystem has room for what would be unpacked to it.
+  pub fn check_free_space(&self) -> Result<(), RZipError> {
+    match self
+      .disks
+      .iter()
+      .find(|disk| disk.required_bytes > disk.available_bytes)
+    {
+      Some(disk) => Err(RZipError::InsufficientSpace(
+        disk.path.clone(),
+        disk.required_bytes,
+        disk.available_bytes,
+      )),
+      None => Ok(()),
+    }
+  }
+
+  fn add_preview(
+    &mut self,
+    preview: &ArchivePreview,
+    filesystems: &mut Vec<(FilesystemId, DiskUsage)>,
+  ) -> io::Result<()> {
+    let bytes = preview
+      .entries
+      .iter()
+      .map(|entry| match &entry.kind {
+        PreviewKind::Directory | PreviewKind::Index => 0,
+        PreviewKind::File(size)
+        | PreviewKind::Unlisted(size, _)
+        | PreviewKind::Archive(size, _) => *size,
+      })
+      .sum::<u64>();
+    self.unpacked_bytes += bytes;
+    self.overwritten.extend(preview.overwritten.iter().cloned());
+
+    // Outputs that aren't there yet go on the filesystem of the closest directory that is
+    if let Some(existing_path) = preview.out_path.ancestors().find(|path| path.exists()) {
+      let id = filesystem_id(existing_path)?;
+      match filesystems.iter_mut().find(|(other_id, _)| *other_id == id) {
+        Some((_, disk)) => disk.required_bytes += bytes,
+        None => filesystems.push((
+          id,
+          DiskUsage {
+            path: preview.out_path.clone(),
+            required_bytes: bytes,
+            available_bytes: fs4::available_space(existing_path)?,
+          },
+        )),
+      }
+    }
+
+    for entry in &preview.entries {
+      if let PreviewKind::Archive(_, nested) = &entry.kind {
+        self.add_preview(nested, filesystems)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(unix)]
+fn filesystem_id(path: &Path) -> io::Result<FilesystemId> {
+  use std::os::unix::fs::MetadataExt;
+
+  Ok(fs::metadata(path)?.dev())
+}
+
+/// Paths are on the volume their prefix, such as `C:`, names.
+#[cfg(not(unix))]
+fn filesystem_id(path: &Path) -> io::Result<FilesystemId> {
+  let path = fs::canonicalize(path)?;
+  Ok(path.components().next().map(PathBuf::from).unwrap_or(path))
+}
@@ -1,60 +1,413 @@
 mod error;
+pub mod flatten;
+pub mod format;
+pub mod impact;
+pub mod limits;
+pub mod memory;
+pub mod naming;
+pub mod plan;
+pub mod policy;
+pub mod preview;
 pub mod unpack;
+pub mod volume;
 
 use std::{
-  fs,
+  collections::{BTreeMap, HashMap, HashSet},
+  fs::{self, File},
+  io,
   path::{Path, PathBuf},
+  sync::mpsc,
+  time::SystemTime,
 };
 
-pub use error::RZipError;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-/// The list of extensinsions used to check if a file is an archive.
-const ARCHIVE_EXTENSIONS: [&str; 8] = ["zip", "txz", "xz", "tar", "tgz", "gz", "7z", "rar"];
+pub use error::{LimitViolation, RZipError, RZipProcessingError, RZipWarning};
+pub use format::ArchiveFormat;
+pub use impact::{DiskUsage, ExtractionImpact};
+pub use limits::{ExtractLimits, RunUsage};
+pub use memory::InMemoryArchives;
+pub use naming::NameTemplate;
+pub use plan::{ExtractionPlan, PlannedArchive};
+pub use policy::{
+  ConflictPolicy, OutputLayout, OwnershipPolicy, SpecialEntryKind, SpecialEntryPolicy,
+  TimestampPolicy, UnsafePathPolicy,
+};
 
+/// How to extract archives. Passwords and the state of the run are left out when it's serialized.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RZipExtractConfig {
   pub target_path: PathBuf,
   pub out_dir: Option<PathBuf>,
   pub delete_after_extracting: bool,
+  /// Candidate passwords, tried in order on encrypted archives.
+  #[serde(skip)]
+  pub passwords: Vec<String>,
+  /// Limits on what each archive, and the run as a whole, may unpack.
+  pub limits: ExtractLimits,
+  /// Totals of what this run has unpacked so far.
+  #[serde(skip)]
+  pub usage: RunUsage,
+  /// How many levels of nested archives to unpack below the archive being extracted. Archives
+  /// nested any deeper are left packed.
+  pub max_depth: Option<usize>,
+  /// What to do with entries that would unpack outside of their archive's output directory.
+  pub unsafe_paths: UnsafePathPolicy,
+  /// What to do with symlinks, hard links and special files such as FIFOs and device nodes.
+  pub special_entries: SpecialEntryPolicy,
+  /// Who unpacked files and directories belong to.
+  pub ownership: OwnershipPolicy,
+  /// Permission bits to clear from every unpacked file and directory, on top of the setuid, setgid
+  /// and sticky bits that are always cleared.
+  pub umask: u32,
+  /// Which timestamps unpacked files and directories get.
+  pub timestamps: TimestampPolicy,
+  /// Restore the extended attributes that tar archives record. Other formats don't record any.
+  /// Only the `user` namespace is restored unless `all_xattrs` is set too.
+  pub preserve_xattrs: bool,
+  /// Restore extended attributes outside of the `user` namespace as well, such as
+  /// `security.capability`, which can grant the unpacked files privileges.
+  pub all_xattrs: bool,
+  /// What to do when an archive's output, or a file in it, is already there.
+  pub on_conflict: ConflictPolicy,
+  /// Move the contents of an archive's output up a level when it's a single directory, so that
+  /// `project.zip` holding `project/` unpacks to `project/` rather than `project/project/`.
+  pub collapse_single_root: bool,
+  /// Whether to mirror nested archives, or flatten every file into the top output directory.
+  pub layout: OutputLayout,
+  /// The name each archive's output is given, for top-level and nested archives alike.
+  pub name_template: NameTemplate,
+  /// How many archives to extract at once, top-level and nested alike. 0 extracts one per core.
+  #[serde(skip)]
+  pub jobs: usize,
+  /// Archives nested in another of up to this many bytes are unpacked from memory, without ever
+  /// being written out. Bigger ones, and every nested archive when `None`, are unpacked from disk.
+  pub max_in_memory_bytes: Option<u64>,
+  /// The nested archives this run holds in memory.
+  #[serde(skip)]
+  pub in_memory: InMemoryArchives,
 }
 
+/// The SHA-256 hash of an archive's contents.
+type ContentHash = [u8; 32];
+
+/// Extract an archive, then any archives found in its output. Nested archives that are too deep,
+/// or that are copies of an archive they're nested in, are left packed and reported as warnings.
 pub fn recursive_file_extract(
-  path: &PathBuf,
-  out_path: &PathBuf,
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipError> {
+  thread_pool(config)?.install(|| extract_recursive(path, out_path, config))
+}
+
+/// Extract each of `archives` to the matching one of `out_paths` as [recursive_file_extract]
+/// does, [config.jobs](RZipExtractConfig::jobs) at a time. Each archive's result is passed to
+/// `report` in the order the archives are given, whichever finishes first.
+pub fn extract_archives(
+  archives: &[PathBuf],
+  out_paths: &[PathBuf],
   config: &RZipExtractConfig,
+  mut report: impl FnMut(&Path, Result<Vec<RZipWarning>, RZipError>),
 ) -> Result<(), RZipError> {
-  // Unpack the file
-  match unpack::unpack_file(path, out_path) {
-    Ok(_) => (),
+  let pool = thread_pool(config)?;
+  if !can_extract_concurrently(archives, out_paths, config) {
+    for (path, out_path) in archives.iter().zip(out_paths) {
+      report(
+        path,
+        pool.install(|| extract_recursive(path, out_path, config)),
+      );
+    }
+    return Ok(());
+  }
+
+  let (sender, receiver) = mpsc::channel();
+  pool.in_place_scope(|scope| {
+    for (index, (path, out_path)) in archives.iter().zip(out_paths).enumerate() {
+      let sender = sender.clone();
+      scope.spawn(move |_| {
+        // The receiver only goes away once every archive has reported
+        let _ = sender.send((index, extract_recursive(path, out_path, config)));
+      });
+    }
+    drop(sender);
+
+    // Results that come in early wait for the ones before them
+    let mut finished = BTreeMap::new();
+    let mut next = 0;
+    for (index, result) in receiver {
+      finished.insert(index, result);
+      while let Some(result) = finished.remove(&next) {
+        report(&archives[next], result);
+        next += 1;
+      }
+    }
+  });
+
+  Ok(())
+}
+
+/// The pool that archives are extracted on, with [config.jobs](RZipExtractConfig::jobs) threads.
+fn thread_pool(config: &RZipExtractConfig) -> Result<ThreadPool, RZipError> {
+  ThreadPoolBuilder::new()
+    .num_threads(config.jobs)
+    .build()
+    .map_err(|e| RZipError::RuntimeError(format!("Unable to start extraction threads: {e}")))
+}
+
+/// Whether `archives` can be extracted at the same time. They can't when one is inside another's
+/// output, where unpacking the other could touch it, or when they share a limit on the whole run,
+/// which would otherwise stop whichever archive happened to cross it.
+fn can_extract_concurrently(
+  archives: &[PathBuf],
+  out_paths: &[PathBuf],
+  config: &RZipExtractConfig,
+) -> bool {
+  let out_paths: HashSet<&Path> = out_paths.iter().map(PathBuf::as_path).collect();
+  config.limits.max_run_bytes.is_none()
+    && !archives
+      .iter()
+      .any(|path| path.ancestors().skip(1).any(|dir| out_paths.contains(dir)))
+}
+
+/// Extract an archive and everything nested in it, on the current thread pool.
+fn extract_recursive(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipError> {
+  let mut ancestors = vec![(content_hash(path)?, path.to_path_buf())];
+  let mut unpacked = vec![];
+  let mut warnings = vec![];
+  extract_nested(
+    path,
+    out_path,
+    config,
+    &mut ancestors,
+    &mut unpacked,
+    &mut warnings,
+  )?;
+
+  // The archive may have been unpacked somewhere other than `out_path` to avoid a conflict
+  if let (OutputLayout::Flat, Some(flat_archive)) = (config.layout, unpacked.first()) {
+    warnings.extend(flatten::flatten_output(&flat_archive.out_path, &unpacked)?);
+  }
+
+  Ok(warnings)
+}
+
+/// Recursively extract an archive. `ancestors` holds the hash and path of each archive on the way
+/// down to this one, including itself. Each archive unpacked is added to `unpacked`.
+fn extract_nested(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+  ancestors: &mut Vec<(ContentHash, PathBuf)>,
+  unpacked: &mut Vec<flatten::UnpackedArchive>,
+  warnings: &mut Vec<RZipWarning>,
+) -> Result<(), RZipError> {
+  let modified = || config.in_memory.modified(path);
+  let Some(out_path) = &resolve_output_conflict(out_path, modified, config)? else {
+    warnings.push(RZipWarning::OutputExists(
+      path.to_path_buf(),
+      out_path.to_path_buf(),
+    ));
+    return Ok(());
+  };
+
+  // Unpack the file. An archive held in memory is let go of once it's unpacked, as it was never
+  // written out.
+  let mut placed = vec![];
+  match unpack::unpack_file_placing(path, out_path, config, &mut placed) {
+    Ok(unpack_warnings) => warnings.extend(unpack_warnings),
     Err(e) => return Err(e),
   }
+  let in_memory = config.in_memory.remove(path).is_some();
+  unpacked.push(flatten::UnpackedArchive {
+    path: path.to_path_buf(),
+    out_path: out_path.clone(),
+    placed,
+  });
 
-  // Check to see if there are any other zips after extraction and re-call self
-  // on each if there are.
-  let residual_archives = get_archives_in_dir(out_path)?;
-  for res_path in residual_archives {
-    let res_out_path = get_out_path_for_archive(&res_path, config)?;
-    recursive_file_extract(&res_path, &res_out_path, config)?;
+  // Nested archives still held in memory once the residual ones are extracted were left packed,
+  // so they're written out
+  let result = extract_all_residual(out_path, config, ancestors, unpacked, warnings);
+  config.in_memory.write_out_under(out_path)?;
+  result?;
+
+  // Delete the file if the configuration calls for it, along with any other volumes
+  if config.delete_after_extracting && !in_memory {
+    for archive_file in volume::archive_files(path)? {
+      fs::remove_file(archive_file)?;
+    }
   }
 
-  // Delete the file if the configuration calls for it
-  if config.delete_after_extracting {
-    fs::remove_file(path)?;
+  Ok(())
+}
+
+/// Extract every archive found in `out_path`, on disk or held in memory, as [extract_residual]
+/// does.
+fn extract_all_residual(
+  out_path: &Path,
+  config: &RZipExtractConfig,
+  ancestors: &mut Vec<(ContentHash, PathBuf)>,
+  unpacked: &mut Vec<flatten::UnpackedArchive>,
+  warnings: &mut Vec<RZipWarning>,
+) -> Result<(), RZipError> {
+  // Check to see if there are any other zips after extraction and re-call self
+  // on each if there are. A decompressed single file might be an archive itself.
+  let residual_archives = if out_path.is_file() {
+    if is_archive_filetype(out_path) {
+      vec![out_path.to_path_buf()]
+    } else {
+      vec![]
+    }
+  } else {
+    config.in_memory.write_out_volumes(out_path)?;
+    let mut residual_archives = get_archives_in_dir(&out_path.to_path_buf())?;
+    residual_archives.extend(config.in_memory.paths_under(out_path));
+    residual_archives.sort();
+    residual_archives
+  };
+  let res_out_paths = get_out_paths_for_archives(&residual_archives, config)?;
+  if can_extract_concurrently(&residual_archives, &res_out_paths, config) {
+    let ancestors = &*ancestors;
+    let results: Vec<_> = residual_archives
+      .par_iter()
+      .zip(&res_out_paths)
+      .map(|(res_path, res_out_path)| {
+        let (mut ancestors, mut unpacked, mut warnings) = (ancestors.clone(), vec![], vec![]);
+        let result = extract_residual(
+          res_path,
+          res_out_path,
+          config,
+          &mut ancestors,
+          &mut unpacked,
+          &mut warnings,
+        );
+        (result, unpacked, warnings)
+      })
+      .collect();
+
+    // Results are gathered in order, so what's reported doesn't depend on which finished first
+    for (result, res_unpacked, res_warnings) in results {
+      unpacked.extend(res_unpacked);
+      warnings.extend(res_warnings);
+      result?;
+    }
+  } else {
+    for (res_path, res_out_path) in residual_archives.iter().zip(&res_out_paths) {
+      extract_residual(
+        res_path,
+        res_out_path,
+        config,
+        ancestors,
+        unpacked,
+        warnings,
+      )?;
+    }
   }
 
   Ok(())
 }
 
+/// Extract an archive found in the output of the last of `ancestors`. Archives past the maximum
+/// depth, and archives that contain themselves, which would otherwise be unpacked over and over,
+/// are left packed, as are files that none of the backends can unpack.
+fn extract_residual(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+  ancestors: &mut Vec<(ContentHash, PathBuf)>,
+  unpacked: &mut Vec<flatten::UnpackedArchive>,
+  warnings: &mut Vec<RZipWarning>,
+) -> Result<(), RZipError> {
+  if let Some(max_depth) = config.max_depth {
+    if ancestors.len() > max_depth {
+      warnings.push(RZipWarning::MaxDepth(path.to_path_buf(), max_depth));
+      return Ok(());
+    }
+  }
+  let hash = config.in_memory.content_hash(path)?;
+  if let Some((_, ancestor)) = ancestors
+    .iter()
+    .find(|(ancestor_hash, _)| *ancestor_hash == hash)
+  {
+    warnings.push(RZipWarning::ArchiveCycle(
+      path.to_path_buf(),
+      ancestor.clone(),
+    ));
+    return Ok(());
+  }
+
+  ancestors.push((hash, path.to_path_buf()));
+  let result = extract_nested(path, out_path, config, ancestors, unpacked, warnings);
+  ancestors.pop();
+
+  // A file that only looks like an archive, such as a text file named `notes.gz`, is kept as it is
+  // rather than failing the archive it's in
+  match result {
+    Err(RZipError::ProcessingErrors(_)) => {
+      config.in_memory.write_out(path)?;
+      warnings.push(RZipWarning::Undecodable(path.to_path_buf()));
+      Ok(())
+    }
+    result => result,
+  }
+}
+
+/// The path to unpack an archive to when its output is already there, or `None` if the archive is
+/// left packed. `modified` gives the archive's modification time, which merging compares.
+fn resolve_output_conflict(
+  out_path: &Path,
+  modified: impl FnOnce() -> io::Result<SystemTime>,
+  config: &RZipExtractConfig,
+) -> Result<Option<PathBuf>, RZipError> {
+  let Ok(existing) = fs::symlink_metadata(out_path) else {
+    return Ok(Some(out_path.to_path_buf()));
+  };
+
+  Ok(match config.on_conflict {
+    ConflictPolicy::Overwrite => Some(out_path.to_path_buf()),
+    ConflictPolicy::Skip => None,
+    ConflictPolicy::Rename => Some(policy::available_path(out_path, !existing.is_dir())),
+    // Directories are merged file by file as they're unpacked
+    ConflictPolicy::Merge if existing.is_dir() => Some(out_path.to_path_buf()),
+    ConflictPolicy::Merge => (modified()? > existing.modified()?).then(|| out_path.to_path_buf()),
+  })
+}
+
+/// Hash the contents of an archive, including every volume of a multi-volume archive.
+fn content_hash(path: &Path) -> Result<ContentHash, RZipError> {
+  let mut hasher = Sha256::new();
+  for archive_file in volume::archive_files(path)? {
+    io::copy(&mut File::open(archive_file)?, &mut hasher)?;
+  }
+
+  Ok(hasher.finalize().into())
+}
+
 pub fn get_out_path_for_archive(
-  archive_path: &PathBuf,
+  archive_path: &Path,
   config: &RZipExtractConfig,
 ) -> Result<PathBuf, RZipError> {
-  let output_path = archive_path.file_stem().ok_or_else(|| {
-    RZipError::RuntimeError(format!(
-      "Unable to determine file stem for {}",
-      archive_path.display()
-    ))
-  })?;
+  let output_name = config.name_template.render_with(
+    archive_path,
+    || config.in_memory.modified(archive_path),
+    || config.in_memory.content_hash(archive_path),
+  )?;
+  out_path_with_name(archive_path, &output_name, config)
+}
 
+/// The path of the output named `output_path` of the archive at `archive_path`.
+fn out_path_with_name(
+  archive_path: &Path,
+  output_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<PathBuf, RZipError> {
   if let Some(out_dir) = &config.out_dir {
     if config.target_path == *archive_path {
       return Ok(out_dir.join(output_path));
@@ -67,6 +420,43 @@ pub fn get_out_path_for_archive(
   }
 }
 
+/// Determine the output path of each archive in a set that is extracted together, such as the
+/// archives found in a directory. Archives that would share an output, like `a.zip` and `a.7z`
+/// both unpacking to `a`, each unpack to a directory named after their whole file name instead
+/// (`a.zip.d` and `a.7z.d`), whatever order they come in.
+pub fn get_out_paths_for_archives(
+  archive_paths: &[PathBuf],
+  config: &RZipExtractConfig,
+) -> Result<Vec<PathBuf>, RZipError> {
+  let out_paths = archive_paths
+    .iter()
+    .map(|archive_path| get_out_path_for_archive(archive_path, config))
+    .collect::<Result<Vec<_>, _>>()?;
+  Ok(separate_out_paths(archive_paths, out_paths))
+}
+
+/// Give each of a set of archives that share one of `out_paths` an output of its own, as
+/// [get_out_paths_for_archives] does.
+fn separate_out_paths(archive_paths: &[PathBuf], out_paths: Vec<PathBuf>) -> Vec<PathBuf> {
+  let mut out_path_counts = HashMap::<&Path, usize>::new();
+  for out_path in &out_paths {
+    *out_path_counts.entry(out_path).or_default() += 1;
+  }
+
+  archive_paths
+    .iter()
+    .zip(&out_paths)
+    .map(|(archive_path, out_path)| {
+      if out_path_counts[out_path.as_path()] == 1 {
+        return out_path.clone();
+      }
+      let mut output_name = archive_path.file_name().unwrap_or_default().to_os_string();
+      output_name.push(".d");
+      out_path.with_file_name(output_name)
+    })
+    .collect()
+}
+
 fn get_relative_path(
   archive_path: &Path,
   config: &RZipExtractConfig,
@@ -83,11 +473,7 @@ fn get_relative_path(
     .map(|res| res.to_path_buf())
 }
 
-fn construct_output_path(
-  relative_path: &Path,
-  out_dir: &Path,
-  output_path: &std::ffi::OsStr,
-) -> PathBuf {
+fn construct_output_path(relative_path: &Path, out_dir: &Path, output_path: &Path) -> PathBuf {
   let rel_dir = relative_path.parent().unwrap(); // handle errors appropriately
   out_dir.join(rel_dir).join(output_path)
 }
@@ -98,24 +484,20 @@ pub fn is_archive_filetype(path: &Path) -> bool {
     return false;
   }
 
-  // If we have an extension
-  if let Some(ext) = path.extension() {
-    // And it has a str representation present in the extensions array
-    if let Some(ext_str) = ext.to_str() {
-      return ARCHIVE_EXTENSIONS.contains(&ext_str);
-    }
-  }
-
-  false
+  // Sniff the contents. Files we can't read aren't archives we can unpack.
+  matches!(ArchiveFormat::detect(path), Ok(Some(_)))
 }
 
 pub fn get_archives_in_dir(path: &PathBuf) -> Result<Vec<PathBuf>, RZipError> {
   let mut output_entries = Vec::new();
 
-  let read_entries = fs::read_dir(path)?;
-  for entry in read_entries {
+  // Entries are visited in path order, so archives are always found in the same order
+  let mut read_entries = fs::read_dir(path)?
+    .map(|entry| entry.map(|entry| entry.path()))
+    .collect::<io::Result<Vec<_>>>()?;
+  read_entries.sort();
+  for path in read_entries {
     // Handle directory vs file
-    let path = entry?.path();
     if path.is_dir() {
       // For directories, we recurse
       let subpath_entries = get_archives_in_dir(&path)?;
@@ -126,5 +508,55 @@ pub fn get_archives_in_dir(path: &PathBuf) -> Result<Vec<PathBuf>, RZipError> {
     }
   }
 
+  // Multi-volume archives are extracted once, through their first volume
+  volume::retain_first_volumes(&mut output_entries);
+
   Ok(output_entries)
 }
+
+#[cfg(test)]
+mod test {
+  use tempfile::TempDir;
+
+  use super::*;
+
+  #[test]
+  fn test_archive_cycle_is_left_packed() {
+    let data_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = temp_dir.path().join("nested.zip");
+    fs::copy(data_root.join("nested/nested.zip"), &target_path).unwrap();
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      ..Default::default()
+    };
+
+    // nested.zip holds a byte-identical copy of doc_set1.zip, so pretend it's nested in one
+    let doc_set1_path = data_root.join("series/doc_set1.zip");
+    let mut ancestors = vec![
+      (content_hash(&doc_set1_path).unwrap(), doc_set1_path.clone()),
+      (content_hash(&target_path).unwrap(), target_path.clone()),
+    ];
+    let mut warnings = vec![];
+    let out_path = get_out_path_for_archive(&target_path, &config).unwrap();
+    extract_nested(
+      &target_path,
+      &out_path,
+      &config,
+      &mut ancestors,
+      &mut vec![],
+      &mut warnings,
+    )
+    .unwrap();
+
+    let nested = temp_dir.path().join("nested");
+    assert!(matches!(
+      &warnings[..],
+      [RZipWarning::ArchiveCycle(path, ancestor)]
+        if *path == nested.join("doc_set1.zip") && *ancestor == doc_set1_path
+    ));
+    assert!(!nested.join("doc_set1").exists());
+    assert!(nested.join("doc_set2/doc4.txt").exists());
+    assert_eq!(ancestors.len(), 2);
+  }
+}
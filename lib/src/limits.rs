@@ -0,0 +1,286 @@
+use std::{
+  cell::Cell,
+  fs,
+  io::{self, Read, Write},
+  path::Path,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::LimitViolation, volume, RZipExtractConfig};
+
+/// Limits on what unpacking an archive may write, guarding against decompression bombs. A limit
+/// that is `None` isn't enforced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtractLimits {
+  /// Maximum uncompressed bytes unpacked from a single archive.
+  pub max_archive_bytes: Option<u64>,
+  /// Maximum uncompressed bytes unpacked over a whole run, across every nested archive.
+  pub max_run_bytes: Option<u64>,
+  /// Maximum ratio of the bytes unpacked from an archive to the archive's own size.
+  pub max_ratio: Option<u64>,
+  /// Maximum number of entries in a single archive.
+  pub max_entries: Option<u64>,
+}
+
+/// Running totals of an extraction run. Clones of a config share the same totals.
+#[derive(Debug, Clone, Default)]
+pub struct RunUsage {
+  bytes: Arc<AtomicU64>,
+}
+
+impl RunUsage {
+  /// The uncompressed bytes unpacked so far.
+  pub fn bytes(&self) -> u64 {
+    self.bytes.load(Ordering::Relaxed)
+  }
+
+  fn add_bytes(&self, bytes: u64) {
+    self.bytes.fetch_add(bytes, Ordering::Relaxed);
+  }
+}
+
+/// Tracks what a single attempt at unpacking an archive has written against the configured
+/// limits. Bytes only count towards the run once the attempt [finishes](LimitTracker::finish).
+pub(crate) struct LimitTracker<'a> {
+  limits: &'a ExtractLimits,
+  usage: &'a RunUsage,
+  packed_bytes: u64,
+  entries: Cell<u64>,
+  bytes: Cell<u64>,
+}
+
+impl<'a> LimitTracker<'a> {
+  pub(crate) fn new(archive_path: &Path, config: &'a RZipExtractConfig) -> io::Result<Self> {
+    // The ratio is measured against every volume of a multi-volume archive
+    let mut packed_bytes = 0;
+    if let Some(archive) = config.in_memory.get(archive_path) {
+      packed_bytes = archive.contents.len() as u64;
+    } else {
+      for archive_file in volume::archive_files(archive_path).map_err(io::Error::other)? {
+        packed_bytes += fs::metadata(archive_file)?.len();
+      }
+    }
+
+    Ok(Self::with_packed_bytes(packed_bytes, config))
+  }
+
+  /// Track an archive of `packed_bytes` that isn't on disk, such as one read into memory.
+  pub(crate) fn with_packed_bytes(packed_bytes: u64, config: &'a RZipExtractConfig) -> Self {
+    Self {
+      limits: &config.limits,
+      usage: &config.usage,
+      packed_bytes,
+      entries: Cell::new(0),
+      bytes: Cell::new(0),
+    }
+  }
+
+  /// Count a new entry, checking its size up front when the archive declares it.
+  pub(crate) fn add_entry(&self, declared_size: Option<u64>) -> Result<(), LimitViolation> {
+    let entries = self.entries.get() + 1;
+    if let Some(max_entries) = self.limits.max_entries {
+      if entries > max_entries {
+        return Err(LimitViolation::Entries(max_entries));
+      }
+    }
+    self.entries.set(entries);
+
+    self.check_bytes(self.bytes.get().saturating_add(declared_size.unwrap_or(0)))
+  }
+
+  /// Count bytes as they are unpacked.
+  pub(crate) fn add_bytes(&self, len: u64) -> Result<(), LimitViolation> {
+    let bytes = self.bytes.get().saturating_add(len);
+    self.check_bytes(bytes)?;
+    self.bytes.set(bytes);
+
+    Ok(())
+  }
+
+  fn check_bytes(&self, bytes: u64) -> Result<(), LimitViolation> {
+    if let Some(max_archive_bytes) = self.limits.max_archive_bytes {
+      if bytes > max_archive_bytes {
+        return Err(LimitViolation::ArchiveBytes(max_archive_bytes));
+      }
+    }
+    if let Some(max_run_bytes) = self.limits.max_run_bytes {
+      if self.usage.bytes().saturating_add(bytes) > max_run_bytes {
+        return Err(LimitViolation::RunBytes(max_run_bytes));
+      }
+    }
+    if let Some(max_ratio) = self.limits.max_ratio {
+      if bytes > self.packed_bytes.saturating_mul(max_ratio) {
+        return Err(LimitViolation::Ratio(max_ratio));
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Wrap a reader of uncompressed data so that everything read counts towards the limits.
+  pub(crate) fn reader<R: Read>(&self, inner: R) -> LimitedReader<'_, 'a, R> {
+    LimitedReader {
+      inner,
+      limits: self,
+    }
+  }
+
+  /// Wrap a writer of uncompressed data so that everything written counts towards the limits.
+  pub(crate) fn writer<W: Write>(&self, inner: W) -> LimitedWriter<'_, 'a, W> {
+    LimitedWriter {
+      inner,
+      limits: self,
+    }
+  }
+
+  /// Add the bytes of a successful attempt to the run's totals.
+  pub(crate) fn finish(self) {
+    self.usage.add_bytes(self.bytes.get());
+  }
+}
+
+/// A reader that counts what it reads towards a [LimitTracker]. Violations are returned as IO
+/// errors wrapping the [LimitViolation].
+pub(crate) struct LimitedReader<'t, 'a, R> {
+  inner: R,
+  limits: &'t LimitTracker<'a>,
+}
+
+impl<R: Read> Read for LimitedReader<'_, '_, R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let len = self.inner.read(buf)?;
+    self
+      .limits
+      .add_bytes(len as u64)
+      .map_err(io::Error::other)?;
+
+    Ok(len)
+  }
+}
+
+/// A writer that counts what it writes towards a [LimitTracker]. Violations are returned as IO
+/// errors wrapping the [LimitViolation].
+pub(crate) struct LimitedWriter<'t, 'a, W> {
+  inner: W,
+  limits: &'t LimitTracker<'a>,
+}
+
+impl<W: Write> Write for LimitedWriter<'_, '_, W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    // Check before writing so that nothing past a limit reaches the disk
+    self
+      .limits
+      .check_bytes(self.limits.bytes.get().saturating_add(buf.len() as u64))
+      .map_err(io::Error::other)?;
+    let len = self.inner.write(buf)?;
+    self
+      .limits
+      .add_bytes(len as u64)
+      .map_err(io::Error::other)?;
+
+    Ok(len)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs::File;
+
+  use tempfile::TempDir;
+
+  use super::*;
+
+  fn config_with_limits(limits: ExtractLimits) -> RZipExtractConfig {
+    RZipExtractConfig {
+      limits,
+      ..Default::default()
+    }
+  }
+
+  fn archive_of_size(temp_dir: &TempDir, size: usize) -> std::path::PathBuf {
+    let archive_path = temp_dir.path().join("archive.zip");
+    File::create(&archive_path)
+      .unwrap()
+      .write_all(&vec![0; size])
+      .unwrap();
+    archive_path
+  }
+
+  #[test]
+  fn test_archive_limits() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive_path = archive_of_size(&temp_dir, 10);
+    let config = config_with_limits(ExtractLimits {
+      max_archive_bytes: Some(50),
+      max_ratio: Some(4),
+      max_entries: Some(2),
+      ..Default::default()
+    });
+
+    let limits = LimitTracker::new(&archive_path, &config).unwrap();
+    assert_eq!(limits.add_entry(Some(30)), Ok(()));
+    assert_eq!(limits.add_bytes(30), Ok(()));
+    assert_eq!(
+      limits.add_entry(Some(30)),
+      Err(LimitViolation::ArchiveBytes(50))
+    );
+    assert_eq!(limits.add_bytes(15), Err(LimitViolation::Ratio(4)));
+    assert_eq!(limits.add_entry(None), Err(LimitViolation::Entries(2)));
+  }
+
+  #[test]
+  fn test_run_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive_path = archive_of_size(&temp_dir, 10);
+    let config = config_with_limits(ExtractLimits {
+      max_run_bytes: Some(100),
+      ..Default::default()
+    });
+
+    // Failed attempts don't count towards the run
+    {
+      let limits = LimitTracker::new(&archive_path, &config).unwrap();
+      assert_eq!(limits.add_bytes(80), Ok(()));
+    }
+    assert_eq!(config.usage.bytes(), 0);
+
+    let limits = LimitTracker::new(&archive_path, &config).unwrap();
+    assert_eq!(limits.add_bytes(80), Ok(()));
+    limits.finish();
+    assert_eq!(config.usage.clone().bytes(), 80);
+
+    let limits = LimitTracker::new(&archive_path, &config).unwrap();
+    assert_eq!(limits.add_bytes(20), Ok(()));
+    assert_eq!(limits.add_bytes(1), Err(LimitViolation::RunBytes(100)));
+  }
+
+  #[test]
+  fn test_limited_writer() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive_path = archive_of_size(&temp_dir, 10);
+    let config = config_with_limits(ExtractLimits {
+      max_archive_bytes: Some(8),
+      ..Default::default()
+    });
+
+    let limits = LimitTracker::new(&archive_path, &config).unwrap();
+    let mut out = vec![];
+    let mut writer = limits.writer(&mut out);
+    writer.write_all(b"12345").unwrap();
+    let err = writer.write_all(b"6789").unwrap_err();
+    assert_eq!(
+      err.into_inner().unwrap().downcast_ref::<LimitViolation>(),
+      Some(&LimitViolation::ArchiveBytes(8))
+    );
+    assert_eq!(out, b"12345");
+  }
+}
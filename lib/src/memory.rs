@@ -0,0 +1,155 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  ffi::OsString,
+  fs::{self, File},
+  io::{self, Write},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex, MutexGuard},
+  time::SystemTime,
+};
+
+use filetime::FileTime;
+use sha2::{Digest, Sha256};
+
+use crate::{error::RZipError, volume, ContentHash};
+
+/// Nested archives held in memory rather than written out, by the path each would have been
+/// written to. Clones of a config share the same archives.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryArchives {
+  archives: Arc<Mutex<BTreeMap<PathBuf, InMemoryArchive>>>,
+}
+
+/// The contents of a nested archive held in memory, and the modification time it would have been
+/// written with.
+#[derive(Debug, Clone)]
+pub(crate) struct InMemoryArchive {
+  pub(crate) contents: Arc<[u8]>,
+  pub(crate) modified: SystemTime,
+}
+
+impl InMemoryArchives {
+  /// The paths of the archives held in memory.
+  pub fn paths(&self) -> Vec<PathBuf> {
+    self.lock().keys().cloned().collect()
+  }
+
+  pub(crate) fn insert(&self, path: PathBuf, contents: Vec<u8>, modified: SystemTime) {
+    let archive = InMemoryArchive {
+      contents: contents.into(),
+      modified,
+    };
+    self.lock().insert(path, archive);
+  }
+
+  pub(crate) fn get(&self, path: &Path) -> Option<InMemoryArchive> {
+    self.lock().get(path).cloned()
+  }
+
+  pub(crate) fn remove(&self, path: &Path) -> Option<InMemoryArchive> {
+    self.lock().remove(path)
+  }
+
+  /// The paths of the archives held for paths under `dir`, in path order.
+  pub(crate) fn paths_under(&self, dir: &Path) -> Vec<PathBuf> {
+    let archives = self.lock();
+    archives
+      .keys()
+      .filter(|path| path.starts_with(dir) && *path != dir)
+      .cloned()
+      .collect()
+  }
+
+  /// Hold the archives held for paths under `from` for the same paths under `to` instead, once
+  /// what was unpacked around them is moved there.
+  pub(crate) fn rename_under(&self, from: &Path, to: &Path) {
+    let mut archives = self.lock();
+    let moved: Vec<_> = archives
+      .keys()
+      .filter(|path| path.starts_with(from))
+      .cloned()
+      .collect();
+    for path in moved {
+      let archive = archives.remove(&path).unwrap();
+      let relative_path = path.strip_prefix(from).unwrap();
+      archives.insert(to.join(relative_path), archive);
+    }
+  }
+
+  /// Let go of the archives held for paths under `dir`, as when the attempt that unpacked them
+  /// fails.
+  pub(crate) fn remove_under(&self, dir: &Path) {
+    self.lock().retain(|path, _| !path.starts_with(dir));
+  }
+
+  /// Write the archive held for `path` out to it, as it would have been written had it not been
+  /// held in memory.
+  pub(crate) fn write_out(&self, path: &Path) -> io::Result<()> {
+    let Some(archive) = self.remove(path) else {
+      return Ok(());
+    };
+    File::create(path)?.write_all(&archive.contents)?;
+    let modified = FileTime::from_system_time(archive.modified);
+    filetime::set_file_times(path, modified, modified)
+  }
+
+  /// Write out every archive held for a path under `dir`.
+  pub(crate) fn write_out_under(&self, dir: &Path) -> io::Result<()> {
+    for path in self.paths_under(dir) {
+      self.write_out(&path)?;
+    }
+
+    Ok(())
+  }
+
+  /// The hash of the archive at `path`, whether it's held in memory or on disk.
+  pub(crate) fn content_hash(&self, path: &Path) -> Result<ContentHash, RZipError> {
+    match self.get(path) {
+      Some(archive) => Ok(Sha256::digest(&archive.contents).into()),
+      None => crate::content_hash(path),
+    }
+  }
+
+  /// The modification time of the archive at `path`, whether it's held in memory or on disk.
+  pub(crate) fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+    match self.get(path) {
+      Some(archive) => Ok(archive.modified),
+      None => fs::metadata(path)?.modified(),
+    }
+  }
+
+  /// Write out the archives held for paths under `dir` that share a name with volumes on disk, so
+  /// that they're unpacked as part of their multi-volume archive.
+  pub(crate) fn write_out_volumes(&self, dir: &Path) -> io::Result<()> {
+    // The volume stems of the files on disk, scanned once per directory
+    let mut disk_stems: BTreeMap<PathBuf, BTreeSet<OsString>> = BTreeMap::new();
+    for path in self.paths_under(dir) {
+      let (Some(stem), Some(parent)) = (volume::volume_stem(&path), path.parent()) else {
+        continue;
+      };
+      if !disk_stems.contains_key(parent) {
+        let mut stems = BTreeSet::new();
+        for entry in fs::read_dir(parent)? {
+          let sibling = entry?.path();
+          if sibling.is_file() {
+            stems.extend(volume::volume_stem(&sibling));
+          }
+        }
+        disk_stems.insert(parent.to_path_buf(), stems);
+      }
+      if disk_stems[parent].contains(&stem) {
+        self.write_out(&path)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  fn lock(&self) -> MutexGuard<'_, BTreeMap<PathBuf, InMemoryArchive>> {
+    // The archives are only ever left consistent, so a panic elsewhere doesn't spoil them
+    self
+      .archives
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+}
@@ -0,0 +1,280 @@
+use std::{
+  ffi::OsString,
+  fmt, fs, io,
+  path::{Path, PathBuf},
+  str::FromStr,
+  time::SystemTime,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{error::RZipError, format, volume, ContentHash};
+
+/// A placeholder in a [NameTemplate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+  Literal(String),
+  /// The archive's name without its archive or volume extension.
+  Stem,
+  /// The rest of the archive's name after the stem, such as `tar.gz`.
+  Ext,
+  /// The day the archive was last modified, as `YYYY-MM-DD` in UTC.
+  Date,
+  /// The first 8 hex digits of the SHA-256 hash of the archive's contents.
+  Hash8,
+  /// The name of the directory the archive is in.
+  Parent,
+}
+
+const PLACEHOLDERS: [(&str, Part); 5] = [
+  ("stem", Part::Stem),
+  ("ext", Part::Ext),
+  ("date", Part::Date),
+  ("hash8", Part::Hash8),
+  ("parent", Part::Parent),
+];
+
+/// The name an archive's output is given, relative to the directory it's unpacked in, such as
+/// `{stem}`, `{stem}-{date}` or `{parent}/{stem}`. Each of the placeholders `{stem}`, `{ext}`,
+/// `{date}`, `{hash8}` and `{parent}` is replaced by a property of the archive, and `/` separates
+/// directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+  parts: Vec<Part>,
+}
+
+impl Default for NameTemplate {
+  fn default() -> Self {
+    Self {
+      parts: vec![Part::Stem],
+    }
+  }
+}
+
+impl fmt::Display for NameTemplate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for part in &self.parts {
+      match part {
+        Part::Literal(literal) => f.write_str(literal)?,
+        placeholder => {
+          let (name, _) = PLACEHOLDERS
+            .iter()
+            .find(|(_, candidate)| candidate == placeholder)
+            .unwrap();
+          write!(f, "{{{name}}}")?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl FromStr for NameTemplate {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    // Whatever the placeholders become, the output has to stay in the directory it's unpacked in
+    if s.starts_with('/') || s.contains('\\') {
+      return Err(format!("Name template {s} must be a relative path"));
+    }
+    if s.split('/').any(|dir| matches!(dir, "" | "." | "..")) {
+      return Err(format!("Name template {s} has an empty, . or .. directory"));
+    }
+
+    let mut parts = vec![];
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+      if start > 0 {
+        parts.push(Part::Literal(rest[..start].to_string()));
+      }
+      let end = rest[start..]
+        .find('}')
+        .ok_or_else(|| format!("Name template {s} has an unclosed {{"))?;
+      let name = &rest[start + 1..start + end];
+      let (_, part) = PLACEHOLDERS
+        .iter()
+        .find(|(candidate, _)| *candidate == name)
+        .ok_or_else(|| {
+          format!(
+            "Unknown placeholder {{{name}}} in name template {s}, expected one of {{stem}}, \
+             {{ext}}, {{date}}, {{hash8}} or {{parent}}"
+          )
+        })?;
+      parts.push(part.clone());
+      rest = &rest[start + end + 1..];
+    }
+    if rest.contains('}') {
+      return Err(format!("Name template {s} has an unopened }}"));
+    }
+    if !rest.is_empty() {
+      parts.push(Part::Literal(rest.to_string()));
+    }
+
+    Ok(Self { parts })
+  }
+}
+
+// Templates are stored as they're written, such as `{stem}-{date}`
+impl Serialize for NameTemplate {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for NameTemplate {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?
+      .parse()
+      .map_err(de::Error::custom)
+  }
+}
+
+impl NameTemplate {
+  /// The name of the output of the archive at `archive_path`. When that's the archive's own name,
+  /// as for `{stem}` of an archive without an extension, `.d` is appended so the two don't collide.
+  /// Names that would be empty, or leave the directory the output is in, are the archive's name
+  /// with `.d` appended too.
+  pub fn render(&self, archive_path: &Path) -> Result<PathBuf, RZipError> {
+    self.render_with(
+      archive_path,
+      || fs::metadata(archive_path)?.modified(),
+      || crate::content_hash(archive_path),
+    )
+  }
+
+  /// Render the name of an archive's output, getting its modification time and content hash from
+  /// `modified` and `hash` only if the template needs them.
+  pub(crate) fn render_with(
+    &self,
+    archive_path: &Path,
+    modified: impl Fn() -> io::Result<SystemTime>,
+    hash: impl Fn() -> Result<ContentHash, RZipError>,
+  ) -> Result<PathBuf, RZipError> {
+    let file_name = archive_path.file_name().unwrap_or_default();
+    let stem = volume::volume_stem(archive_path)
+      .or_else(|| format::archive_stem(archive_path).map(|stem| stem.to_os_string()))
+      .ok_or_else(|| {
+        RZipError::RuntimeError(format!(
+          "Unable to determine file stem for {}",
+          archive_path.display()
+        ))
+      })?;
+
+    let mut name = OsString::new();
+    for part in &self.parts {
+      match part {
+        Part::Literal(literal) => name.push(literal),
+        Part::Stem => name.push(&stem),
+        Part::Ext => {
+          let file_name = file_name.to_string_lossy();
+          let stem_len = stem.to_string_lossy().len();
+          name.push(file_name.get(stem_len + 1..).unwrap_or_default());
+        }
+        Part::Date => name.push(utc_date(modified()?)),
+        Part::Hash8 => {
+          let hash = hash()?;
+          name.push(
+            hash[..4]
+              .iter()
+              .map(|b| format!("{b:02x}"))
+              .collect::<String>(),
+          );
+        }
+        Part::Parent => {
+          let parent = archive_path
+            .parent()
+            .and_then(Path::file_name)
+            .ok_or_else(|| {
+              RZipError::RuntimeError(format!(
+                "Unable to determine parent directory name for {}",
+                archive_path.display()
+              ))
+            })?;
+          name.push(parent);
+        }
+      }
+    }
+
+    // A placeholder can't lead the output out of its directory, as a `{stem}` of `..` would for
+    // `...zip`, so such names fall back to the archive's whole name
+    let mut output_name = PathBuf::from(name);
+    let is_unsafe = output_name
+      .to_string_lossy()
+      .split(['/', '\\'])
+      .any(|dir| matches!(dir, "" | "." | ".."));
+    if is_unsafe {
+      output_name = PathBuf::from(file_name);
+    }
+    if output_name.as_os_str() == file_name {
+      output_name.as_mut_os_string().push(".d");
+    }
+    Ok(output_name)
+  }
+}
+
+/// Format the day `time` falls on as `YYYY-MM-DD`, in UTC.
+fn utc_date(time: SystemTime) -> String {
+  let days = time
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .map(|duration| (duration.as_secs() / 86_400) as i64)
+    .unwrap_or_else(|e| -(e.duration().as_secs().div_ceil(86_400) as i64));
+
+  // Convert days since 1970-01-01 to a civil date, counting in 400-year eras from 0000-03-01
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let day_of_era = z.rem_euclid(146_097);
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month_index = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+  let month = if month_index < 10 {
+    month_index + 3
+  } else {
+    month_index - 9
+  };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+  format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_parse_name_template() {
+    for template in [
+      "{stem}",
+      "{stem}_{ext}",
+      "{stem}-{date}",
+      "{hash8}",
+      "{parent}/{stem}",
+    ] {
+      assert_eq!(
+        template.parse::<NameTemplate>().unwrap().to_string(),
+        template
+      );
+    }
+    for template in [
+      "{size}",
+      "{stem",
+      "stem}",
+      "../{stem}",
+      "/{stem}",
+      "a//{stem}",
+    ] {
+      assert!(template.parse::<NameTemplate>().is_err(), "{template}");
+    }
+  }
+
+  #[test]
+  fn test_utc_date() {
+    let date = |secs| utc_date(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+    assert_eq!(date(0), "1970-01-01");
+    assert_eq!(date(951_782_400), "2000-02-29");
+    assert_eq!(date(1_000_000_000), "2001-09-09");
+    assert_eq!(date(1_700_000_000), "2023-11-14");
+  }
+}
@@ -0,0 +1,109 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::RZipError, RZipExtractConfig};
+
+/// Which archives a run would extract and where to, along with the configuration to extract them
+/// with, so that a run can be reviewed before it's [applied](ExtractionPlan::verify). Plans are
+/// stored as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractionPlan {
+  /// The configuration the archives are extracted with. Passwords aren't part of the plan.
+  pub config: RZipExtractConfig,
+  pub archives: Vec<PlannedArchive>,
+}
+
+/// An archive in an [ExtractionPlan], with what it was like when it was planned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedArchive {
+  pub path: PathBuf,
+  pub out_path: PathBuf,
+  /// The size of the archive in bytes.
+  pub size: u64,
+  pub modified: SystemTime,
+  /// The SHA-256 hash of the archive's contents, including every volume, in hex.
+  pub sha256: String,
+}
+
+impl ExtractionPlan {
+  /// Plan extracting the target of `config`, which is either a single archive or a directory of
+  /// them.
+  pub fn new(config: &RZipExtractConfig) -> Result<Self, RZipError> {
+    let target_path = &config.target_path;
+    let archives = if target_path.is_dir() {
+      crate::get_archives_in_dir(target_path)?
+    } else if crate::is_archive_filetype(target_path) {
+      vec![target_path.clone()]
+    } else {
+      return Err(RZipError::RuntimeError(format!(
+        "{} is not an archive",
+        target_path.display()
+      )));
+    };
+    let out_paths = crate::get_out_paths_for_archives(&archives, config)?;
+
+    Ok(Self {
+      config: config.clone(),
+      archives: archives
+        .into_iter()
+        .zip(out_paths)
+        .map(|(path, out_path)| PlannedArchive::new(path, out_path))
+        .collect::<Result<_, _>>()?,
+    })
+  }
+
+  pub fn from_json(json: &str) -> Result<Self, RZipError> {
+    Ok(serde_json::from_str(json)?)
+  }
+
+  pub fn to_json(&self) -> Result<String, RZipError> {
+    Ok(serde_json::to_string_pretty(self)?)
+  }
+
+  /// Check that every archive is still there and unchanged since it was planned.
+  pub fn verify(&self) -> Result<(), RZipError> {
+    for archive in &self.archives {
+      // The hash is only worked out once the cheaper checks pass
+      let changed = match fs::metadata(&archive.path) {
+        Ok(metadata) => {
+          metadata.len() != archive.size
+            || metadata.modified()? != archive.modified
+            || content_hash(&archive.path)? != archive.sha256
+        }
+        Err(_) => true,
+      };
+      if changed {
+        return Err(RZipError::ArchiveChanged(archive.path.clone()));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl PlannedArchive {
+  fn new(path: PathBuf, out_path: PathBuf) -> Result<Self, RZipError> {
+    let metadata = fs::metadata(&path)?;
+    Ok(Self {
+      size: metadata.len(),
+      modified: metadata.modified()?,
+      sha256: content_hash(&path)?,
+      path,
+      out_path,
+    })
+  }
+}
+
+fn content_hash(path: &Path) -> Result<String, RZipError> {
+  Ok(
+    crate::content_hash(path)?
+      .iter()
+      .map(|b| format!("{b:02x}"))
+      .collect(),
+  )
+}
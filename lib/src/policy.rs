@@ -0,0 +1,895 @@
+use std::{
+  cell::{Cell, OnceCell, RefCell},
+  fmt, fs, io,
+  path::{Component, Path, PathBuf},
+  str::FromStr,
+  time::SystemTime,
+};
+
+use filetime::FileTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  error::{RZipProcessingError, RZipWarning},
+  format::ArchiveFormat,
+  RZipExtractConfig,
+};
+
+/// What to do with an entry whose path would unpack outside of the archive's output directory,
+/// such as `../evil.sh` or `/etc/passwd`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnsafePathPolicy {
+  /// Fail the archive, removing whatever it unpacked.
+  #[default]
+  Reject,
+  /// Leave the entry out, reporting it as a warning.
+  Skip,
+}
+
+impl fmt::Display for UnsafePathPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Reject => "reject",
+      Self::Skip => "skip",
+    })
+  }
+}
+
+impl FromStr for UnsafePathPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "reject" => Ok(Self::Reject),
+      "skip" => Ok(Self::Skip),
+      _ => Err(format!(
+        "Unknown unsafe path policy {s}, expected reject or skip"
+      )),
+    }
+  }
+}
+
+/// What to do with links and special files, the entries that don't unpack to a regular file or a
+/// directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecialEntryPolicy {
+  /// Create them. Links that point outside of the output directory are handled as unsafe paths.
+  #[default]
+  Allow,
+  /// Leave them out, reporting each as a warning.
+  Skip,
+  /// Unpack links as copies of the files they point to, and special files as empty regular files.
+  /// Links to anything but an unpacked file are skipped.
+  Materialize,
+  /// Fail the archive, removing whatever it unpacked.
+  Reject,
+}
+
+impl fmt::Display for SpecialEntryPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Allow => "allow",
+      Self::Skip => "skip",
+      Self::Materialize => "materialize",
+      Self::Reject => "reject",
+    })
+  }
+}
+
+impl FromStr for SpecialEntryPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "allow" => Ok(Self::Allow),
+      "skip" => Ok(Self::Skip),
+      "materialize" => Ok(Self::Materialize),
+      "reject" => Ok(Self::Reject),
+      _ => Err(format!(
+        "Unknown special entry policy {s}, expected allow, skip, materialize or reject"
+      )),
+    }
+  }
+}
+
+/// Who unpacked files and directories belong to. Owners are only set on unix platforms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnershipPolicy {
+  /// Leave them to whoever runs the extraction.
+  #[default]
+  Ignore,
+  /// Give them the owners the archive records when running as root, and otherwise leave them to
+  /// whoever runs the extraction with a warning. Entries of formats that don't record owners are
+  /// left alone.
+  Preserve,
+  /// Give them these owners, leaving whichever is `None` to whoever runs the extraction.
+  Remap { uid: Option<u32>, gid: Option<u32> },
+}
+
+/// What to do when an archive's output, or a file in it, is already there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+  /// Unpack into existing output, replacing files the archive has too.
+  #[default]
+  Overwrite,
+  /// Leave archives whose output exists packed, and keep files already there, reporting each as a
+  /// warning.
+  Skip,
+  /// Unpack to the first free name of the form `foo (1)`, for both outputs and files.
+  Rename,
+  /// Unpack into existing output, keeping whichever of two files was modified last. An output that
+  /// is a single file is kept if it's newer than the archive.
+  Merge,
+}
+
+impl fmt::Display for ConflictPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Overwrite => "overwrite",
+      Self::Skip => "skip",
+      Self::Rename => "rename",
+      Self::Merge => "merge",
+    })
+  }
+}
+
+impl FromStr for ConflictPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "overwrite" => Ok(Self::Overwrite),
+      "skip" => Ok(Self::Skip),
+      "rename" => Ok(Self::Rename),
+      "merge" => Ok(Self::Merge),
+      _ => Err(format!(
+        "Unknown conflict policy {s}, expected overwrite, skip, rename or merge"
+      )),
+    }
+  }
+}
+
+/// Which timestamps unpacked files and directories get.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampPolicy {
+  /// The modification and access times recorded in the archive. Entries without a recorded access
+  /// time get their modification time for both.
+  #[default]
+  Preserve,
+  /// The time they were unpacked at.
+  Now,
+}
+
+impl fmt::Display for TimestampPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Preserve => "preserve",
+      Self::Now => "now",
+    })
+  }
+}
+
+impl FromStr for TimestampPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "preserve" => Ok(Self::Preserve),
+      "now" => Ok(Self::Now),
+      _ => Err(format!(
+        "Unknown timestamp policy {s}, expected preserve or now"
+      )),
+    }
+  }
+}
+
+/// How the files unpacked from an archive and the archives nested in it are laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputLayout {
+  /// Mirror the archives, with each nested archive unpacked next to itself.
+  #[default]
+  Nested,
+  /// Move every file that isn't an unpacked archive into the top output directory, listing where
+  /// each came from in an index.
+  Flat,
+}
+
+impl fmt::Display for OutputLayout {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Nested => "nested",
+      Self::Flat => "flat",
+    })
+  }
+}
+
+impl FromStr for OutputLayout {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "nested" => Ok(Self::Nested),
+      "flat" => Ok(Self::Flat),
+      _ => Err(format!("Unknown layout {s}, expected nested or flat")),
+    }
+  }
+}
+
+/// The kinds of entry that [SpecialEntryPolicy] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialEntryKind {
+  Symlink,
+  Hardlink,
+  Fifo,
+  CharDevice,
+  BlockDevice,
+}
+
+impl SpecialEntryKind {
+  /// File type bits of a unix mode.
+  const S_IFMT: u32 = 0o170000;
+  const S_IFIFO: u32 = 0o010000;
+  const S_IFCHR: u32 = 0o020000;
+  const S_IFBLK: u32 = 0o060000;
+  const S_IFLNK: u32 = 0o120000;
+
+  /// The kind of a special entry with the unix `mode`, or `None` for any other entry.
+  pub(crate) fn from_mode(mode: u32) -> Option<Self> {
+    match mode & Self::S_IFMT {
+      Self::S_IFLNK => Some(Self::Symlink),
+      Self::S_IFIFO => Some(Self::Fifo),
+      Self::S_IFCHR => Some(Self::CharDevice),
+      Self::S_IFBLK => Some(Self::BlockDevice),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for SpecialEntryKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Symlink => "symlink",
+      Self::Hardlink => "hard link",
+      Self::Fifo => "FIFO",
+      Self::CharDevice => "character device",
+      Self::BlockDevice => "block device",
+    })
+  }
+}
+
+/// The permissions, owners, timestamps and extended attributes an archive records for an entry,
+/// as far as its format has them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct EntryMetadata {
+  pub(crate) mode: Option<u32>,
+  pub(crate) uid: Option<u32>,
+  pub(crate) gid: Option<u32>,
+  pub(crate) mtime: Option<SystemTime>,
+  pub(crate) atime: Option<SystemTime>,
+  pub(crate) xattrs: Vec<(String, Vec<u8>)>,
+}
+
+/// How a backend unpacks a link or special file, as decided by [EntryGuard::special_entry].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SpecialEntryAction {
+  /// Create the entry as it is in the archive. Links carry the path they point to once unpacked.
+  Create(Option<PathBuf>),
+  /// Write a regular file instead, with the contents of the given file or empty.
+  Materialize(Option<PathBuf>),
+  /// Leave the entry out.
+  Skip,
+}
+
+/// Applies the configured policies to the entries of a single attempt at unpacking an archive, so
+/// that every backend treats them the same way. Entries that are left out are collected as
+/// warnings, which are returned once the attempt [finishes](EntryGuard::finish).
+pub(crate) struct EntryGuard<'a> {
+  archive_path: &'a Path,
+  out_path: &'a Path,
+  config: &'a RZipExtractConfig,
+  warnings: RefCell<Vec<RZipWarning>>,
+  canonical_out_path: OnceCell<PathBuf>,
+  directories: RefCell<Vec<(PathBuf, EntryMetadata)>>,
+  /// Directories that conflicted with a file, and where they were renamed to or `None` if they
+  /// were skipped.
+  resolved_dirs: RefCell<Vec<(PathBuf, Option<PathBuf>)>>,
+  /// Whether recorded owners were left unset, as only root can preserve them.
+  kept_owners: Cell<bool>,
+}
+
+impl<'a> EntryGuard<'a> {
+  pub(crate) fn new(
+    archive_path: &'a Path,
+    out_path: &'a Path,
+    config: &'a RZipExtractConfig,
+  ) -> Self {
+    Self {
+      archive_path,
+      out_path,
+      config,
+      warnings: RefCell::new(vec![]),
+      canonical_out_path: OnceCell::new(),
+      directories: RefCell::new(vec![]),
+      resolved_dirs: RefCell::new(vec![]),
+      kept_owners: Cell::new(false),
+    }
+  }
+
+  /// The path that an entry named `entry_name` unpacks to, or `None` if the entry is skipped. Paths
+  /// that lead through a link out of the output directory are as unsafe as `../` ones.
+  pub(crate) fn entry_path(
+    &self,
+    entry_name: &str,
+  ) -> Result<Option<PathBuf>, RZipProcessingError> {
+    match enclosed_path(self.out_path, entry_name) {
+      Some(entry_path) if self.is_physically_enclosed(&entry_path)? => Ok(Some(entry_path)),
+      _ => self.unsafe_entry(entry_name),
+    }
+  }
+
+  /// Decide how to unpack a link or special file to `entry_path`. `target` is the path a link
+  /// points to, if the backend knows it.
+  pub(crate) fn special_entry(
+    &self,
+    entry_name: &str,
+    entry_path: &Path,
+    kind: SpecialEntryKind,
+    target: Option<&str>,
+  ) -> Result<SpecialEntryAction, RZipProcessingError> {
+    let policy = self.config.special_entries;
+    match policy {
+      SpecialEntryPolicy::Reject => {
+        return Err(RZipProcessingError::SpecialEntry(
+          entry_name.to_string(),
+          kind,
+        ))
+      }
+      SpecialEntryPolicy::Skip => return Ok(self.skip_special_entry(entry_name, kind)),
+      SpecialEntryPolicy::Allow | SpecialEntryPolicy::Materialize => (),
+    }
+
+    // Links need their targets checked, and backends that can't tell them fail over to others
+    let link_path = match kind {
+      SpecialEntryKind::Symlink | SpecialEntryKind::Hardlink => {
+        let Some(target) = target else {
+          return Err(RZipProcessingError::UnsupportedEntry(
+            entry_name.to_string(),
+          ));
+        };
+        let link_path = match kind {
+          SpecialEntryKind::Symlink => symlink_path(self.out_path, entry_path, target),
+          _ => enclosed_path(self.out_path, target),
+        };
+        match link_path {
+          Some(link_path) => Some(link_path),
+          None => {
+            self.unsafe_entry(entry_name)?;
+            return Ok(SpecialEntryAction::Skip);
+          }
+        }
+      }
+      _ => None,
+    };
+
+    // Links keep what they point to on disk, rather than in memory
+    if let Some(link_path) = &link_path {
+      self.config.in_memory.write_out(link_path)?;
+    }
+    if policy == SpecialEntryPolicy::Allow {
+      return Ok(SpecialEntryAction::Create(link_path));
+    }
+    match link_path {
+      // Only files that are already unpacked, and not reached through a link leading outside, can
+      // be copied
+      Some(link_path) => {
+        if link_path.is_file() && self.is_physically_enclosed(&link_path)? {
+          Ok(SpecialEntryAction::Materialize(Some(link_path)))
+        } else {
+          Ok(self.skip_special_entry(entry_name, kind))
+        }
+      }
+      None => Ok(SpecialEntryAction::Materialize(None)),
+    }
+  }
+
+  fn skip_special_entry(&self, entry_name: &str, kind: SpecialEntryKind) -> SpecialEntryAction {
+    self.warnings.borrow_mut().push(RZipWarning::SpecialEntry(
+      self.archive_path.to_path_buf(),
+      entry_name.to_string(),
+      kind,
+    ));
+    SpecialEntryAction::Skip
+  }
+
+  /// Whether the closest existing ancestor of `path` really is inside of the output directory,
+  /// once any links along the way are followed.
+  fn is_physically_enclosed(&self, path: &Path) -> io::Result<bool> {
+    if !self.out_path.exists() {
+      return Ok(true);
+    }
+    let canonical_out_path = match self.canonical_out_path.get() {
+      Some(canonical_out_path) => canonical_out_path,
+      None => {
+        let canonical_out_path = fs::canonicalize(self.out_path)?;
+        self.canonical_out_path.get_or_init(|| canonical_out_path)
+      }
+    };
+
+    // The path itself is replaced when it's written, so only its ancestors matter
+    for ancestor in path.ancestors().skip(1) {
+      if fs::symlink_metadata(ancestor).is_ok() {
+        return match fs::canonicalize(ancestor) {
+          Ok(ancestor) => Ok(ancestor.starts_with(canonical_out_path)),
+          // A dangling link leads nowhere safe
+          Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+          Err(e) => Err(e),
+        };
+      }
+    }
+
+    Ok(false)
+  }
+
+  /// Handle an entry that would reach outside of the output directory according to policy.
+  fn unsafe_entry(&self, entry_name: &str) -> Result<Option<PathBuf>, RZipProcessingError> {
+    match self.config.unsafe_paths {
+      UnsafePathPolicy::Reject => Err(RZipProcessingError::UnsafePath(entry_name.to_string())),
+      UnsafePathPolicy::Skip => {
+        self.warnings.borrow_mut().push(RZipWarning::UnsafePath(
+          self.archive_path.to_path_buf(),
+          entry_name.to_string(),
+        ));
+        Ok(None)
+      }
+    }
+  }
+
+  /// Where an entry unpacks to when a file is already at `entry_path`, or `None` if the entry is
+  /// skipped. Directories are merged with whatever directory is there, and otherwise conflict like
+  /// files, as does a file in the way of a directory the entry is in. Whatever is there is left
+  /// for the caller to replace, and entries in a directory that was renamed or skipped follow it.
+  pub(crate) fn resolve_conflict(
+    &self,
+    entry_name: &str,
+    entry_path: PathBuf,
+    metadata: &EntryMetadata,
+    is_dir: bool,
+  ) -> io::Result<Option<PathBuf>> {
+    let resolved_dir = self
+      .resolved_dirs
+      .borrow()
+      .iter()
+      .find(|(dir, _)| entry_path.starts_with(dir))
+      .cloned();
+    let entry_path = match resolved_dir {
+      Some((dir, Some(renamed_dir))) => match entry_path.strip_prefix(dir) {
+        Ok(relative_path) if !relative_path.as_os_str().is_empty() => {
+          renamed_dir.join(relative_path)
+        }
+        _ => renamed_dir,
+      },
+      Some((_, None)) => return Ok(None),
+      None => entry_path,
+    };
+
+    // An archive held in memory for the same path conflicts like the file it would have been
+    self.config.in_memory.write_out(&entry_path)?;
+    let (conflict_path, is_dir) = match self.file_in_the_way(&entry_path) {
+      Some(file_path) => (file_path, true),
+      None => (entry_path.clone(), is_dir),
+    };
+    let existing = match fs::symlink_metadata(&conflict_path) {
+      Ok(existing) if !(is_dir && existing.is_dir()) => existing,
+      _ => return Ok(Some(entry_path)),
+    };
+
+    let keep_existing = match self.config.on_conflict {
+      ConflictPolicy::Overwrite => false,
+      ConflictPolicy::Skip => true,
+      ConflictPolicy::Rename => {
+        let renamed_path = available_path(&conflict_path, !is_dir);
+        if is_dir {
+          self
+            .resolved_dirs
+            .borrow_mut()
+            .push((conflict_path.clone(), Some(renamed_path.clone())));
+        }
+        return Ok(Some(match entry_path.strip_prefix(&conflict_path) {
+          Ok(relative_path) if !relative_path.as_os_str().is_empty() => {
+            renamed_path.join(relative_path)
+          }
+          _ => renamed_path,
+        }));
+      }
+      // Entries without a recorded time can't be older
+      ConflictPolicy::Merge => match metadata.mtime {
+        Some(mtime) => existing.modified()? > mtime,
+        None => false,
+      },
+    };
+    if !keep_existing {
+      return Ok(Some(entry_path));
+    }
+
+    if is_dir {
+      self.resolved_dirs.borrow_mut().push((conflict_path, None));
+    }
+    self.warnings.borrow_mut().push(RZipWarning::Conflict(
+      self.archive_path.to_path_buf(),
+      entry_name.to_string(),
+    ));
+    Ok(None)
+  }
+
+  /// The file in the way of one of the directories that `entry_path` is in, if any.
+  fn file_in_the_way(&self, entry_path: &Path) -> Option<PathBuf> {
+    entry_path
+      .ancestors()
+      .skip(1)
+      .take_while(|dir| dir.starts_with(self.out_path) && *dir != self.out_path)
+      .find(|dir| fs::symlink_metadata(dir).is_ok_and(|metadata| !metadata.is_dir()))
+      .map(Path::to_path_buf)
+  }
+
+  /// How many bytes of a file entry unpacked to `entry_path` may be held in memory instead of
+  /// written out, if any. Only entries named like archives are, as only nested archives are
+  /// unpacked from there.
+  pub(crate) fn max_in_memory_bytes(&self, entry_path: &Path) -> Option<u64> {
+    let file_name = entry_path.file_name()?.to_str()?;
+    ArchiveFormat::from_file_name(file_name)?;
+    self.config.max_in_memory_bytes
+  }
+
+  /// Hold the nested archive unpacked to `entry_path` in memory instead of writing it out, with
+  /// the modification time its file would have had.
+  pub(crate) fn keep_in_memory(
+    &self,
+    entry_path: &Path,
+    contents: Vec<u8>,
+    metadata: &EntryMetadata,
+  ) {
+    let modified = metadata
+      .mtime
+      .filter(|_| self.config.timestamps == TimestampPolicy::Preserve)
+      .unwrap_or_else(SystemTime::now);
+    let entry_path = entry_path.to_path_buf();
+    self.config.in_memory.insert(entry_path, contents, modified);
+  }
+
+  /// Apply the configured permissions, owners, timestamps and extended attributes to an unpacked
+  /// entry. Directories are left until the attempt finishes, so that neither their permissions nor
+  /// the entries unpacked into them get in the way, and links don't get permissions.
+  pub(crate) fn set_metadata(&self, entry_path: &Path, metadata: EntryMetadata) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(entry_path)?.file_type();
+    if file_type.is_dir() {
+      self
+        .directories
+        .borrow_mut()
+        .push((entry_path.to_path_buf(), metadata));
+      return Ok(());
+    }
+
+    self.apply_metadata(entry_path, &metadata, file_type.is_symlink())
+  }
+
+  fn apply_metadata(
+    &self,
+    entry_path: &Path,
+    metadata: &EntryMetadata,
+    is_symlink: bool,
+  ) -> io::Result<()> {
+    #[cfg(unix)]
+    self.apply_unix_metadata(entry_path, metadata, is_symlink)?;
+
+    let Some(mtime) = metadata
+      .mtime
+      .filter(|_| self.config.timestamps == TimestampPolicy::Preserve)
+    else {
+      return Ok(());
+    };
+    let mtime = FileTime::from_system_time(mtime);
+    let atime = metadata.atime.map_or(mtime, FileTime::from_system_time);
+    if is_symlink {
+      filetime::set_symlink_file_times(entry_path, atime, mtime)
+    } else {
+      filetime::set_file_times(entry_path, atime, mtime)
+    }
+  }
+
+  #[cfg(unix)]
+  fn apply_unix_metadata(
+    &self,
+    entry_path: &Path,
+    metadata: &EntryMetadata,
+    is_symlink: bool,
+  ) -> io::Result<()> {
+    use std::os::unix::fs::{lchown, PermissionsExt};
+
+    // Only root can give files to others, so anyone else keeps them
+    let (uid, gid) = match self.config.ownership {
+      OwnershipPolicy::Ignore => (None, None),
+      OwnershipPolicy::Preserve if unsafe { libc::geteuid() } != 0 => {
+        if metadata.uid.is_some() || metadata.gid.is_some() {
+          self.kept_owners.set(true);
+        }
+        (None, None)
+      }
+      OwnershipPolicy::Preserve => (metadata.uid, metadata.gid),
+      OwnershipPolicy::Remap { uid, gid } => (uid, gid),
+    };
+    if uid.is_some() || gid.is_some() {
+      lchown(entry_path, uid, gid)?;
+    }
+    if is_symlink {
+      return Ok(());
+    }
+
+    // Attributes go on before the permissions can make the file read-only. Other namespaces can
+    // grant privileges, and one that can't be set doesn't stop the rest of the entry.
+    if self.config.preserve_xattrs {
+      for (name, value) in &metadata.xattrs {
+        if !self.config.all_xattrs && !name.starts_with("user.") {
+          continue;
+        }
+        if let Err(e) = xattr::set(entry_path, name, value) {
+          self.warnings.borrow_mut().push(RZipWarning::Xattr(
+            self.archive_path.to_path_buf(),
+            self.entry_name(entry_path),
+            name.clone(),
+            e.to_string(),
+          ));
+        }
+      }
+    }
+
+    // Entries without a recorded mode keep the one they were created with
+    let mode = match metadata.mode {
+      Some(mode) => mode,
+      None => fs::metadata(entry_path)?.permissions().mode(),
+    };
+    fs::set_permissions(
+      entry_path,
+      fs::Permissions::from_mode(mode & 0o777 & !self.config.umask),
+    )
+  }
+
+  /// The name in the archive of the entry unpacked to `entry_path`.
+  #[cfg(unix)]
+  fn entry_name(&self, entry_path: &Path) -> String {
+    let relative_path = entry_path.strip_prefix(self.out_path).unwrap_or(entry_path);
+    relative_path
+      .components()
+      .map(|component| component.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/")
+  }
+
+  /// The warnings of a successful attempt, once the metadata of its directories is applied.
+  pub(crate) fn finish(self) -> io::Result<Vec<RZipWarning>> {
+    // Deeper directories go first, before their parents can lock them away
+    let mut directories = self.directories.take();
+    directories.sort_by_key(|(entry_path, _)| std::cmp::Reverse(entry_path.components().count()));
+    for (entry_path, metadata) in directories {
+      self.apply_metadata(&entry_path, &metadata, false)?;
+    }
+
+    let mut warnings = self.warnings.into_inner();
+    if self.kept_owners.get() {
+      warnings.push(RZipWarning::KeptOwners(self.archive_path.to_path_buf()));
+    }
+    Ok(warnings)
+  }
+}
+
+/// The first of `path (1)`, `path (2)` and so on that nothing is at. The number goes before
+/// the extension if `keep_extension` is set, as in `doc (1).txt`.
+pub(crate) fn available_path(path: &Path, keep_extension: bool) -> PathBuf {
+  numbered_paths(path, keep_extension)
+    .find(|path| fs::symlink_metadata(path).is_err())
+    .unwrap()
+}
+
+/// `path (1)`, `path (2)` and so on, numbered as in [available_path].
+pub(crate) fn numbered_paths(
+  path: &Path,
+  keep_extension: bool,
+) -> impl Iterator<Item = PathBuf> + '_ {
+  let (stem, extension) = match (path.file_stem(), path.extension()) {
+    (Some(stem), Some(extension)) if keep_extension => (stem, Some(extension)),
+    _ => (path.file_name().unwrap_or_default(), None),
+  };
+
+  (1..).map(move |n| {
+    let mut name = stem.to_os_string();
+    name.push(format!(" ({n})"));
+    if let Some(extension) = extension {
+      name.push(".");
+      name.push(extension);
+    }
+    path.with_file_name(name)
+  })
+}
+
+/// The path that an entry named `entry_name` unpacks to under `out_path`, or `None` if it would
+/// land outside of `out_path`.
+pub(crate) fn enclosed_path(out_path: &Path, entry_name: &str) -> Option<PathBuf> {
+  let mut entry_path = out_path.to_path_buf();
+  for component in Path::new(entry_name).components() {
+    match component {
+      Component::Normal(part) => entry_path.push(part),
+      Component::CurDir => (),
+      Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+    }
+  }
+
+  Some(entry_path)
+}
+
+/// The path that a symlink unpacked to `entry_path` points to, or `None` if it would point outside
+/// of `out_path`. `..` is only followed at the start of `target`, where it walks up from the
+/// link's own directory; after a directory name it could be undone by that directory being a link.
+pub(crate) fn symlink_path(out_path: &Path, entry_path: &Path, target: &str) -> Option<PathBuf> {
+  let mut link_path = entry_path.parent()?.to_path_buf();
+  let mut leading = true;
+  for component in Path::new(target).components() {
+    match component {
+      Component::ParentDir if leading => {
+        if !link_path.pop() || !link_path.starts_with(out_path) {
+          return None;
+        }
+      }
+      Component::Normal(part) => {
+        leading = false;
+        link_path.push(part);
+      }
+      Component::CurDir => (),
+      Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+    }
+  }
+
+  Some(link_path)
+}
+
+#[cfg(test)]
+mod test {
+  use tempfile::TempDir;
+
+  use super::*;
+
+  #[test]
+  fn test_enclosed_path() {
+    let out_path = Path::new("out");
+    assert_eq!(
+      enclosed_path(out_path, "dir/./file.txt"),
+      Some(PathBuf::from("out/dir/file.txt"))
+    );
+    assert_eq!(enclosed_path(out_path, "../file.txt"), None);
+    assert_eq!(enclosed_path(out_path, "dir/../../file.txt"), None);
+    assert_eq!(enclosed_path(out_path, "/etc/passwd"), None);
+  }
+
+  #[test]
+  fn test_entry_path_policy() {
+    let archive_path = Path::new("archive.zip");
+    let out_path = Path::new("out");
+    let mut config = RZipExtractConfig::default();
+
+    let guard = EntryGuard::new(archive_path, out_path, &config);
+    assert!(matches!(
+      guard.entry_path("../evil.sh"),
+      Err(RZipProcessingError::UnsafePath(name)) if name == "../evil.sh"
+    ));
+
+    config.unsafe_paths = UnsafePathPolicy::Skip;
+    let guard = EntryGuard::new(archive_path, out_path, &config);
+    assert_eq!(
+      guard.entry_path("doc.txt").unwrap(),
+      Some(PathBuf::from("out/doc.txt"))
+    );
+    assert_eq!(guard.entry_path("/etc/passwd").unwrap(), None);
+    assert!(matches!(
+      &guard.finish().unwrap()[..],
+      [RZipWarning::UnsafePath(path, name)] if path == archive_path && name == "/etc/passwd"
+    ));
+  }
+
+  #[test]
+  fn test_symlink_path() {
+    let out_path = Path::new("out");
+    let entry_path = Path::new("out/dir/link");
+    assert_eq!(
+      symlink_path(out_path, entry_path, "../doc.txt"),
+      Some(PathBuf::from("out/doc.txt"))
+    );
+    assert_eq!(
+      symlink_path(out_path, entry_path, "./sub/doc.txt"),
+      Some(PathBuf::from("out/dir/sub/doc.txt"))
+    );
+    assert_eq!(symlink_path(out_path, entry_path, "../../doc.txt"), None);
+    assert_eq!(symlink_path(out_path, entry_path, "sub/../doc.txt"), None);
+    assert_eq!(symlink_path(out_path, entry_path, "/etc/passwd"), None);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_entry_path_through_link() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_path = temp_dir.path().join("out");
+    fs::create_dir_all(out_path.join("dir")).unwrap();
+    std::os::unix::fs::symlink("dir", out_path.join("inner")).unwrap();
+    std::os::unix::fs::symlink(temp_dir.path(), out_path.join("outer")).unwrap();
+    let config = RZipExtractConfig::default();
+
+    // Links already in the output directory are followed to see where entries really land
+    let guard = EntryGuard::new(Path::new("archive.tar"), &out_path, &config);
+    assert_eq!(
+      guard.entry_path("inner/doc.txt").unwrap(),
+      Some(out_path.join("inner/doc.txt"))
+    );
+    assert!(matches!(
+      guard.entry_path("outer/sub/evil.txt"),
+      Err(RZipProcessingError::UnsafePath(_))
+    ));
+    // The link itself can be replaced
+    assert_eq!(
+      guard.entry_path("outer").unwrap(),
+      Some(out_path.join("outer"))
+    );
+  }
+
+  #[test]
+  fn test_special_entry_policy() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_path = temp_dir.path().join("out");
+    fs::create_dir_all(&out_path).unwrap();
+    fs::write(out_path.join("doc.txt"), "doc").unwrap();
+    let entry_path = out_path.join("link");
+    let mut config = RZipExtractConfig::default();
+
+    let guard = EntryGuard::new(Path::new("archive.tar"), &out_path, &config);
+    let special_entry = |kind, target| guard.special_entry("link", &entry_path, kind, target);
+    assert_eq!(
+      special_entry(SpecialEntryKind::Symlink, Some("doc.txt")).unwrap(),
+      SpecialEntryAction::Create(Some(out_path.join("doc.txt")))
+    );
+    assert_eq!(
+      special_entry(SpecialEntryKind::Fifo, None).unwrap(),
+      SpecialEntryAction::Create(None)
+    );
+    assert!(matches!(
+      special_entry(SpecialEntryKind::Symlink, None),
+      Err(RZipProcessingError::UnsupportedEntry(_))
+    ));
+    assert!(matches!(
+      special_entry(SpecialEntryKind::Hardlink, Some("../doc.txt")),
+      Err(RZipProcessingError::UnsafePath(_))
+    ));
+
+    config.special_entries = SpecialEntryPolicy::Materialize;
+    let guard = EntryGuard::new(Path::new("archive.tar"), &out_path, &config);
+    let special_entry = |kind, target| guard.special_entry("link", &entry_path, kind, target);
+    assert_eq!(
+      special_entry(SpecialEntryKind::Hardlink, Some("doc.txt")).unwrap(),
+      SpecialEntryAction::Materialize(Some(out_path.join("doc.txt")))
+    );
+    assert_eq!(
+      special_entry(SpecialEntryKind::CharDevice, None).unwrap(),
+      SpecialEntryAction::Materialize(None)
+    );
+    // Only files that are already unpacked can be copied
+    assert_eq!(
+      special_entry(SpecialEntryKind::Symlink, Some("missing.txt")).unwrap(),
+      SpecialEntryAction::Skip
+    );
+    assert!(matches!(
+      &guard.finish().unwrap()[..],
+      [RZipWarning::SpecialEntry(_, name, SpecialEntryKind::Symlink)] if name == "link"
+    ));
+  }
+}
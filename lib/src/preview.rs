@@ -0,0 +1,575 @@
+use std::{
+  fs,
+  io::{self, Cursor, Write},
+  path::{Component, Path, PathBuf},
+  time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+  error::{RZipError, RZipProcessingError, RZipWarning},
+  flatten,
+  format::ArchiveFormat,
+  limits::LimitTracker,
+  policy::{
+    self, EntryGuard, EntryMetadata, SpecialEntryKind, SpecialEntryPolicy, UnsafePathPolicy,
+  },
+  unpack::{self, ArchiveReader},
+  volume::{self, VolumeSet},
+  ContentHash, OutputLayout, RZipExtractConfig, TimestampPolicy,
+};
+
+/// File type bits of the `st_mode` libarchive reports for an entry.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// How much of the nested archives in one listing a preview holds in memory, unless
+/// [max_in_memory_bytes](RZipExtractConfig::max_in_memory_bytes) allows more.
+const MAX_PREVIEW_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How much is kept of a nested archive too big to hold, enough to recognize its format.
+const HEADER_LEN: usize = 512;
+
+/// What extracting an archive would unpack, read from the archive's listing without writing
+/// anything.
+#[derive(Debug)]
+pub struct ArchivePreview {
+  /// Where the archive is, or would be once the archives around it are unpacked.
+  pub archive_path: PathBuf,
+  /// Where the archive would be unpacked to.
+  pub out_path: PathBuf,
+  /// What would be unpacked, in the order the archive lists it.
+  pub entries: Vec<PreviewEntry>,
+  /// The warnings unpacking the archive would give, including for the archives nested in it that
+  /// would be left packed.
+  pub warnings: Vec<RZipWarning>,
+  /// The files that would be deleted once the archive is unpacked.
+  pub deleted: Vec<PathBuf>,
+  /// The files already there that unpacking the archive would replace.
+  pub overwritten: Vec<PathBuf>,
+}
+
+/// Something an archive would unpack.
+#[derive(Debug)]
+pub struct PreviewEntry {
+  pub path: PathBuf,
+  pub kind: PreviewKind,
+}
+
+#[derive(Debug)]
+pub enum PreviewKind {
+  Directory,
+  /// A file, and its size in bytes.
+  File(u64),
+  /// A nested archive, its size in bytes and what it would unpack in turn.
+  Archive(u64, Box<ArchivePreview>),
+  /// A nested archive whose listing couldn't be read, its size in bytes and why.
+  Unlisted(u64, String),
+  /// The index written to a flattened output.
+  Index,
+}
+
+/// An entry read from an archive's listing. The data of entries that look like archives is kept
+/// so that they can be previewed in turn.
+struct ListedEntry {
+  name: String,
+  mode: u32,
+  size: u64,
+  mtime: Option<SystemTime>,
+  /// The data of an entry that looks like an archive, or only its start if it's too big to hold.
+  data: Option<Vec<u8>>,
+  /// Whether `data` was cut short.
+  truncated: bool,
+  /// The hash of all of `data`, including what was cut.
+  hasher: Sha256,
+}
+
+impl ListedEntry {
+  fn new(name: String, mode: u32, mtime: Option<SystemTime>, keep_data: bool) -> Self {
+    Self {
+      name,
+      mode,
+      size: 0,
+      mtime,
+      data: keep_data.then(Vec::new),
+      truncated: false,
+      hasher: Sha256::new(),
+    }
+  }
+
+  /// Add a chunk of the entry's data, holding it only while the listing's `budget` allows.
+  fn push_data(&mut self, chunk: &[u8], budget: &mut u64) {
+    self.size += chunk.len() as u64;
+    let Some(data) = &mut self.data else {
+      return;
+    };
+    self.hasher.update(chunk);
+    if self.truncated {
+      return;
+    }
+    if chunk.len() as u64 <= *budget {
+      *budget -= chunk.len() as u64;
+      data.extend_from_slice(chunk);
+      return;
+    }
+
+    // Past the budget only the start is kept, giving back what the rest held
+    *budget += data.len() as u64;
+    let header_rest = HEADER_LEN.saturating_sub(data.len()).min(chunk.len());
+    data.extend_from_slice(&chunk[..header_rest]);
+    data.truncate(HEADER_LEN);
+    data.shrink_to_fit();
+    *budget = budget.saturating_sub(data.len() as u64);
+    self.truncated = true;
+  }
+
+  fn content_hash(&self) -> ContentHash {
+    self.hasher.clone().finalize().into()
+  }
+}
+
+/// Writes a decompressed stream into its listed entry.
+struct ListedData<'e> {
+  entry: &'e mut ListedEntry,
+  budget: u64,
+}
+
+impl Write for ListedData<'_> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.entry.push_data(buf, &mut self.budget);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Preview what [recursive_file_extract](crate::recursive_file_extract) would unpack from the
+/// archive at `path` to `out_path`. Nested archives are read into memory and previewed in turn,
+/// so nothing is written. The [limits](RZipExtractConfig::limits) apply as they do when
+/// unpacking, counting towards the config's run usage.
+pub fn preview_recursive_extract(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<ArchivePreview, RZipError> {
+  // Multi-volume archives are read starting from their first volume, as they are when unpacking
+  let volume_set = match config.in_memory.get(path) {
+    Some(_) => None,
+    None => VolumeSet::find(path)?,
+  };
+  let first_volume = volume_set
+    .as_ref()
+    .map_or(path, |volume_set| volume_set.first());
+  let format = ArchiveFormat::detect(first_volume)?.ok_or(RZipError::RuntimeError(format!(
+    "Didn't recognize archive format of {}",
+    first_volume.display()
+  )))?;
+  let listed = unpack::open_archive(first_volume, config)
+    .and_then(|reader| {
+      let limits = LimitTracker::new(path, config)?;
+      let listed = list_archive(reader, format, &limits, config)?;
+      limits.finish();
+      Ok(listed)
+    })
+    .map_err(|e| match e {
+      RZipProcessingError::NoMatchingPassword => RZipError::Encrypted(path.to_path_buf()),
+      RZipProcessingError::LimitExceeded(violation) => {
+        RZipError::LimitExceeded(path.to_path_buf(), violation)
+      }
+      e => RZipError::ProcessingErrors(vec![e]),
+    })?;
+
+  let modified = config.in_memory.modified(path)?;
+  let mut ancestors = vec![(crate::content_hash(path)?, path.to_path_buf())];
+  let preview = preview_nested(
+    path,
+    format,
+    listed,
+    out_path,
+    modified,
+    config,
+    &mut ancestors,
+  )?;
+
+  // A single decompressed file has nothing to flatten
+  Ok(match config.layout {
+    OutputLayout::Flat if !format.is_compressed_stream() => flatten_preview(preview),
+    _ => preview,
+  })
+}
+
+/// Preview an archive modified at `modified` from its `listed` entries. `ancestors` holds the hash
+/// and path of each archive on the way down to this one, including itself.
+fn preview_nested(
+  path: &Path,
+  format: ArchiveFormat,
+  listed: Vec<ListedEntry>,
+  out_path: &Path,
+  modified: SystemTime,
+  config: &RZipExtractConfig,
+  ancestors: &mut Vec<(ContentHash, PathBuf)>,
+) -> Result<ArchivePreview, RZipError> {
+  let mut preview = ArchivePreview {
+    archive_path: path.to_path_buf(),
+    out_path: out_path.to_path_buf(),
+    entries: vec![],
+    warnings: vec![],
+    deleted: vec![],
+    overwritten: vec![],
+  };
+  let Some(out_path) = crate::resolve_output_conflict(out_path, || Ok(modified), config)? else {
+    preview.warnings.push(RZipWarning::OutputExists(
+      path.to_path_buf(),
+      out_path.to_path_buf(),
+    ));
+    return Ok(preview);
+  };
+  preview.out_path = out_path.clone();
+
+  // Only the outermost archive is on disk, along with any other volumes of it
+  preview.deleted = match (config.delete_after_extracting, ancestors.len()) {
+    (false, _) => vec![],
+    (true, 1) => volume::archive_files(path)?,
+    (true, _) => vec![path.to_path_buf()],
+  };
+
+  // A compressed file holds a single file, which is unpacked to `out_path` itself
+  let mut files = vec![];
+  if format.is_compressed_stream() {
+    if out_path.symlink_metadata().is_ok() {
+      preview.overwritten.push(out_path.clone());
+    }
+    files.extend(listed.into_iter().map(|entry| (out_path.clone(), entry)));
+  } else {
+    let mut listed = listed;
+    if config.collapse_single_root && out_path.symlink_metadata().is_err() {
+      collapse_single_root(&mut listed);
+    }
+    for entry in listed {
+      let Some(entry_path) = policy::enclosed_path(&out_path, &entry.name) else {
+        match config.unsafe_paths {
+          UnsafePathPolicy::Reject => {
+            return Err(RZipError::UnsafePath(path.to_path_buf(), entry.name))
+          }
+          UnsafePathPolicy::Skip => {
+            let warning = RZipWarning::UnsafePath(path.to_path_buf(), entry.name);
+            preview.warnings.push(warning);
+            continue;
+          }
+        }
+      };
+      let file_type = entry.mode & S_IFMT;
+      match (
+        SpecialEntryKind::from_mode(file_type),
+        config.special_entries,
+      ) {
+        (Some(kind), SpecialEntryPolicy::Reject) => {
+          return Err(RZipError::SpecialEntry(
+            path.to_path_buf(),
+            entry.name,
+            kind,
+          ))
+        }
+        (Some(kind), SpecialEntryPolicy::Skip) => {
+          let warning = RZipWarning::SpecialEntry(path.to_path_buf(), entry.name, kind);
+          preview.warnings.push(warning);
+        }
+        _ if file_type == S_IFDIR => preview.entries.push(PreviewEntry {
+          path: entry_path,
+          kind: PreviewKind::Directory,
+        }),
+        _ => files.push((entry_path, entry)),
+      }
+    }
+  }
+
+  // Files already there are replaced, kept or renamed around as they are when unpacked
+  let guard = EntryGuard::new(path, &out_path, config);
+  let mut resolved = vec![];
+  for (entry_path, entry) in files {
+    if format.is_compressed_stream() {
+      resolved.push((entry_path, entry));
+      continue;
+    }
+    let metadata = EntryMetadata {
+      mtime: entry
+        .mtime
+        .filter(|_| config.timestamps == TimestampPolicy::Preserve),
+      ..Default::default()
+    };
+    let Some(resolved_path) = guard.resolve_conflict(&entry.name, entry_path, &metadata, false)?
+    else {
+      continue;
+    };
+    if resolved_path.symlink_metadata().is_ok() {
+      preview.overwritten.push(resolved_path.clone());
+    }
+    resolved.push((resolved_path, entry));
+  }
+  preview.warnings.extend(guard.finish()?);
+
+  // Nested archives that would share an output are told apart as they are when unpacked
+  let mut archives = vec![];
+  for (entry_path, entry) in resolved {
+    let nested_format = entry.data.as_ref().and_then(|data| {
+      let file_name = entry_path.file_name()?.to_str()?;
+      ArchiveFormat::detect_bytes(file_name, data)
+    });
+    match nested_format {
+      Some(nested_format) => archives.push((entry_path, entry, nested_format)),
+      None => preview.entries.push(PreviewEntry {
+        path: entry_path,
+        kind: PreviewKind::File(entry.size),
+      }),
+    }
+  }
+  let archive_paths: Vec<_> = archives.iter().map(|(path, ..)| path.clone()).collect();
+  let out_paths = archives
+    .iter()
+    .map(|(entry_path, entry, _)| nested_out_path(entry_path, entry, config))
+    .collect::<Result<Vec<_>, _>>()?;
+  let out_paths = crate::separate_out_paths(&archive_paths, out_paths);
+
+  for ((entry_path, entry, nested_format), nested_out_path) in archives.into_iter().zip(out_paths) {
+    let kind = preview_nested_archive(
+      &entry_path,
+      nested_format,
+      entry,
+      &nested_out_path,
+      config,
+      ancestors,
+      &mut preview.warnings,
+    )?;
+    preview.entries.push(PreviewEntry {
+      path: entry_path,
+      kind,
+    });
+  }
+
+  Ok(preview)
+}
+
+/// Preview the archive nested at `path`, read from its listed `entry`. Archives that would be left
+/// packed are previewed as files, adding the reason to `warnings`.
+fn preview_nested_archive(
+  path: &Path,
+  format: ArchiveFormat,
+  entry: ListedEntry,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+  ancestors: &mut Vec<(ContentHash, PathBuf)>,
+  warnings: &mut Vec<RZipWarning>,
+) -> Result<PreviewKind, RZipError> {
+  // Stop at the maximum depth, and at archives that contain themselves
+  if let Some(max_depth) = config.max_depth {
+    if ancestors.len() > max_depth {
+      warnings.push(RZipWarning::MaxDepth(path.to_path_buf(), max_depth));
+      return Ok(PreviewKind::File(entry.size));
+    }
+  }
+  let size = entry.size;
+  let hash = entry.content_hash();
+  if let Some((_, ancestor)) = ancestors
+    .iter()
+    .find(|(ancestor_hash, _)| *ancestor_hash == hash)
+  {
+    warnings.push(RZipWarning::ArchiveCycle(
+      path.to_path_buf(),
+      ancestor.clone(),
+    ));
+    return Ok(PreviewKind::File(entry.size));
+  }
+  if entry.truncated {
+    let reason = format!("Too big to preview in memory, at {size} bytes");
+    return Ok(PreviewKind::Unlisted(size, reason));
+  }
+
+  // Limits are reported as they are when unpacking, rather than as an archive that can't be read
+  let data = entry.data.unwrap_or_default();
+  let limits = LimitTracker::with_packed_bytes(size, config);
+  let listed = match list_archive(Box::new(Cursor::new(data)), format, &limits, config) {
+    Ok(listed) => listed,
+    Err(RZipProcessingError::LimitExceeded(violation)) => {
+      return Err(RZipError::LimitExceeded(path.to_path_buf(), violation))
+    }
+    Err(e) => return Ok(PreviewKind::Unlisted(size, e.to_string())),
+  };
+  limits.finish();
+  let modified = match (config.timestamps, entry.mtime) {
+    (TimestampPolicy::Preserve, Some(mtime)) => mtime,
+    _ => SystemTime::now(),
+  };
+  ancestors.push((hash, path.to_path_buf()));
+  let preview = preview_nested(path, format, listed, out_path, modified, config, ancestors);
+  ancestors.pop();
+
+  Ok(PreviewKind::Archive(size, Box::new(preview?)))
+}
+
+/// Where a nested archive would be unpacked to, naming it from its listed entry rather than a
+/// file on disk.
+fn nested_out_path(
+  entry_path: &Path,
+  entry: &ListedEntry,
+  config: &RZipExtractConfig,
+) -> Result<PathBuf, RZipError> {
+  let output_name = config.name_template.render_with(
+    entry_path,
+    || match (config.timestamps, entry.mtime) {
+      (TimestampPolicy::Preserve, Some(mtime)) => Ok(mtime),
+      _ => Ok(SystemTime::now()),
+    },
+    || Ok(entry.content_hash()),
+  )?;
+  crate::out_path_with_name(entry_path, &output_name, config)
+}
+
+/// Read the listing of an archive from `reader`, counting what it holds against `limits` and
+/// keeping the data of entries that look like archives. A compressed file lists as the single
+/// file it holds.
+fn list_archive(
+  mut reader: Box<dyn ArchiveReader>,
+  format: ArchiveFormat,
+  limits: &LimitTracker,
+  config: &RZipExtractConfig,
+) -> Result<Vec<ListedEntry>, RZipProcessingError> {
+  use compress_tools::{ArchiveContents, ArchiveIterator};
+
+  let mut budget = config
+    .max_in_memory_bytes
+    .map_or(MAX_PREVIEW_BYTES, |max_bytes| {
+      max_bytes.max(MAX_PREVIEW_BYTES)
+    });
+  if format.is_compressed_stream() {
+    limits.add_entry(None)?;
+    let mut entry = ListedEntry::new(String::new(), S_IFREG, None, true);
+    let data = ListedData {
+      entry: &mut entry,
+      budget,
+    };
+    compress_tools::uncompress_data(&mut reader, limits.writer(data))?;
+    return Ok(vec![entry]);
+  }
+
+  let mut entries: Vec<ListedEntry> = vec![];
+  let mut archive = ArchiveIterator::from_read(reader)?;
+  for contents in &mut archive {
+    match contents {
+      ArchiveContents::StartOfEntry(name, stat) => {
+        // `st_mode` and the times are narrower on some platforms
+        #[allow(clippy::unnecessary_cast)]
+        let (mode, size, mtime) = (
+          stat.st_mode as u32,
+          stat.st_size as u64,
+          stat.st_mtime as i64,
+        );
+        limits.add_entry(Some(size))?;
+        let is_archive_name = ArchiveFormat::from_file_name(&name).is_some();
+        let keep_data = mode & S_IFMT == S_IFREG && is_archive_name;
+        entries.push(ListedEntry::new(
+          name,
+          mode,
+          unpack::unix_time(mtime, 0),
+          keep_data,
+        ));
+      }
+      ArchiveContents::DataChunk(chunk) => {
+        let Some(entry) = entries.last_mut() else {
+          continue;
+        };
+        // Files that aren't named as archives are kept if they start like one
+        if entry.size == 0 && ArchiveFormat::from_magic(&chunk).is_some() {
+          entry.data.get_or_insert_with(Vec::new);
+        }
+        limits.add_bytes(chunk.len() as u64)?;
+        entry.push_data(&chunk, &mut budget);
+      }
+      ArchiveContents::EndOfEntry => (),
+      ArchiveContents::Err(e) => return Err(unpack::compress_tools_error(e)),
+    }
+  }
+  archive.close().map_err(unpack::compress_tools_error)?;
+
+  Ok(entries)
+}
+
+/// Move the entries of an archive up a level if they're all in a single directory, as unpacking
+/// with [collapse_single_root](RZipExtractConfig::collapse_single_root) would.
+fn collapse_single_root(listed: &mut Vec<ListedEntry>) {
+  fn root_of(entry: &ListedEntry) -> Option<Component<'_>> {
+    Path::new(&entry.name).components().next()
+  }
+  let is_root = |entry: &ListedEntry| Path::new(&entry.name).components().count() == 1;
+  let Some(root) = listed.first().and_then(root_of) else {
+    return;
+  };
+  let single_root = listed.iter().all(|entry| root_of(entry) == Some(root));
+  let root_is_dir = listed
+    .iter()
+    .all(|entry| !is_root(entry) || entry.mode & S_IFMT == S_IFDIR);
+  if !(single_root && root_is_dir) {
+    return;
+  }
+
+  listed.retain(|entry| !is_root(entry));
+  for entry in listed {
+    let relative_path: PathBuf = Path::new(&entry.name).components().skip(1).collect();
+    entry.name = relative_path.to_string_lossy().into_owned();
+  }
+}
+
+/// Lay out a preview as the flat layout would, with every file that isn't an unpacked archive
+/// moved into the top output directory in path order.
+fn flatten_preview(preview: ArchivePreview) -> ArchivePreview {
+  fn collect_files(
+    preview: ArchivePreview,
+    files: &mut Vec<(PathBuf, PreviewKind)>,
+    flat_preview: &mut ArchivePreview,
+  ) {
+    flat_preview.warnings.extend(preview.warnings);
+    flat_preview.overwritten.extend(preview.overwritten);
+    for entry in preview.entries {
+      match entry.kind {
+        PreviewKind::Directory | PreviewKind::Index => (),
+        PreviewKind::Archive(_, nested) => collect_files(*nested, files, flat_preview),
+        kind => files.push((entry.path, kind)),
+      }
+    }
+  }
+
+  let mut flat_preview = ArchivePreview {
+    archive_path: preview.archive_path.clone(),
+    out_path: preview.out_path.clone(),
+    entries: vec![],
+    warnings: vec![],
+    deleted: preview.deleted.clone(),
+    overwritten: vec![],
+  };
+  let mut files = vec![];
+  collect_files(preview, &mut files, &mut flat_preview);
+  files.sort_by(|(a, _), (b, _)| a.cmp(b));
+  let paths: Vec<_> = files.iter().map(|(path, _)| path.clone()).collect();
+  // What's already in the output keeps its name, unless the archive's files replace it
+  let kept = fs::read_dir(&flat_preview.out_path)
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| !paths.contains(path))
+    .collect();
+  let flat_paths = flatten::flat_paths(&flat_preview.out_path, &paths, kept);
+  flat_preview.entries = flat_paths
+    .into_iter()
+    .zip(files)
+    .map(|(path, (_, kind))| PreviewEntry { path, kind })
+    .collect();
+  flat_preview.entries.push(PreviewEntry {
+    path: flat_preview.out_path.join(flatten::INDEX_FILE_NAME),
+    kind: PreviewKind::Index,
+  });
+
+  flat_preview
+}
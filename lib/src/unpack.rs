@@ -1,101 +1,1391 @@
-use std::{fs::File, path::Path};
-
-use crate::error::{RZipError, RZipProcessingError};
-
-type UnpackStage = fn(&Path, &Path) -> Result<(), RZipProcessingError>;
-
-pub fn unpack_file(path: &Path, out_path: &Path) -> Result<RZipError, RZipError> {
-  // Get extension
-  // TODO - Handle .tar.gz
-  let ext = path
-    .extension()
-    .ok_or(RZipError::RuntimeError(format!(
-      "Failed to get extension for {}",
-      path.display()
-    )))?
-    .to_str()
-    .ok_or(RZipError::RuntimeError(format!(
-      "Failed to convert extension to str: {}",
-      path.display()
-    )))?;
-
-  let unpack_stages: Vec<UnpackStage> = match ext {
-    "zip" => vec![compress_tools_unpack],
-    "7z" => vec![compress_tools_unpack, seven_z_unpack],
-    "gz" | "tgz" => vec![compress_tools_unpack, flake2_unpack],
-    "xz" | "txz" => vec![compress_tools_unpack],
-    "tar" => vec![compress_tools_unpack, tar_unpack],
-    "rar" => vec![compress_tools_unpack],
-    _ => {
-      return Err(RZipError::RuntimeError(format!(
-        "Didn't recognize extension {}",
-        ext
-      )))
+use std::{
+  borrow::Cow,
+  ffi::OsString,
+  fs::{self, File},
+  io::{self, BufReader, Cursor, Read, Seek, Write},
+  iter,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use crate::{
+  error::{RZipError, RZipProcessingError, RZipWarning},
+  format::ArchiveFormat,
+  limits::LimitTracker,
+  policy::{self, EntryGuard, EntryMetadata, SpecialEntryAction, SpecialEntryKind},
+  volume::VolumeSet,
+  RZipExtractConfig,
+};
+
+/// An unpacking backend. A successful stage returns warnings about the entries it left out.
+type UnpackStage =
+  fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+
+/// File type bits of the `st_mode` libarchive reports for an entry.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+pub fn unpack_file(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipError> {
+  unpack_file_placing(path, out_path, config, &mut vec![])
+}
+
+/// Unpack a file as [unpack_file] does, adding each path that its output is moved to to `placed`.
+pub(crate) fn unpack_file_placing(
+  path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+  placed: &mut Vec<PathBuf>,
+) -> Result<Vec<RZipWarning>, RZipError> {
+  // Multi-volume archives are read starting from their first volume, whichever volume we were
+  // given. This also reports any missing volumes before a backend fails on them.
+  // Archives held in memory are never volumes, as those are written out to be found with the rest
+  let in_memory = config.in_memory.get(path);
+  let volume_set = match in_memory {
+    Some(_) => None,
+    None => VolumeSet::find(path)?,
+  };
+  let path = volume_set
+    .as_ref()
+    .map_or(path, |volume_set| volume_set.first());
+
+  // Detect the format from the file's contents
+  let format = match in_memory {
+    Some(archive) => {
+      let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+      ArchiveFormat::detect_bytes(&file_name, &archive.contents)
     }
+    None => ArchiveFormat::detect(path)?,
+  };
+  let format = format.ok_or(RZipError::RuntimeError(format!(
+    "Didn't recognize archive format of {}",
+    path.display()
+  )))?;
+
+  let unpack_stages: Vec<UnpackStage> = match format {
+    ArchiveFormat::Zip => vec![compress_tools_unpack, zip_unpack],
+    ArchiveFormat::SevenZ => vec![compress_tools_unpack, seven_z_unpack],
+    // Compressed tarballs are decompressed and unpacked in a single streaming pass
+    ArchiveFormat::TarGz => tarball_stages(flake2_unpack, config),
+    ArchiveFormat::TarXz => tarball_stages(xz_unpack, config),
+    ArchiveFormat::TarBz2 => tarball_stages(bzip2_unpack, config),
+    ArchiveFormat::TarZst => tarball_stages(zstd_unpack, config),
+    // Other compressed files are decompressed to a single file at `out_path`. libarchive removes
+    // every layer of compression at once, so it only backs up the single-layer decoders.
+    ArchiveFormat::Gzip => vec![flake2_decompress, compress_tools_decompress],
+    ArchiveFormat::Xz => vec![xz_decompress, compress_tools_decompress],
+    ArchiveFormat::Bzip2 => vec![bzip2_decompress, compress_tools_decompress],
+    ArchiveFormat::Zstd => vec![zstd_decompress, compress_tools_decompress],
+    ArchiveFormat::Tar => tarball_stages(tar_unpack, config),
+    ArchiveFormat::Rar => vec![compress_tools_unpack],
+  };
+
+  // Each stage unpacks to a fresh staging path next to `out_path`, so that it never sees what an
+  // earlier stage left behind, and nothing watching `out_path` ever sees partial output. Only the
+  // output of the stage that succeeds is moved into place.
+  let out_path_existed = fs::symlink_metadata(out_path).is_ok();
+  if let Some(parent) = out_path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+
+  // Nested archives are only held in memory when unpacking to a fresh output, so that conflicts
+  // with what's already there are resolved as usual
+  let stage_config = if out_path_existed && config.max_in_memory_bytes.is_some() {
+    Cow::Owned(RZipExtractConfig {
+      max_in_memory_bytes: None,
+      ..config.clone()
+    })
+  } else {
+    Cow::Borrowed(config)
   };
 
   let mut errors = vec![];
   for stage_fn in unpack_stages {
-    let res = stage_fn(path, out_path);
-    match res {
-      Ok(_) => return Ok(RZipError::ProcessingErrors(errors)),
+    // Another backend would only run into the same limit or entry, so those stop here, removing
+    // what was unpacked rather than leaving a partial tree
+    let archive_path = path.to_path_buf();
+    let staging_path = staging_path(out_path);
+    let error = match stage_fn(path, &staging_path, &stage_config) {
+      Ok(mut warnings) => {
+        let placed = place_staged_output(
+          path,
+          &staging_path,
+          out_path,
+          out_path_existed,
+          config,
+          placed,
+        );
+        discard_staged_output(&staging_path, config)?;
+        warnings.extend(placed?);
+        return Ok(warnings);
+      }
+      Err(RZipProcessingError::LimitExceeded(violation)) => {
+        RZipError::LimitExceeded(archive_path, violation)
+      }
+      Err(RZipProcessingError::UnsafePath(entry_name)) => {
+        RZipError::UnsafePath(archive_path, entry_name)
+      }
+      Err(RZipProcessingError::SpecialEntry(entry_name, kind)) => {
+        RZipError::SpecialEntry(archive_path, entry_name, kind)
+      }
       Err(e) => {
         errors.push(e);
+        discard_staged_output(&staging_path, config)?;
+        continue;
       }
-    }
+    };
+    discard_staged_output(&staging_path, config)?;
+    return Err(error);
   }
 
-  // If we reach the very end instead of a stage returning Ok then we have failed.
+  // If we reach the very end instead of a stage returning Ok then we have failed. Encrypted
+  // archives that none of the passwords opened get reported as such.
+  if errors
+    .iter()
+    .any(|e| matches!(e, RZipProcessingError::NoMatchingPassword))
+  {
+    return Err(RZipError::Encrypted(path.to_path_buf()));
+  }
   Err(RZipError::ProcessingErrors(errors))
 }
 
+/// The stages for a tarball: libarchive, backed up by `tar_stage` for the links that libarchive's
+/// entry iterator leaves to it. The iterator doesn't give extended attributes either, so only
+/// `tar_stage` runs when they're restored.
+fn tarball_stages(tar_stage: UnpackStage, config: &RZipExtractConfig) -> Vec<UnpackStage> {
+  match config.preserve_xattrs {
+    true => vec![tar_stage],
+    false => vec![compress_tools_unpack, tar_stage],
+  }
+}
+
 /// Unpack an archive using the [compress_tools] backend.
 ///
 /// Documentation: https://github.com/OSSystems/compress-tools-rs/.
 pub fn compress_tools_unpack(
   archive_path: &Path,
   out_path: &Path,
-) -> Result<(), RZipProcessingError> {
-  let archive_file: File = File::open(archive_path)?;
-  compress_tools::uncompress_archive(archive_file, out_path, compress_tools::Ownership::Ignore)
-    .map_err(|e| e.into())
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use compress_tools::{ArchiveContents, ArchiveIterator};
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  let mut archive = ArchiveIterator::from_read(open_archive(archive_path, config)?)?;
+  let mut out_file = None;
+  for contents in &mut archive {
+    match contents {
+      ArchiveContents::StartOfEntry(entry_name, stat) => {
+        limits.add_entry(Some(stat.st_size as u64))?;
+        // The data of a skipped entry is still read, but goes nowhere
+        let Some(entry_path) = guard.entry_path(&entry_name)? else {
+          continue;
+        };
+
+        // `st_mode`, `st_rdev`, the owners and the times are narrower on some platforms
+        #[allow(clippy::unnecessary_cast)]
+        let (st_mode, st_rdev) = (stat.st_mode as u32, stat.st_rdev as u64);
+        // libarchive reports an access time of 0 when the archive has none
+        #[allow(clippy::unnecessary_cast)]
+        let metadata = EntryMetadata {
+          mode: Some(st_mode),
+          uid: Some(stat.st_uid as u32),
+          gid: Some(stat.st_gid as u32),
+          mtime: unix_time(stat.st_mtime as i64, 0),
+          atime: unix_time(stat.st_atime as i64, 0).filter(|_| stat.st_atime != 0),
+          ..Default::default()
+        };
+        let is_dir = st_mode & S_IFMT == S_IFDIR;
+        let Some(entry_path) =
+          guard.resolve_conflict(&entry_name, entry_path, &metadata, is_dir)?
+        else {
+          continue;
+        };
+
+        match st_mode & S_IFMT {
+          S_IFDIR => {
+            create_entry_dir(&entry_path)?;
+            guard.set_metadata(&entry_path, metadata)?;
+          }
+          S_IFREG => out_file = Some((EntryWriter::create(&entry_path, &guard)?, metadata)),
+          // The iterator doesn't give link targets, so the guard leaves links to other backends.
+          // Hard links are the entries it reports without a file type.
+          file_type => {
+            let kind = match file_type {
+              0 => SpecialEntryKind::Hardlink,
+              _ => SpecialEntryKind::from_mode(file_type)
+                .ok_or_else(|| RZipProcessingError::UnsupportedEntry(entry_name.clone()))?,
+            };
+            let action = guard.special_entry(&entry_name, &entry_path, kind, None)?;
+            unpack_special_entry(action, kind, &entry_path, metadata, &limits, &guard, |_| {
+              create_special_file(kind, &entry_path, Some(st_rdev))
+            })?;
+          }
+        }
+      }
+      ArchiveContents::DataChunk(chunk) => {
+        limits.add_bytes(chunk.len() as u64)?;
+        if let Some((file, ..)) = &mut out_file {
+          file.write_all(&chunk)?;
+        }
+      }
+      ArchiveContents::EndOfEntry => {
+        if let Some((file, metadata)) = out_file.take() {
+          file.finish(metadata, &guard)?;
+        }
+      }
+      ArchiveContents::Err(e) => return Err(compress_tools_error(e)),
+    }
+  }
+  archive.close().map_err(compress_tools_error)?;
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
 }
 
 /// Unpack an archive using the [sevenz_rust] backend.
 ///
 /// Documentation: https://github.com/dyz1990/sevenz-rust
-pub fn seven_z_unpack(archive_path: &Path, out_path: &Path) -> Result<(), RZipProcessingError> {
-  sevenz_rust::decompress_file(archive_path, out_path).map_err(|e| e.into())
+pub fn seven_z_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use sevenz_rust::{Error, Password};
+
+  // Each attempt unpacks to a fresh directory next to `out_path`, so that a wrong password never
+  // leaves files behind for the next attempt to run into. A wrong password surfaces as any number
+  // of decoding errors, so every failure other than a limit or a rejected entry moves on to the
+  // next password.
+  let passwords = config
+    .passwords
+    .iter()
+    .map(|password| Password::from(password.as_str()));
+  for (attempt, password) in iter::once(Password::empty()).chain(passwords).enumerate() {
+    let attempt_path = staging_path(out_path);
+    let mut warnings = match seven_z_extract(archive_path, &attempt_path, config, password) {
+      Ok(warnings) => warnings,
+      Err(e) => {
+        discard_staged_output(&attempt_path, config)?;
+        match e {
+          RZipProcessingError::SevenZ(Error::PasswordRequired) if attempt == 0 => continue,
+          e if attempt == 0 => return Err(e),
+          e @ (RZipProcessingError::LimitExceeded(_)
+          | RZipProcessingError::UnsafePath(_)
+          | RZipProcessingError::SpecialEntry(..)) => return Err(e),
+          _ => continue,
+        }
+      }
+    };
+    let placed = place_attempt(archive_path, &attempt_path, out_path, config);
+    discard_staged_output(&attempt_path, config)?;
+    warnings.extend(placed?);
+    return Ok(warnings);
+  }
+
+  Err(RZipProcessingError::NoMatchingPassword)
+}
+
+/// Unpack an archive using the [zip] backend. Encrypted entries are decrypted with the first
+/// configured password that opens them.
+///
+/// Documentation: https://docs.rs/zip/latest/zip/
+pub fn zip_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use zip::{result::ZipError, ZipArchive};
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  let mut archive = ZipArchive::new(open_archive(archive_path, config)?)?;
+  for index in 0..archive.len() {
+    let (entry_name, is_dir, encrypted, size, metadata) = {
+      let entry = archive.by_index_raw(index)?;
+      // Zip archives don't record owners
+      let (mtime, atime) = zip_entry_times(&entry);
+      let metadata = EntryMetadata {
+        mode: entry.unix_mode(),
+        mtime,
+        atime,
+        ..Default::default()
+      };
+      (
+        entry.name().to_string(),
+        entry.is_dir(),
+        entry.encrypted(),
+        entry.size(),
+        metadata,
+      )
+    };
+    limits.add_entry(Some(size))?;
+    let Some(entry_path) = guard.entry_path(&entry_name)? else {
+      continue;
+    };
+    let Some(entry_path) = guard.resolve_conflict(&entry_name, entry_path, &metadata, is_dir)?
+    else {
+      continue;
+    };
+
+    let mut out_file = None;
+    if is_dir {
+      create_entry_dir(&entry_path)?;
+    } else if let Some(kind) = metadata.mode.and_then(SpecialEntryKind::from_mode) {
+      // A symlink's target is stored as its contents
+      let target = match kind {
+        SpecialEntryKind::Symlink => {
+          Some(read_link_target(&mut archive.by_index(index)?, &limits)?)
+        }
+        _ => None,
+      };
+      let action = guard.special_entry(&entry_name, &entry_path, kind, target.as_deref())?;
+      unpack_special_entry(
+        action,
+        kind,
+        &entry_path,
+        metadata,
+        &limits,
+        &guard,
+        |_| match &target {
+          Some(target) => Ok(create_symlink(target, &entry_path)?),
+          None => create_special_file(kind, &entry_path, None),
+        },
+      )?;
+      continue;
+    } else if !encrypted {
+      let entry = &mut archive.by_index(index)?;
+      out_file = Some(write_file_entry(entry, &entry_path, &limits, &guard)?);
+    } else {
+      for password in &config.passwords {
+        let mut entry = match archive.by_index_decrypt(index, password.as_bytes()) {
+          Ok(entry) => entry,
+          Err(ZipError::InvalidPassword) => continue,
+          Err(e) => return Err(e.into()),
+        };
+
+        // ZipCrypto only checks one byte of the password up front, so some wrong passwords are
+        // only caught by the entry's checksum
+        match write_file_entry(&mut entry, &entry_path, &limits, &guard) {
+          Ok(entry_file) => {
+            out_file = Some(entry_file);
+            break;
+          }
+          Err(RZipProcessingError::Io(e)) if e.kind() == io::ErrorKind::InvalidData => continue,
+          Err(e) => return Err(e),
+        }
+      }
+
+      if out_file.is_none() {
+        return Err(RZipProcessingError::NoMatchingPassword);
+      }
+    }
+
+    match out_file {
+      Some(out_file) => out_file.finish(metadata, &guard)?,
+      None => guard.set_metadata(&entry_path, metadata)?,
+    }
+  }
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
 }
 
 /// Unpack an archive using the [flate2] backend.
 ///
 /// Documentation: https://docs.rs/flate2/latest/flate2/
-pub fn flake2_unpack(archive_path: &Path, out_path: &Path) -> Result<(), RZipProcessingError> {
+pub fn flake2_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
   use flate2::read::GzDecoder;
-  use tar::Archive;
 
-  let tar_gz = File::open(archive_path)?;
+  let limits = LimitTracker::new(archive_path, config)?;
+  let tar_gz = open_archive(archive_path, config)?;
   let tar = GzDecoder::new(tar_gz);
-  let mut archive = Archive::new(tar);
-  archive.unpack(out_path)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  unpack_tar(limits.reader(tar), out_path, &limits, &guard)?;
 
-  Ok(())
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
+}
+
+/// Unpack an archive using the [bzip2] backend.
+///
+/// Documentation: https://docs.rs/bzip2/latest/bzip2/
+pub fn bzip2_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use bzip2::read::BzDecoder;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let tar_bz2 = open_archive(archive_path, config)?;
+  let tar = BzDecoder::new(tar_bz2);
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  unpack_tar(limits.reader(tar), out_path, &limits, &guard)?;
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
+}
+
+/// Unpack an archive using the [ruzstd] backend.
+///
+/// Documentation: https://docs.rs/ruzstd/latest/ruzstd/
+pub fn zstd_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use ruzstd::decoding::StreamingDecoder;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let tar_zst = open_archive(archive_path, config)?;
+  let tar = StreamingDecoder::new(tar_zst)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  unpack_tar(limits.reader(tar), out_path, &limits, &guard)?;
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
+}
+
+/// Unpack an archive using the [lzma_rust2] backend.
+///
+/// Documentation: https://docs.rs/lzma-rust2/latest/lzma_rust2/
+pub fn xz_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use lzma_rust2::XzReader;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let tar_xz = BufReader::new(open_archive(archive_path, config)?);
+  let tar = XzReader::new(tar_xz, true);
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  unpack_tar(limits.reader(tar), out_path, &limits, &guard)?;
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
 }
 
 /// Unpack an archive using the [tar] backend.
 ///
 /// Documentation: https://docs.rs/tar/latest/tar/
-pub fn tar_unpack(archive_path: &Path, out_path: &Path) -> Result<(), RZipProcessingError> {
-  use tar::Archive;
+pub fn tar_unpack(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  let limits = LimitTracker::new(archive_path, config)?;
+  let tar = open_archive(archive_path, config)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  unpack_tar(limits.reader(tar), out_path, &limits, &guard)?;
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
+}
+
+/// Decompress a single compressed file to `out_path` using the [compress_tools] backend.
+///
+/// Documentation: https://github.com/OSSystems/compress-tools-rs/.
+pub fn compress_tools_decompress(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  let limits = LimitTracker::new(archive_path, config)?;
+  limits.add_entry(None)?;
+  let archive_file = open_archive(archive_path, config)?;
+  let out_file = create_out_file(out_path)?;
+  compress_tools::uncompress_data(archive_file, limits.writer(out_file))?;
+
+  limits.finish();
+  Ok(vec![])
+}
+
+/// Decompress a single gzip file to `out_path` using the [flate2] backend.
+///
+/// Documentation: https://docs.rs/flate2/latest/flate2/
+pub fn flake2_decompress(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use flate2::read::MultiGzDecoder;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let gz = open_archive(archive_path, config)?;
+  let mut decoder = MultiGzDecoder::new(gz);
+  limits.add_entry(None)?;
+  write_entry(&mut decoder, out_path, &limits)?;
+
+  limits.finish();
+  Ok(vec![])
+}
+
+/// Decompress a single bzip2 file to `out_path` using the [bzip2] backend.
+///
+/// Documentation: https://docs.rs/bzip2/latest/bzip2/
+pub fn bzip2_decompress(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use bzip2::read::MultiBzDecoder;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let bz2 = open_archive(archive_path, config)?;
+  let mut decoder = MultiBzDecoder::new(bz2);
+  limits.add_entry(None)?;
+  write_entry(&mut decoder, out_path, &limits)?;
+
+  limits.finish();
+  Ok(vec![])
+}
+
+/// Decompress a single xz file to `out_path` using the [lzma_rust2] backend.
+///
+/// Documentation: https://docs.rs/lzma-rust2/latest/lzma_rust2/
+pub fn xz_decompress(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use lzma_rust2::XzReader;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let xz = BufReader::new(open_archive(archive_path, config)?);
+  let mut decoder = XzReader::new(xz, true);
+  limits.add_entry(None)?;
+  write_entry(&mut decoder, out_path, &limits)?;
+
+  limits.finish();
+  Ok(vec![])
+}
+
+/// Decompress a single Zstandard file to `out_path` using the [ruzstd] backend.
+///
+/// Documentation: https://docs.rs/ruzstd/latest/ruzstd/
+pub fn zstd_decompress(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  use ruzstd::decoding::StreamingDecoder;
+
+  let limits = LimitTracker::new(archive_path, config)?;
+  let zst = open_archive(archive_path, config)?;
+  let mut decoder = StreamingDecoder::new(zst)?;
+  limits.add_entry(None)?;
+  write_entry(&mut decoder, out_path, &limits)?;
+
+  limits.finish();
+  Ok(vec![])
+}
+
+/// A readable and seekable archive source.
+pub trait ArchiveReader: Read + Seek {}
+
+impl<T: Read + Seek> ArchiveReader for T {}
+
+/// Open an archive for reading, from memory if it's held there. The first volume of a multi-volume
+/// archive opens the concatenation of every volume in its set.
+pub(crate) fn open_archive(
+  archive_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Box<dyn ArchiveReader>, RZipProcessingError> {
+  if let Some(archive) = config.in_memory.get(archive_path) {
+    return Ok(Box::new(Cursor::new(archive.contents)));
+  }
+  let volume_set = VolumeSet::find(archive_path).map_err(|e| io::Error::other(e.to_string()))?;
+
+  Ok(match volume_set {
+    Some(volume_set) if volume_set.first() == archive_path => Box::new(volume_set.open()?),
+    _ => Box::new(File::open(archive_path)?),
+  })
+}
+
+/// Create the file a single compressed file is decompressed into, along with its parent
+/// directories.
+fn create_out_file(out_path: &Path) -> io::Result<File> {
+  prepare_entry_path(out_path)?;
+  File::create(out_path)
+}
+
+/// Make way for an entry at `entry_path` by creating its parent directories, and removing whatever
+/// is already there so that nothing is written through it.
+fn prepare_entry_path(entry_path: &Path) -> io::Result<()> {
+  if let Some(parent) = entry_path.parent() {
+    create_entry_dir(parent)?;
+  }
+
+  remove_out_path(entry_path)
+}
+
+/// Create the directory at `dir_path` along with its parents, replacing a file in the way of any of
+/// them. Only a file the conflict policy let the entry replace can be there.
+fn create_entry_dir(dir_path: &Path) -> io::Result<()> {
+  let file_in_the_way = dir_path
+    .ancestors()
+    .find(|path| fs::symlink_metadata(path).is_ok())
+    .filter(|path| !path.is_dir());
+  if let Some(file_path) = file_in_the_way {
+    fs::remove_file(file_path)?;
+  }
+
+  fs::create_dir_all(dir_path)
+}
+
+/// Unpack a link or special file to `entry_path` as the guard decided. `create` makes the entry as
+/// it is in the archive, given the path a link points to.
+fn unpack_special_entry(
+  action: SpecialEntryAction,
+  kind: SpecialEntryKind,
+  entry_path: &Path,
+  metadata: EntryMetadata,
+  limits: &LimitTracker,
+  guard: &EntryGuard,
+  create: impl FnOnce(Option<PathBuf>) -> Result<(), RZipProcessingError>,
+) -> Result<(), RZipProcessingError> {
+  match action {
+    // A hard link shares the metadata of the file it links to
+    SpecialEntryAction::Create(link_path) => {
+      prepare_entry_path(entry_path)?;
+      create(link_path)?;
+      if kind != SpecialEntryKind::Hardlink {
+        guard.set_metadata(entry_path, metadata)?;
+      }
+      return Ok(());
+    }
+    // Copies count towards the limits like any other unpacked data
+    SpecialEntryAction::Materialize(Some(link_path)) => {
+      write_entry(&mut File::open(link_path)?, entry_path, limits)?
+    }
+    SpecialEntryAction::Materialize(None) => write_entry(&mut io::empty(), entry_path, limits)?,
+    SpecialEntryAction::Skip => return Ok(()),
+  }
+
+  // A link's mode says nothing about the file it's materialized as
+  let metadata = EntryMetadata {
+    mode: None,
+    ..metadata
+  };
+  Ok(guard.set_metadata(entry_path, metadata)?)
+}
+
+/// Read the target of a link stored as an entry's contents.
+fn read_link_target(entry: &mut dyn Read, limits: &LimitTracker) -> io::Result<String> {
+  let mut target = vec![];
+  limits.reader(entry).read_to_end(&mut target)?;
+
+  Ok(String::from_utf8_lossy(&target).into_owned())
+}
+
+#[cfg(unix)]
+pub(crate) fn create_symlink(target: &str, entry_path: &Path) -> io::Result<()> {
+  std::os::unix::fs::symlink(target, entry_path)
+}
+
+#[cfg(windows)]
+pub(crate) fn create_symlink(target: &str, entry_path: &Path) -> io::Result<()> {
+  std::os::windows::fs::symlink_file(target, entry_path)
+}
+
+/// Create a FIFO or device node. Device nodes need their device number, and usually root.
+#[cfg(unix)]
+fn create_special_file(
+  kind: SpecialEntryKind,
+  entry_path: &Path,
+  device: Option<u64>,
+) -> Result<(), RZipProcessingError> {
+  use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+  let unsupported = || RZipProcessingError::UnsupportedEntry(entry_path.display().to_string());
+  let (file_type, device) = match (kind, device) {
+    (SpecialEntryKind::Fifo, _) => (libc::S_IFIFO, 0),
+    (SpecialEntryKind::CharDevice, Some(device)) => (libc::S_IFCHR, device),
+    (SpecialEntryKind::BlockDevice, Some(device)) => (libc::S_IFBLK, device),
+    _ => return Err(unsupported()),
+  };
+  let path = CString::new(entry_path.as_os_str().as_bytes()).map_err(|_| unsupported())?;
+  // SAFETY: `path` is a valid NUL terminated string that outlives the call
+  #[allow(clippy::unnecessary_cast)]
+  let res = unsafe { libc::mknod(path.as_ptr(), file_type | 0o644, device as libc::dev_t) };
+  if res != 0 {
+    return Err(io::Error::last_os_error().into());
+  }
+
+  Ok(())
+}
 
-  let tar = File::open(archive_path).unwrap();
-  let mut archive: Archive<File> = Archive::new(tar);
-  archive.unpack(out_path)?;
+#[cfg(not(unix))]
+fn create_special_file(
+  _kind: SpecialEntryKind,
+  entry_path: &Path,
+  _device: Option<u64>,
+) -> Result<(), RZipProcessingError> {
+  Err(RZipProcessingError::UnsupportedEntry(
+    entry_path.display().to_string(),
+  ))
+}
+
+/// A device number from its major and minor numbers.
+#[cfg(unix)]
+fn device_number(major: u32, minor: u32) -> Option<u64> {
+  #[allow(clippy::unnecessary_cast)]
+  Some(libc::makedev(major as _, minor as _) as u64)
+}
+
+#[cfg(not(unix))]
+fn device_number(_major: u32, _minor: u32) -> Option<u64> {
+  None
+}
+
+/// Write the contents of an entry to `entry_path`, counting them towards the limits.
+fn write_entry(
+  entry: &mut impl Read,
+  entry_path: &Path,
+  limits: &LimitTracker,
+) -> Result<(), RZipProcessingError> {
+  let out_file = create_out_file(entry_path)?;
+  io::copy(entry, &mut limits.writer(out_file))?;
+
+  Ok(())
+}
+
+/// Where the contents of a regular file entry are written. Nested archives are held in memory
+/// while they fit within [RZipExtractConfig::max_in_memory_bytes], so that they can be unpacked
+/// from there without ever being written out. Anything bigger, or that turns out not to be an
+/// archive after all, is written to its file.
+struct EntryWriter {
+  entry_path: PathBuf,
+  file: Option<File>,
+  contents: Vec<u8>,
+  max_in_memory_bytes: u64,
+}
+
+impl EntryWriter {
+  fn create(entry_path: &Path, guard: &EntryGuard) -> io::Result<Self> {
+    let max_in_memory_bytes = guard.max_in_memory_bytes(entry_path);
+    prepare_entry_path(entry_path)?;
+    let file = match max_in_memory_bytes {
+      Some(_) => None,
+      None => Some(File::create(entry_path)?),
+    };
+
+    Ok(Self {
+      entry_path: entry_path.to_path_buf(),
+      file,
+      contents: vec![],
+      max_in_memory_bytes: max_in_memory_bytes.unwrap_or_default(),
+    })
+  }
+
+  /// Finish the entry, either keeping it in memory or applying its metadata to its file.
+  fn finish(mut self, metadata: EntryMetadata, guard: &EntryGuard) -> io::Result<()> {
+    if self.file.is_none() {
+      let file_name = self.entry_path.file_name().unwrap_or_default();
+      if ArchiveFormat::detect_bytes(&file_name.to_string_lossy(), &self.contents).is_some() {
+        guard.keep_in_memory(&self.entry_path, self.contents, &metadata);
+        return Ok(());
+      }
+      self.write_out()?;
+    }
+
+    drop(self.file);
+    guard.set_metadata(&self.entry_path, metadata)
+  }
+
+  /// Stop holding the entry in memory, writing what it has so far to its file.
+  fn write_out(&mut self) -> io::Result<&mut File> {
+    let mut file = File::create(&self.entry_path)?;
+    file.write_all(&self.contents)?;
+    self.contents = vec![];
+    Ok(self.file.insert(file))
+  }
+}
+
+impl Write for EntryWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let file = match &mut self.file {
+      Some(file) => file,
+      None if self.contents.len() + buf.len() <= self.max_in_memory_bytes as usize => {
+        self.contents.extend_from_slice(buf);
+        return Ok(buf.len());
+      }
+      None => self.write_out()?,
+    };
+    file.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match &mut self.file {
+      Some(file) => file.flush(),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Write the contents of a file entry to `entry_path`, counting them towards the limits.
+fn write_file_entry(
+  entry: &mut (impl Read + ?Sized),
+  entry_path: &Path,
+  limits: &LimitTracker,
+  guard: &EntryGuard,
+) -> Result<EntryWriter, RZipProcessingError> {
+  let mut out_file = EntryWriter::create(entry_path, guard)?;
+  io::copy(entry, &mut limits.writer(&mut out_file))?;
+
+  Ok(out_file)
+}
+
+/// Unpack the entries of a tar stream.
+fn unpack_tar(
+  tar: impl Read,
+  out_path: &Path,
+  limits: &LimitTracker,
+  guard: &EntryGuard,
+) -> Result<(), RZipProcessingError> {
+  fs::create_dir_all(out_path)?;
+
+  // The guard sets every entry's times, as configured
+  let mut archive = tar::Archive::new(tar);
+  archive.set_preserve_mtime(false);
+  for entry in archive.entries()? {
+    let mut entry = entry?;
+    limits.add_entry(Some(entry.size()))?;
+    // [tar::Entry::unpack_in] silently skips some unsafe paths and strips the root off others, so
+    // the guard decides first
+    let entry_name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+    let Some(entry_path) = guard.entry_path(&entry_name)? else {
+      continue;
+    };
+
+    let metadata = tar_entry_metadata(&mut entry)?;
+    let entry_type = entry.header().entry_type();
+    let Some(entry_path) =
+      guard.resolve_conflict(&entry_name, entry_path, &metadata, entry_type.is_dir())?
+    else {
+      continue;
+    };
+    let special_kind = match entry_type {
+      tar::EntryType::Symlink => Some(SpecialEntryKind::Symlink),
+      tar::EntryType::Link => Some(SpecialEntryKind::Hardlink),
+      tar::EntryType::Fifo => Some(SpecialEntryKind::Fifo),
+      tar::EntryType::Char => Some(SpecialEntryKind::CharDevice),
+      tar::EntryType::Block => Some(SpecialEntryKind::BlockDevice),
+      _ => None,
+    };
+    if entry_type.is_dir() {
+      create_entry_dir(&entry_path)?;
+    } else if let Some(kind) = special_kind {
+      let target = entry
+        .link_name_bytes()
+        .map(|target| String::from_utf8_lossy(&target).into_owned());
+      let action = guard.special_entry(&entry_name, &entry_path, kind, target.as_deref())?;
+      // Only device entries are sure to fill in their device numbers
+      let header = entry.header();
+      let device = match (header.device_major(), header.device_minor()) {
+        (Ok(Some(major)), Ok(Some(minor))) => device_number(major, minor),
+        _ => None,
+      };
+      unpack_special_entry(
+        action,
+        kind,
+        &entry_path,
+        metadata,
+        limits,
+        guard,
+        |link_path| match (kind, &target, link_path) {
+          (SpecialEntryKind::Symlink, Some(target), _) => Ok(create_symlink(target, &entry_path)?),
+          (SpecialEntryKind::Hardlink, _, Some(link_path)) => {
+            Ok(fs::hard_link(link_path, &entry_path)?)
+          }
+          _ => create_special_file(kind, &entry_path, device),
+        },
+      )?;
+      continue;
+    } else {
+      // The stream's bytes are already counted as they're read
+      let mut out_file = EntryWriter::create(&entry_path, guard)?;
+      io::copy(&mut entry, &mut out_file)?;
+      out_file.finish(metadata, guard)?;
+      continue;
+    }
+    guard.set_metadata(&entry_path, metadata)?;
+  }
 
   Ok(())
 }
 
+/// The metadata a tar entry records in its header and PAX extensions, which take precedence.
+fn tar_entry_metadata<R: Read>(entry: &mut tar::Entry<R>) -> io::Result<EntryMetadata> {
+  let header = entry.header();
+  let mut metadata = EntryMetadata {
+    mode: header.mode().ok(),
+    uid: header.uid().ok().and_then(|uid| uid.try_into().ok()),
+    gid: header.gid().ok().and_then(|gid| gid.try_into().ok()),
+    mtime: header
+      .mtime()
+      .ok()
+      .and_then(|mtime| unix_time(mtime as i64, 0)),
+    // GNU headers leave the access time at 0 unless it was recorded
+    atime: header
+      .as_gnu()
+      .and_then(|gnu| gnu.atime().ok())
+      .filter(|atime| *atime != 0)
+      .and_then(|atime| unix_time(atime as i64, 0)),
+    ..Default::default()
+  };
+
+  let Some(extensions) = entry.pax_extensions()? else {
+    return Ok(metadata);
+  };
+  for extension in extensions {
+    let extension = extension?;
+    let (Ok(key), Ok(value)) = (extension.key(), extension.value()) else {
+      continue;
+    };
+    match key {
+      "mtime" => metadata.mtime = pax_time(value).or(metadata.mtime),
+      "atime" => metadata.atime = pax_time(value).or(metadata.atime),
+      _ => {
+        if let Some(name) = key.strip_prefix("SCHILY.xattr.") {
+          let value = extension.value_bytes().to_vec();
+          metadata.xattrs.push((name.to_string(), value));
+        }
+      }
+    }
+  }
+
+  Ok(metadata)
+}
+
+/// Parse a PAX timestamp, which is in seconds with an optional fraction.
+fn pax_time(value: &str) -> Option<SystemTime> {
+  let (secs, fraction) = value.split_once('.').unwrap_or((value, ""));
+  let nanos = format!("{fraction:0<9}").get(..9)?.parse().ok()?;
+  unix_time(secs.parse().ok()?, nanos)
+}
+
+/// The modification and access times a zip entry records. The extended timestamp and NTFS extra
+/// fields are precise, while the DOS time every entry has is only given to two seconds in an
+/// unknown time zone, taken to be UTC.
+fn zip_entry_times<R: Read>(
+  entry: &zip::read::ZipFile<'_, R>,
+) -> (Option<SystemTime>, Option<SystemTime>) {
+  use zip::ExtraField;
+
+  for field in entry.extra_data_fields() {
+    match field {
+      ExtraField::ExtendedTimestamp(timestamp) if timestamp.mod_time().is_some() => {
+        let time = |secs: Option<u32>| secs.and_then(|secs| unix_time(secs.into(), 0));
+        return (time(timestamp.mod_time()), time(timestamp.ac_time()));
+      }
+      ExtraField::Ntfs(ntfs) => return (nt_time(ntfs.mtime()), nt_time(ntfs.atime())),
+      _ => (),
+    }
+  }
+
+  (entry.last_modified().and_then(dos_time), None)
+}
+
+/// A time given in seconds and nanoseconds since the unix epoch, if it's not before it.
+pub(crate) fn unix_time(secs: i64, nanos: u32) -> Option<SystemTime> {
+  let secs = u64::try_from(secs).ok()?;
+  SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+/// A Windows time, given in 100ns intervals since 1601. Unset times are 0.
+fn nt_time(intervals: u64) -> Option<SystemTime> {
+  const UNIX_EPOCH_INTERVALS: u64 = 116_444_736_000_000_000;
+  let since_epoch = intervals.checked_sub(UNIX_EPOCH_INTERVALS)?;
+  unix_time(
+    (since_epoch / 10_000_000) as i64,
+    (since_epoch % 10_000_000) as u32 * 100,
+  )
+}
+
+/// A DOS date and time, taken to be in UTC.
+fn dos_time(datetime: zip::DateTime) -> Option<SystemTime> {
+  // Days since the unix epoch of a date in the proleptic Gregorian calendar
+  let (month, day) = (i64::from(datetime.month()), i64::from(datetime.day()));
+  let year = i64::from(datetime.year()) - i64::from(month <= 2);
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  let days = era * 146_097 + day_of_era - 719_468;
+
+  let secs_of_day = i64::from(datetime.hour()) * 3600
+    + i64::from(datetime.minute()) * 60
+    + i64::from(datetime.second());
+  unix_time(days * 86_400 + secs_of_day, 0)
+}
+
+/// Unpack a 7z archive with a single password, counting what is written towards the limits.
+fn seven_z_extract(
+  archive_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+  password: sevenz_rust::Password,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  let limits = LimitTracker::new(archive_path, config)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  let archive_file = open_archive(archive_path, config)?;
+
+  // Errors from writing an entry are kept aside, as sevenz_rust only passes along its own
+  let mut entry_error = None;
+  let res = sevenz_rust::decompress_with_extract_fn_and_password(
+    archive_file,
+    out_path,
+    password,
+    |entry, reader, _| match write_seven_z_entry(entry, reader, &limits, &guard) {
+      Ok(()) => Ok(true),
+      Err(e) => {
+        entry_error = Some(e);
+        Err(sevenz_rust::Error::other("Failed to unpack entry"))
+      }
+    },
+  );
+  if let Some(e) = entry_error {
+    return Err(e);
+  }
+  res?;
+
+  let warnings = guard.finish()?;
+  limits.finish();
+  Ok(warnings)
+}
+
+fn write_seven_z_entry(
+  entry: &sevenz_rust::SevenZArchiveEntry,
+  reader: &mut dyn Read,
+  limits: &LimitTracker,
+  guard: &EntryGuard,
+) -> Result<(), RZipProcessingError> {
+  limits.add_entry(Some(entry.size()))?;
+  // Unix modes are kept in the upper half of the attributes, and 7z archives don't record owners
+  let metadata = EntryMetadata {
+    mode: (entry.has_windows_attributes && entry.windows_attributes & 0x8000 != 0)
+      .then_some(entry.windows_attributes >> 16),
+    mtime: entry
+      .has_last_modified_date
+      .then(|| entry.last_modified_date().into()),
+    atime: entry.has_access_date.then(|| entry.access_date().into()),
+    ..Default::default()
+  };
+  let entry_path = match guard.entry_path(entry.name())? {
+    Some(entry_path) => {
+      guard.resolve_conflict(entry.name(), entry_path, &metadata, entry.is_directory())?
+    }
+    None => None,
+  };
+  let Some(entry_path) = entry_path else {
+    // Entries share a decoder, so a skipped entry's data still needs reading
+    io::copy(&mut limits.reader(reader), &mut io::sink())?;
+    return Ok(());
+  };
+
+  if entry.is_directory() {
+    create_entry_dir(&entry_path)?;
+  } else if let Some(kind) = metadata.mode.and_then(SpecialEntryKind::from_mode) {
+    // A symlink's target is stored as its contents, and anything else is read past
+    let target = read_link_target(reader, limits)?;
+    let target = (kind == SpecialEntryKind::Symlink).then_some(target);
+    let action = guard.special_entry(entry.name(), &entry_path, kind, target.as_deref())?;
+    return unpack_special_entry(action, kind, &entry_path, metadata, limits, guard, |_| {
+      match &target {
+        Some(target) => Ok(create_symlink(target, &entry_path)?),
+        None => create_special_file(kind, &entry_path, None),
+      }
+    });
+  } else {
+    let out_file = write_file_entry(reader, &entry_path, limits, guard)?;
+    return Ok(out_file.finish(metadata, guard)?);
+  }
+
+  Ok(guard.set_metadata(&entry_path, metadata)?)
+}
+
+/// Convert a compress-tools error, recognizing libarchive's reports of encrypted entries. libarchive
+/// can't take a passphrase through compress-tools, so encrypted entries always fail here.
+pub(crate) fn compress_tools_error(e: compress_tools::Error) -> RZipProcessingError {
+  let message = e.to_string().to_lowercase();
+  if message.contains("encrypt") || message.contains("passphrase") {
+    RZipProcessingError::NoMatchingPassword
+  } else {
+    e.into()
+  }
+}
+
+/// A free path next to `out_path` to unpack to before moving the output into place. It's hidden, so
+/// that one left behind by a crash isn't mistaken for output.
+fn staging_path(out_path: &Path) -> PathBuf {
+  let mut staging_name = OsString::from(".");
+  staging_name.push(out_path.file_name().unwrap_or_default());
+  staging_name.push(".rzip-staging");
+  let staging_path = out_path.with_file_name(staging_name);
+  if fs::symlink_metadata(&staging_path).is_err() {
+    staging_path
+  } else {
+    policy::available_path(&staging_path, false)
+  }
+}
+
+/// Move the output a stage unpacked to `staging_path` into place at `out_path`. Output is renamed
+/// into place whole, unless a directory is already there, in which case the staged entries are
+/// moved into it one at a time under the conflict policy. Each path something is moved to is added
+/// to `placed`.
+fn place_staged_output(
+  archive_path: &Path,
+  staging_path: &Path,
+  out_path: &Path,
+  out_path_existed: bool,
+  config: &RZipExtractConfig,
+  placed: &mut Vec<PathBuf>,
+) -> Result<Vec<RZipWarning>, RZipError> {
+  // Directories the archive made read-only are kept writable until they're in place
+  let locked = unlock_staged_dirs(staging_path)?;
+
+  // Only output that was unpacked just now is collapsed, as whatever was there before isn't the
+  // archive's to move. Archives held in memory move along with the rest of the output.
+  let mut staged_root = staging_path.to_path_buf();
+  if config.collapse_single_root && !out_path_existed {
+    let in_memory = config.in_memory.paths_under(staging_path);
+    if !in_memory
+      .iter()
+      .any(|path| path.parent() == Some(staging_path))
+    {
+      if let Some(root_name) = collapse_single_root(staging_path)? {
+        staged_root.push(root_name);
+      }
+    }
+  }
+  if !(out_path.is_dir() && staging_path.is_dir()) {
+    // What's there can only be merged into when both are directories, so it's replaced
+    remove_out_path(out_path)?;
+    fs::rename(staging_path, out_path)?;
+    config.in_memory.rename_under(&staged_root, out_path);
+    relock_placed_dirs(&locked, &[(staged_root, out_path.to_path_buf())])?;
+    placed.push(out_path.to_path_buf());
+    return Ok(vec![]);
+  }
+
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  let mut moves = vec![];
+  let merged = merge_staged_dir(staging_path, out_path, "", &guard, &mut moves);
+  placed.extend(moves.iter().map(|(_, placed_path)| placed_path.clone()));
+  merged?;
+  let warnings = guard.finish()?;
+  relock_placed_dirs(&locked, &moves)?;
+  Ok(warnings)
+}
+
+/// Move each entry of the staged directory `staged_dir` into `dir`, merging directories and
+/// resolving conflicts with the files already there. `prefix` is the entries' path in the archive.
+/// Each entry moved whole is added to `moves` along with where it went.
+fn merge_staged_dir(
+  staged_dir: &Path,
+  dir: &Path,
+  prefix: &str,
+  guard: &EntryGuard,
+  moves: &mut Vec<(PathBuf, PathBuf)>,
+) -> io::Result<()> {
+  let mut entries = fs::read_dir(staged_dir)?
+    .map(|entry| entry.map(|entry| entry.file_name()))
+    .collect::<io::Result<Vec<_>>>()?;
+  entries.sort();
+  for file_name in entries {
+    let staged_path = staged_dir.join(&file_name);
+    let entry_path = dir.join(&file_name);
+    let entry_name = format!("{prefix}{}", file_name.to_string_lossy());
+    let staged_metadata = fs::symlink_metadata(&staged_path)?;
+    if staged_metadata.is_dir() && entry_path.is_dir() {
+      let prefix = format!("{entry_name}/");
+      merge_staged_dir(&staged_path, &entry_path, &prefix, guard, moves)?;
+      continue;
+    }
+
+    // A staged directory is moved whole, and anything of another type is replaced rather than
+    // renamed onto
+    let metadata = EntryMetadata {
+      mtime: staged_metadata.modified().ok(),
+      ..Default::default()
+    };
+    let is_dir = staged_metadata.is_dir();
+    if let Some(entry_path) = guard.resolve_conflict(&entry_name, entry_path, &metadata, is_dir)? {
+      if is_dir || entry_path.is_dir() {
+        remove_out_path(&entry_path)?;
+      }
+      fs::rename(&staged_path, &entry_path)?;
+      moves.push((staged_path, entry_path));
+    }
+  }
+
+  Ok(())
+}
+
+/// Move what an attempt unpacked to `attempt_path` to `out_path`, renaming it into place unless a
+/// directory is already there, in which case it's merged in under the conflict policy.
+fn place_attempt(
+  archive_path: &Path,
+  attempt_path: &Path,
+  out_path: &Path,
+  config: &RZipExtractConfig,
+) -> Result<Vec<RZipWarning>, RZipProcessingError> {
+  let locked = unlock_staged_dirs(attempt_path)?;
+  if !out_path.is_dir() {
+    if fs::symlink_metadata(attempt_path).is_ok() {
+      fs::rename(attempt_path, out_path)?;
+      config.in_memory.rename_under(attempt_path, out_path);
+      relock_placed_dirs(
+        &locked,
+        &[(attempt_path.to_path_buf(), out_path.to_path_buf())],
+      )?;
+    }
+    return Ok(vec![]);
+  }
+
+  // Archives held in memory are merged as the files they would have been
+  config.in_memory.write_out_under(attempt_path)?;
+  let guard = EntryGuard::new(archive_path, out_path, config);
+  let mut moves = vec![];
+  merge_staged_dir(attempt_path, out_path, "", &guard, &mut moves)?;
+  let warnings = guard.finish()?;
+  relock_placed_dirs(&locked, &moves)?;
+  Ok(warnings)
+}
+
+/// Move the contents of the directory at `out_path` up a level if it holds nothing but a single
+/// directory, returning that directory's name. Both directories need to be writable, as staged
+/// ones are once [unlocked](unlock_staged_dirs).
+fn collapse_single_root(out_path: &Path) -> io::Result<Option<OsString>> {
+  if !out_path.is_dir() {
+    return Ok(None);
+  }
+  let mut entries = fs::read_dir(out_path)?;
+  let root = match (entries.next().transpose()?, entries.next()) {
+    (Some(root), None) if root.file_type()?.is_dir() => root,
+    _ => return Ok(None),
+  };
+  let (root, root_name) = (root.path(), root.file_name());
+
+  // The root moves aside first, as it may hold an entry with its own name. Nothing else is there,
+  // so any name but its own is free.
+  let moved_name = match root_name == ".rzip_root" {
+    true => ".rzip_root (1)",
+    false => ".rzip_root",
+  };
+  let moved_root = out_path.join(moved_name);
+  fs::rename(root, &moved_root)?;
+  for entry in fs::read_dir(&moved_root)? {
+    let entry = entry?;
+    fs::rename(entry.path(), out_path.join(entry.file_name()))?;
+  }
+  fs::remove_dir(moved_root)?;
+
+  Ok(Some(root_name))
+}
+
+/// Remove whatever a stage left at `staging_path`, along with any archives it held in memory.
+fn discard_staged_output(staging_path: &Path, config: &RZipExtractConfig) -> io::Result<()> {
+  config.in_memory.remove_under(staging_path);
+  unlock_staged_dirs(staging_path)?;
+  remove_out_path(staging_path)
+}
+
+/// Give the directory at `staged_path` and every directory under it write and search permission
+/// for their owner, so that what's in them can be moved or removed. Returns the directories that
+/// lacked it along with their modes.
+#[cfg(unix)]
+fn unlock_staged_dirs(staged_path: &Path) -> io::Result<Vec<(PathBuf, u32)>> {
+  use std::os::unix::fs::PermissionsExt;
+
+  use filetime::FileTime;
+
+  let mut locked = vec![];
+  let mut dirs = vec![staged_path.to_path_buf()];
+  while let Some(dir) = dirs.pop() {
+    let metadata = match fs::symlink_metadata(&dir) {
+      Ok(metadata) if metadata.is_dir() => metadata,
+      _ => continue,
+    };
+    let mode = metadata.permissions().mode() & 0o7777;
+    if mode & 0o700 != 0o700 {
+      fs::set_permissions(&dir, fs::Permissions::from_mode(mode | 0o700))?;
+      locked.push((dir.clone(), mode));
+    }
+    for entry in fs::read_dir(&dir)? {
+      let entry = entry?;
+      if entry.file_type()?.is_dir() {
+        dirs.push(entry.path());
+      }
+    }
+
+    // Reading the directory counts as accessing it, which the unpacked times shouldn't show
+    let atime = FileTime::from_last_access_time(&metadata);
+    let mtime = FileTime::from_last_modification_time(&metadata);
+    filetime::set_file_times(&dir, atime, mtime)?;
+  }
+
+  Ok(locked)
+}
+
+#[cfg(not(unix))]
+fn unlock_staged_dirs(_staged_path: &Path) -> io::Result<Vec<(PathBuf, u32)>> {
+  Ok(vec![])
+}
+
+/// Give the `locked` directories their modes back wherever they were placed. `moves` holds the
+/// staged path of each entry that was moved whole and where it went; directories that were merged
+/// into one already there leave it as it was.
+#[cfg(unix)]
+fn relock_placed_dirs(locked: &[(PathBuf, u32)], moves: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+
+  for (dir, mode) in locked {
+    let placed_dir = moves.iter().find_map(|(staged_path, placed_path)| {
+      let relative_path = dir.strip_prefix(staged_path).ok()?;
+      Some(placed_path.join(relative_path))
+    });
+    if let Some(placed_dir) = placed_dir {
+      fs::set_permissions(placed_dir, fs::Permissions::from_mode(*mode))?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn relock_placed_dirs(_locked: &[(PathBuf, u32)], _moves: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+  Ok(())
+}
+
+/// Remove whatever an unpacking attempt left at `out_path`.
+fn remove_out_path(out_path: &Path) -> io::Result<()> {
+  match fs::symlink_metadata(out_path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(out_path),
+    Ok(_) => fs::remove_file(out_path),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
 #[cfg(test)]
 mod test {
   use std::{fs::File, io::Write};
@@ -112,7 +1402,90 @@ mod test {
     let mut file = File::create(&test_file_path).unwrap();
     file.write_all("Meaningless data".as_bytes()).unwrap();
 
-    let res = unpack_file(&test_file_path, Path::new("./some_path"));
+    let res = unpack_file(
+      &test_file_path,
+      Path::new("./some_path"),
+      &RZipExtractConfig::default(),
+    );
     assert!(res.is_err());
   }
+
+  #[test]
+  fn test_unpack_through_staging_path() {
+    let data_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let temp_dir = TempDir::new().unwrap();
+    let dir_names = || {
+      let mut names: Vec<_> = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+      names.sort();
+      names
+    };
+
+    // A staging directory left behind by a crash is left alone
+    fs::create_dir(temp_dir.path().join(".packed_zip.rzip-staging")).unwrap();
+    let archive_path = temp_dir.path().join("packed_zip.zip");
+    fs::copy(data_root.join("indiv/packed_zip.zip"), &archive_path).unwrap();
+    let out_path = temp_dir.path().join("packed_zip");
+    unpack_file(&archive_path, &out_path, &RZipExtractConfig::default()).unwrap();
+    assert!(out_path.join("doc_zip.txt").exists());
+    assert_eq!(
+      dir_names(),
+      [".packed_zip.rzip-staging", "packed_zip", "packed_zip.zip"]
+    );
+
+    // Failed archives leave nothing at all
+    let archive_path = temp_dir.path().join("zeros.zip");
+    fs::copy(data_root.join("limits/zeros.zip"), &archive_path).unwrap();
+    let config = RZipExtractConfig {
+      limits: crate::ExtractLimits {
+        max_archive_bytes: Some(1024),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let res = unpack_file(&archive_path, &temp_dir.path().join("zeros"), &config);
+    assert!(matches!(res, Err(RZipError::LimitExceeded(..))));
+    assert_eq!(
+      dir_names(),
+      [
+        ".packed_zip.rzip-staging",
+        "packed_zip",
+        "packed_zip.zip",
+        "zeros.zip"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_entry_times() {
+    let epoch = SystemTime::UNIX_EPOCH;
+    assert_eq!(
+      pax_time("1000000000.25"),
+      Some(epoch + Duration::new(1_000_000_000, 250_000_000))
+    );
+    assert_eq!(
+      pax_time("1000000000"),
+      Some(epoch + Duration::from_secs(1_000_000_000))
+    );
+    assert_eq!(pax_time("-1"), None);
+    assert_eq!(
+      nt_time(116_444_736_000_000_000 + 10_000_005),
+      Some(epoch + Duration::new(1, 500))
+    );
+    assert_eq!(nt_time(0), None);
+
+    // 2001-09-09 01:46:40 UTC
+    let datetime = zip::DateTime::from_date_and_time(2001, 9, 9, 1, 46, 40).unwrap();
+    assert_eq!(
+      dos_time(datetime),
+      Some(epoch + Duration::from_secs(1_000_000_000))
+    );
+    let datetime = zip::DateTime::from_date_and_time(2024, 2, 29, 0, 0, 0).unwrap();
+    assert_eq!(
+      dos_time(datetime),
+      Some(epoch + Duration::from_secs(1_709_164_800))
+    );
+  }
 }
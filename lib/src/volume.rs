@@ -0,0 +1,557 @@
+use std::{
+  collections::BTreeMap,
+  ffi::OsString,
+  fs::{self, File},
+  io::{self, Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+};
+
+use crate::{error::RZipError, format::ArchiveFormat};
+
+/// The naming schemes used for the volumes of a multi-volume archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum VolumeScheme {
+  /// `foo.part1.rar`, `foo.part2.rar`, ...
+  RarParts,
+  /// `foo.rar`, `foo.r00`, `foo.r01`, ...
+  RarNumbered,
+  /// `foo.7z.001`, `foo.7z.002`, ..., a plain byte split of any archive.
+  NumberedSplit,
+  /// `foo.z01`, `foo.z02`, ..., `foo.zip`
+  ZipSplit,
+}
+
+/// The parsed name of a file that could be one volume of a multi-volume archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VolumeName {
+  scheme: VolumeScheme,
+  /// The name shared by every volume in the set, without the volume suffix.
+  base: String,
+  /// The position of this volume in the set. For [VolumeScheme::ZipSplit] the trailing `.zip`
+  /// volume has no number and is [ZIP_LAST_INDEX].
+  index: usize,
+  /// The number of digits in the volume number, used to name missing volumes.
+  width: usize,
+}
+
+/// Index given to the `.zip` volume of a split zip, which always comes last.
+const ZIP_LAST_INDEX: usize = usize::MAX;
+
+impl VolumeName {
+  /// Parse a file name against each volume naming scheme.
+  fn parse(file_name: &str) -> Option<Self> {
+    let lowercase = file_name.to_ascii_lowercase();
+    let (stem, ext) = lowercase.rsplit_once('.')?;
+    if stem.is_empty() {
+      return None;
+    }
+    let base = file_name[..stem.len()].to_string();
+
+    if ext == "rar" {
+      // `foo.partN.rar` or the first volume of `foo.rar`, `foo.r00`, ...
+      if let Some((part_stem, part)) = stem.rsplit_once(".part") {
+        if let Some(index) = parse_number(part) {
+          return Some(Self {
+            scheme: VolumeScheme::RarParts,
+            base: file_name[..part_stem.len()].to_string(),
+            index,
+            width: part.len(),
+          });
+        }
+      }
+      return Some(Self {
+        scheme: VolumeScheme::RarNumbered,
+        base,
+        index: 0,
+        width: 2,
+      });
+    }
+
+    if ext == "zip" {
+      return Some(Self {
+        scheme: VolumeScheme::ZipSplit,
+        base,
+        index: ZIP_LAST_INDEX,
+        width: 2,
+      });
+    }
+
+    if let Some(number) = ext.strip_prefix('r') {
+      let index = parse_number(number)?;
+      return Some(Self {
+        scheme: VolumeScheme::RarNumbered,
+        base,
+        index: index + 1,
+        width: number.len(),
+      });
+    }
+
+    if let Some(number) = ext.strip_prefix('z') {
+      let index = parse_number(number)?;
+      return Some(Self {
+        scheme: VolumeScheme::ZipSplit,
+        base,
+        index,
+        width: number.len(),
+      });
+    }
+
+    // Numbered splits need to be splits of something we recognize as an archive
+    if ext.len() == 3 && ArchiveFormat::from_file_name(stem).is_some() {
+      let index = parse_number(ext)?;
+      return Some(Self {
+        scheme: VolumeScheme::NumberedSplit,
+        base,
+        index,
+        width: ext.len(),
+      });
+    }
+
+    None
+  }
+
+  /// The index of the first volume in a set using this name's scheme.
+  fn first_index(&self) -> usize {
+    match self.scheme {
+      VolumeScheme::RarNumbered => 0,
+      _ => 1,
+    }
+  }
+
+  /// The key shared by every volume in the same set.
+  fn set_key(&self) -> (VolumeScheme, String) {
+    (self.scheme, self.base.to_ascii_lowercase())
+  }
+
+  /// The file name of the volume at `index` in this name's set.
+  fn volume_file_name(&self, index: usize) -> String {
+    format!("{}{}", self.base, self.volume_suffix(index))
+  }
+
+  /// The suffix following the base name of the volume at `index` in this name's set.
+  fn volume_suffix(&self, index: usize) -> String {
+    let width = self.width;
+    match self.scheme {
+      VolumeScheme::RarParts => format!(".part{index:0width$}.rar"),
+      VolumeScheme::RarNumbered if index == 0 => ".rar".to_string(),
+      VolumeScheme::RarNumbered => format!(".r{:0width$}", index - 1),
+      VolumeScheme::NumberedSplit => format!(".{index:0width$}"),
+      VolumeScheme::ZipSplit if index == ZIP_LAST_INDEX => ".zip".to_string(),
+      VolumeScheme::ZipSplit => format!(".z{index:0width$}"),
+    }
+  }
+
+  /// Whether this is a plain `.rar` or `.zip` name, which ordinary archives have as well as the
+  /// volumes of a set.
+  fn is_plain_archive(&self) -> bool {
+    match self.scheme {
+      VolumeScheme::RarNumbered => self.index == 0,
+      VolumeScheme::ZipSplit => self.index == ZIP_LAST_INDEX,
+      _ => false,
+    }
+  }
+
+  /// Whether the first numbered volume of this name's set exists in `parent`, in either case.
+  fn has_numbered_volume(&self, parent: &Path) -> bool {
+    let suffix = self.volume_suffix(1);
+    [suffix.to_ascii_lowercase(), suffix.to_ascii_uppercase()]
+      .iter()
+      .any(|suffix| parent.join(format!("{}{suffix}", self.base)).is_file())
+  }
+}
+
+/// Parse an all-digit volume number.
+fn parse_number(digits: &str) -> Option<usize> {
+  if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+    return None;
+  }
+
+  digits.parse().ok()
+}
+
+/// Parse the volume name of the file at `path`, if it has one.
+fn parse_path(path: &Path) -> Option<VolumeName> {
+  path
+    .file_name()
+    .and_then(|file_name| file_name.to_str())
+    .and_then(VolumeName::parse)
+}
+
+/// The volumes that together make up one logical archive, in the order their data is read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeSet {
+  volumes: Vec<PathBuf>,
+}
+
+impl VolumeSet {
+  /// Find the volume set that the file at `path` belongs to. Returns `None` if the file isn't
+  /// part of a multi-volume archive, and an error if any volume of its set is missing.
+  pub fn find(path: &Path) -> Result<Option<Self>, RZipError> {
+    let Some(name) = parse_path(path) else {
+      return Ok(None);
+    };
+    let parent = match path.parent() {
+      Some(parent) if !parent.as_os_str().is_empty() => parent,
+      _ => Path::new("."),
+    };
+
+    // Spare ordinary archives the scan, as a set always has the volume numbered after them
+    if name.is_plain_archive() && !name.has_numbered_volume(parent) {
+      return Ok(None);
+    }
+
+    // Collect every sibling that shares this file's set
+    let mut members = BTreeMap::new();
+    for entry in fs::read_dir(parent)? {
+      let sibling = entry?.path();
+      if let Some(sibling_name) = parse_path(&sibling) {
+        if sibling_name.set_key() == name.set_key() && sibling.is_file() {
+          members.insert(sibling_name.index, sibling);
+        }
+      }
+    }
+
+    // A lone first volume is an ordinary archive
+    if members.len() <= 1 && name.index == name.first_index() {
+      return Ok(None);
+    }
+    if name.scheme == VolumeScheme::ZipSplit && members.len() <= 1 {
+      return Ok(None);
+    }
+
+    // Every volume up to the last numbered one has to be present
+    let last_numbered = members
+      .keys()
+      .copied()
+      .filter(|index| *index != ZIP_LAST_INDEX)
+      .max()
+      .unwrap_or(name.first_index());
+    let mut expected: Vec<usize> = (name.first_index()..=last_numbered).collect();
+    if name.scheme == VolumeScheme::ZipSplit {
+      expected.push(ZIP_LAST_INDEX);
+    }
+    let missing: Vec<PathBuf> = expected
+      .iter()
+      .filter(|index| !members.contains_key(index))
+      .map(|index| parent.join(name.volume_file_name(*index)))
+      .collect();
+    if !missing.is_empty() {
+      return Err(RZipError::MissingVolumes(path.to_path_buf(), missing));
+    }
+
+    Ok(Some(Self {
+      volumes: members.into_values().collect(),
+    }))
+  }
+
+  /// The volume holding the start of the archive's data.
+  pub fn first(&self) -> &Path {
+    &self.volumes[0]
+  }
+
+  /// Every volume in the set, in order.
+  pub fn volumes(&self) -> &[PathBuf] {
+    &self.volumes
+  }
+
+  /// Open the set as a single reader over the concatenation of its volumes.
+  pub fn open(&self) -> Result<MultiVolumeReader, io::Error> {
+    MultiVolumeReader::new(&self.volumes)
+  }
+}
+
+/// Get the name shared by every volume of a multi-volume archive, so that `foo.part1.rar` and
+/// `foo.7z.001` both yield `foo`. Returns `None` for files that aren't volumes.
+pub fn volume_stem(path: &Path) -> Option<OsString> {
+  let name = parse_path(path)?;
+  let base = match name.scheme {
+    VolumeScheme::RarParts | VolumeScheme::RarNumbered | VolumeScheme::ZipSplit => name.base,
+    VolumeScheme::NumberedSplit => {
+      let split_archive = path.with_file_name(&name.base);
+      crate::format::archive_stem(&split_archive)?
+        .to_string_lossy()
+        .into_owned()
+    }
+  };
+
+  Some(OsString::from(base))
+}
+
+/// Reduce a list of archives to one entry per multi-volume set, keeping the earliest volume of
+/// each set that is present. Other files are kept as they are.
+pub fn retain_first_volumes(paths: &mut Vec<PathBuf>) {
+  let mut first_volumes: BTreeMap<(PathBuf, VolumeScheme, String), usize> = BTreeMap::new();
+  for path in paths.iter() {
+    if let Some(name) = parse_path(path) {
+      let (scheme, base) = name.set_key();
+      let key = (
+        path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        scheme,
+        base,
+      );
+      let first = first_volumes.entry(key).or_insert(name.index);
+      *first = (*first).min(name.index);
+    }
+  }
+
+  paths.retain(|path| match parse_path(path) {
+    Some(name) => {
+      let (scheme, base) = name.set_key();
+      let key = (
+        path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        scheme,
+        base,
+      );
+      first_volumes.get(&key) == Some(&name.index)
+    }
+    None => true,
+  });
+}
+
+/// Every file belonging to the archive at `path`: all of its volumes for a multi-volume archive,
+/// otherwise just the file itself.
+pub fn archive_files(path: &Path) -> Result<Vec<PathBuf>, RZipError> {
+  Ok(match VolumeSet::find(path)? {
+    Some(volume_set) => volume_set.volumes,
+    None => vec![path.to_path_buf()],
+  })
+}
+
+/// A reader over the concatenated volumes of a multi-volume archive, so every backend can treat a
+/// volume set like a single file. libarchive's multi-file reader also joins volumes into one
+/// stream, which is how it reads split RAR and 7z archives.
+pub struct MultiVolumeReader {
+  volumes: Vec<(File, u64)>,
+  position: u64,
+  total_len: u64,
+}
+
+impl MultiVolumeReader {
+  fn new(paths: &[PathBuf]) -> Result<Self, io::Error> {
+    let mut volumes = Vec::with_capacity(paths.len());
+    let mut total_len = 0;
+    for path in paths {
+      let file = File::open(path)?;
+      let len = file.metadata()?.len();
+      total_len += len;
+      volumes.push((file, len));
+    }
+
+    Ok(Self {
+      volumes,
+      position: 0,
+      total_len,
+    })
+  }
+}
+
+impl Read for MultiVolumeReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    // Find the volume containing the current position
+    let mut volume_start = 0;
+    for (file, len) in &mut self.volumes {
+      if self.position < volume_start + *len {
+        file.seek(SeekFrom::Start(self.position - volume_start))?;
+        let read = file.read(buf)?;
+        self.position += read as u64;
+        return Ok(read);
+      }
+      volume_start += *len;
+    }
+
+    Ok(0)
+  }
+}
+
+impl Seek for MultiVolumeReader {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    let position = match pos {
+      SeekFrom::Start(offset) => Some(offset),
+      SeekFrom::End(offset) => self.total_len.checked_add_signed(offset),
+      SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+    };
+
+    match position {
+      Some(position) => {
+        self.position = position;
+        Ok(position)
+      }
+      None => Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "invalid seek to a negative position",
+      )),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::Write;
+
+  use tempfile::TempDir;
+
+  use super::*;
+
+  fn touch(dir: &Path, names: &[&str]) {
+    for name in names {
+      File::create(dir.join(name)).unwrap();
+    }
+  }
+
+  #[test]
+  fn test_parse_volume_names() {
+    let part = VolumeName::parse("Foo.part02.rar").unwrap();
+    assert_eq!(part.scheme, VolumeScheme::RarParts);
+    assert_eq!(part.base, "Foo");
+    assert_eq!(part.index, 2);
+    assert_eq!(part.volume_file_name(1), "Foo.part01.rar");
+
+    let numbered = VolumeName::parse("foo.r00").unwrap();
+    assert_eq!(numbered.scheme, VolumeScheme::RarNumbered);
+    assert_eq!(numbered.index, 1);
+    assert_eq!(numbered.volume_file_name(0), "foo.rar");
+
+    let split = VolumeName::parse("foo.7z.001").unwrap();
+    assert_eq!(split.scheme, VolumeScheme::NumberedSplit);
+    assert_eq!(split.base, "foo.7z");
+    assert_eq!(split.index, 1);
+
+    let zip = VolumeName::parse("foo.z01").unwrap();
+    assert_eq!(zip.scheme, VolumeScheme::ZipSplit);
+    assert_eq!(zip.volume_file_name(ZIP_LAST_INDEX), "foo.zip");
+
+    assert_eq!(VolumeName::parse("notes.001"), None);
+    assert_eq!(VolumeName::parse("notes.txt"), None);
+  }
+
+  #[test]
+  fn test_volume_stem() {
+    assert_eq!(
+      volume_stem(Path::new("foo.part1.rar")),
+      Some(OsString::from("foo"))
+    );
+    assert_eq!(
+      volume_stem(Path::new("foo.tar.gz.001")),
+      Some(OsString::from("foo"))
+    );
+    assert_eq!(
+      volume_stem(Path::new("foo.z01")),
+      Some(OsString::from("foo"))
+    );
+    assert_eq!(volume_stem(Path::new("foo.txt")), None);
+  }
+
+  #[test]
+  fn test_find_rar_parts() {
+    let temp_dir = TempDir::new().unwrap();
+    touch(
+      temp_dir.path(),
+      &["foo.part1.rar", "foo.part2.rar", "foo.part3.rar", "bar.rar"],
+    );
+
+    // Any volume finds the whole set
+    let volume_set = VolumeSet::find(&temp_dir.path().join("foo.part2.rar"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(volume_set.first(), temp_dir.path().join("foo.part1.rar"));
+    assert_eq!(volume_set.volumes().len(), 3);
+
+    // A single archive isn't a volume set
+    let single = VolumeSet::find(&temp_dir.path().join("bar.rar")).unwrap();
+    assert_eq!(single, None);
+  }
+
+  #[test]
+  fn test_find_plain_archive_volumes() {
+    let temp_dir = TempDir::new().unwrap();
+    touch(
+      temp_dir.path(),
+      &["foo.rar", "foo.R00", "bar.zip", "bar.z01"],
+    );
+
+    // A plain name is a volume when its first numbered volume is beside it, in either case
+    let rar = VolumeSet::find(&temp_dir.path().join("foo.rar"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(rar.volumes().len(), 2);
+    let zip = VolumeSet::find(&temp_dir.path().join("bar.zip"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(zip.first(), temp_dir.path().join("bar.z01"));
+  }
+
+  #[test]
+  fn test_find_missing_volumes() {
+    let temp_dir = TempDir::new().unwrap();
+    touch(
+      temp_dir.path(),
+      &["foo.part1.rar", "foo.part3.rar", "bar.z01", "bar.z02"],
+    );
+
+    match VolumeSet::find(&temp_dir.path().join("foo.part1.rar")) {
+      Err(RZipError::MissingVolumes(_, missing)) => {
+        assert_eq!(missing, vec![temp_dir.path().join("foo.part2.rar")]);
+      }
+      res => panic!("Expected missing volumes, got {res:?}"),
+    }
+
+    // The trailing .zip of a split zip is required
+    match VolumeSet::find(&temp_dir.path().join("bar.z01")) {
+      Err(RZipError::MissingVolumes(_, missing)) => {
+        assert_eq!(missing, vec![temp_dir.path().join("bar.zip")]);
+      }
+      res => panic!("Expected missing volumes, got {res:?}"),
+    }
+  }
+
+  #[test]
+  fn test_retain_first_volumes() {
+    let mut paths = vec![
+      PathBuf::from("dir/foo.zip"),
+      PathBuf::from("dir/foo.z01"),
+      PathBuf::from("dir/bar.part2.rar"),
+      PathBuf::from("dir/bar.part1.rar"),
+      PathBuf::from("dir/baz.rar"),
+      PathBuf::from("other/foo.z01"),
+    ];
+    retain_first_volumes(&mut paths);
+    assert_eq!(
+      paths,
+      vec![
+        PathBuf::from("dir/foo.z01"),
+        PathBuf::from("dir/bar.part1.rar"),
+        PathBuf::from("dir/baz.rar"),
+        PathBuf::from("other/foo.z01"),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_multi_volume_reader() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut paths = vec![];
+    for (i, chunk) in ["abc", "", "defg", "h"].iter().enumerate() {
+      let path = temp_dir.path().join(format!("data.tar.00{}", i + 1));
+      File::create(&path)
+        .unwrap()
+        .write_all(chunk.as_bytes())
+        .unwrap();
+      paths.push(path);
+    }
+
+    let mut reader = MultiVolumeReader::new(&paths).unwrap();
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "abcdefgh");
+
+    // Seeking across volume boundaries
+    reader.seek(SeekFrom::End(-3)).unwrap();
+    let mut tail = String::new();
+    reader.read_to_string(&mut tail).unwrap();
+    assert_eq!(tail, "fgh");
+    reader.seek(SeekFrom::Start(2)).unwrap();
+    let mut middle = [0u8; 3];
+    reader.read_exact(&mut middle).unwrap();
+    assert_eq!(&middle, b"cde");
+  }
+}
@@ -0,0 +1,78 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::RZipExtractConfig;
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set to the input `temp_dir`. The collapse data has the
+/// structure:
+///
+/// ```bash
+/// project.zip
+/// └── project/
+///     ├── doc_project.txt
+///     └── inner.tar.gz
+///         └── inner/
+///             └── inner/
+///                 └── doc_inner.txt
+/// ```
+fn copy_data_to(temp_dir: &Path, data_set: &str, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+fn extract_with(target_path: &Path, collapse_single_root: bool) -> PathBuf {
+  let config = RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    collapse_single_root,
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(target_path, &out_path, &config).unwrap();
+  out_path
+}
+
+#[test]
+fn test_collapse_single_root() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "collapse", "project.zip");
+
+  // Nested archives are collapsed too, but only by a single level
+  let out_path = extract_with(&target_path, true);
+  assert!(out_path.join("doc_project.txt").exists());
+  assert!(out_path.join("inner/inner/doc_inner.txt").exists());
+  assert_eq!(fs::read_dir(&out_path).unwrap().count(), 3);
+  assert_eq!(fs::read_dir(out_path.join("inner")).unwrap().count(), 1);
+  fs::remove_dir_all(&out_path).unwrap();
+
+  let out_path = extract_with(&target_path, false);
+  assert!(out_path.join("project/doc_project.txt").exists());
+  assert!(out_path
+    .join("project/inner/inner/inner/doc_inner.txt")
+    .exists());
+}
+
+#[test]
+fn test_collapse_leaves_other_outputs() {
+  let temp_dir = TempDir::new().unwrap();
+
+  // An archive holding a single file keeps its folder
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "packed_zip.zip");
+  let out_path = extract_with(&target_path, true);
+  assert!(out_path.join("doc_zip.txt").exists());
+
+  // Output that was already there isn't moved about
+  let target_path = copy_data_to(temp_dir.path(), "collapse", "project.zip");
+  fs::create_dir_all(temp_dir.path().join("project")).unwrap();
+  let out_path = extract_with(&target_path, true);
+  assert!(out_path.join("project/doc_project.txt").exists());
+}
@@ -0,0 +1,91 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::RZipExtractConfig;
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies archives that share a stem to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// a.zip
+/// └── doc_zip.txt
+/// a.7z
+/// └── doc_7z.txt
+/// b.tar
+/// └── doc_tar.txt
+/// ```
+fn copy_sibling_data_to(temp_dir: &Path) -> Vec<PathBuf> {
+  let data_root = get_data_root("indiv");
+  [
+    ("packed_zip.zip", "a.zip"),
+    ("packed_7z.7z", "a.7z"),
+    ("packed_tar.tar", "b.tar"),
+  ]
+  .into_iter()
+  .map(|(source_name, archive_name)| {
+    let archive_path = temp_dir.join(archive_name);
+    fs::copy(data_root.join(source_name), &archive_path).unwrap();
+    archive_path
+  })
+  .collect()
+}
+
+/// Check that each archive in the sibling data was unpacked to `dir` on its own.
+fn assert_siblings_apart(dir: &Path) {
+  for doc in ["a.zip.d/doc_zip.txt", "a.7z.d/doc_7z.txt", "b/doc_tar.txt"] {
+    assert!(dir.join(doc).exists(), "{doc}");
+  }
+  assert!(!dir.join("a").exists());
+  assert_eq!(fs::read_dir(dir.join("a.zip.d")).unwrap().count(), 1);
+  assert_eq!(fs::read_dir(dir.join("a.7z.d")).unwrap().count(), 1);
+}
+
+#[test]
+fn test_sibling_out_paths() {
+  let temp_dir = TempDir::new().unwrap();
+  let archives = copy_sibling_data_to(temp_dir.path());
+  let config = RZipExtractConfig {
+    target_path: temp_dir.path().to_path_buf(),
+    ..Default::default()
+  };
+
+  // Colliding archives are told apart the same way whatever order they come in
+  let out_paths = rzip_lib::get_out_paths_for_archives(&archives, &config).unwrap();
+  let expected: Vec<_> = ["a.zip.d", "a.7z.d", "b"]
+    .iter()
+    .map(|name| temp_dir.path().join(name))
+    .collect();
+  assert_eq!(out_paths, expected);
+  let reversed: Vec<_> = archives.iter().rev().cloned().collect();
+  let mut reversed_out_paths = rzip_lib::get_out_paths_for_archives(&reversed, &config).unwrap();
+  reversed_out_paths.reverse();
+  assert_eq!(reversed_out_paths, expected);
+
+  for (archive_path, out_path) in archives.iter().zip(&out_paths) {
+    rzip_lib::recursive_file_extract(archive_path, out_path, &config).unwrap();
+  }
+  assert_siblings_apart(temp_dir.path());
+}
+
+#[test]
+fn test_nested_sibling_out_paths() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join("collide.zip");
+  fs::copy(get_data_root("collide").join("collide.zip"), &target_path).unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  assert_siblings_apart(&out_path);
+}
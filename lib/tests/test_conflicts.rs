@@ -0,0 +1,320 @@
+use std::{
+  fs::{self, File},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use rzip_lib::{unpack, ConflictPolicy, RZipExtractConfig, RZipProcessingError, RZipWarning};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set to the input `temp_dir`. The times data has the
+/// structure:
+///
+/// ```bash
+/// times_tar.tar (and .tar.gz, .tar.bz2, .tar.xz, .tar.zst), times_zip.zip, times_7z.7z
+/// ├── doc_times.txt (modified 2001-09-09)
+/// ├── dir/
+/// └── dir/doc_nested.txt
+/// ```
+fn copy_data_to(temp_dir: &Path, data_set: &str, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+fn config_with_policy(target_path: &Path, on_conflict: ConflictPolicy) -> RZipExtractConfig {
+  RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    on_conflict,
+    ..Default::default()
+  }
+}
+
+/// Put a file in the way of `doc_times.txt` in `out_path`, modified at `modified`.
+fn write_existing(out_path: &Path, modified: SystemTime) {
+  fs::create_dir_all(out_path).unwrap();
+  let existing_path = out_path.join("doc_times.txt");
+  fs::write(&existing_path, "existing\n").unwrap();
+  File::options()
+    .write(true)
+    .open(existing_path)
+    .unwrap()
+    .set_modified(modified)
+    .unwrap();
+}
+
+#[test]
+fn test_every_stage_applies_conflict_policy() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  let stages: [(Stage, &str); 9] = [
+    (unpack::compress_tools_unpack, "times_zip.zip"),
+    (unpack::compress_tools_unpack, "times_7z.7z"),
+    (unpack::zip_unpack, "times_zip.zip"),
+    (unpack::seven_z_unpack, "times_7z.7z"),
+    (unpack::flake2_unpack, "times_tar_gz.tar.gz"),
+    (unpack::bzip2_unpack, "times_tar_bz2.tar.bz2"),
+    (unpack::zstd_unpack, "times_tar_zst.tar.zst"),
+    (unpack::xz_unpack, "times_tar_xz.tar.xz"),
+    (unpack::tar_unpack, "times_tar.tar"),
+  ];
+  let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+  let new = SystemTime::now();
+
+  for (stage, archive_name) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_data_to(temp_dir.path(), "times", archive_name);
+    let unpack_with = |on_conflict, existing_modified: SystemTime| {
+      let existing_secs = existing_modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+      let out_path = temp_dir
+        .path()
+        .join(format!("{on_conflict}_{existing_secs}"));
+      write_existing(&out_path, existing_modified);
+      let config = config_with_policy(&target_path, on_conflict);
+      let warnings = stage(&target_path, &out_path, &config).unwrap();
+      // Directories are merged whatever the policy
+      assert_eq!(
+        fs::read_to_string(out_path.join("dir/doc_nested.txt")).unwrap(),
+        "doc_nested\n"
+      );
+      let doc = fs::read_to_string(out_path.join("doc_times.txt")).unwrap();
+      (warnings, doc, out_path)
+    };
+
+    let (warnings, doc, _) = unpack_with(ConflictPolicy::Overwrite, new);
+    assert!(warnings.is_empty(), "{archive_name}");
+    assert_eq!(doc, "doc_times\n", "{archive_name}");
+
+    let (warnings, doc, _) = unpack_with(ConflictPolicy::Skip, old);
+    assert!(
+      matches!(&warnings[..], [RZipWarning::Conflict(path, name)] if *path == target_path && name == "doc_times.txt"),
+      "{archive_name}: {warnings:?}"
+    );
+    assert_eq!(doc, "existing\n", "{archive_name}");
+
+    let (warnings, doc, out_path) = unpack_with(ConflictPolicy::Rename, new);
+    assert!(warnings.is_empty(), "{archive_name}");
+    assert_eq!(doc, "existing\n", "{archive_name}");
+    assert_eq!(
+      fs::read_to_string(out_path.join("doc_times (1).txt")).unwrap(),
+      "doc_times\n",
+      "{archive_name}"
+    );
+
+    // Merging keeps whichever file is newer
+    let (warnings, doc, _) = unpack_with(ConflictPolicy::Merge, new);
+    assert_eq!(warnings.len(), 1, "{archive_name}");
+    assert_eq!(doc, "existing\n", "{archive_name}");
+    let (warnings, doc, _) = unpack_with(ConflictPolicy::Merge, old);
+    assert!(warnings.is_empty(), "{archive_name}");
+    assert_eq!(doc, "doc_times\n", "{archive_name}");
+  }
+}
+
+#[test]
+fn test_archive_output_conflict() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "packed_zip.zip");
+  let out_path = temp_dir.path().join("packed_zip");
+  fs::create_dir_all(&out_path).unwrap();
+  fs::write(out_path.join("existing.txt"), "existing\n").unwrap();
+  let extract_with = |on_conflict| {
+    let config = config_with_policy(&target_path, on_conflict);
+    rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap()
+  };
+
+  // Skipping leaves the archive packed and the output alone
+  let warnings = extract_with(ConflictPolicy::Skip);
+  assert!(matches!(
+    &warnings[..],
+    [RZipWarning::OutputExists(path, existing)] if *path == target_path && *existing == out_path
+  ));
+  assert_eq!(fs::read_dir(&out_path).unwrap().count(), 1);
+
+  // Renaming unpacks next to it
+  assert!(extract_with(ConflictPolicy::Rename).is_empty());
+  assert!(temp_dir.path().join("packed_zip (1)/doc_zip.txt").exists());
+  assert!(extract_with(ConflictPolicy::Rename).is_empty());
+  assert!(temp_dir.path().join("packed_zip (2)/doc_zip.txt").exists());
+
+  // Merging and overwriting unpack into it, keeping what the archive doesn't have
+  for on_conflict in [ConflictPolicy::Merge, ConflictPolicy::Overwrite] {
+    assert!(extract_with(on_conflict).is_empty());
+    assert!(out_path.join("existing.txt").exists());
+    assert!(out_path.join("doc_zip.txt").exists());
+  }
+}
+
+#[test]
+fn test_single_file_output_conflict() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "doc_gz.txt.gz");
+  let out_path = temp_dir.path().join("doc_gz.txt");
+  let extract_with = |on_conflict, existing_modified| {
+    fs::write(&out_path, "existing\n").unwrap();
+    File::options()
+      .write(true)
+      .open(&out_path)
+      .unwrap()
+      .set_modified(existing_modified)
+      .unwrap();
+    let config = config_with_policy(&target_path, on_conflict);
+    let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+    (warnings, fs::read_to_string(&out_path).unwrap())
+  };
+
+  // A decompressed file is kept when it's newer than the archive
+  let (warnings, doc) = extract_with(ConflictPolicy::Merge, SystemTime::now());
+  assert_eq!(warnings.len(), 1);
+  assert_eq!(doc, "existing\n");
+  let (warnings, doc) = extract_with(
+    ConflictPolicy::Merge,
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1),
+  );
+  assert!(warnings.is_empty());
+  assert_ne!(doc, "existing\n");
+
+  // Renaming keeps the extension last
+  let (warnings, doc) = extract_with(ConflictPolicy::Rename, SystemTime::now());
+  assert!(warnings.is_empty());
+  assert_eq!(doc, "existing\n");
+  assert!(temp_dir.path().join("doc_gz (1).txt").exists());
+}
+
+#[test]
+fn test_output_of_another_type_is_overwritten() {
+  let temp_dir = TempDir::new().unwrap();
+
+  // A file where an archive unpacks to a directory
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "packed_zip.zip");
+  let out_path = temp_dir.path().join("packed_zip");
+  fs::write(&out_path, "existing\n").unwrap();
+  let config = config_with_policy(&target_path, ConflictPolicy::Overwrite);
+  assert!(
+    rzip_lib::recursive_file_extract(&target_path, &out_path, &config)
+      .unwrap()
+      .is_empty()
+  );
+  assert!(out_path.join("doc_zip.txt").is_file());
+
+  // A directory where a compressed file decompresses to a file
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "doc_gz.txt.gz");
+  let out_path = temp_dir.path().join("doc_gz.txt");
+  fs::create_dir_all(out_path.join("existing")).unwrap();
+  let config = config_with_policy(&target_path, ConflictPolicy::Overwrite);
+  assert!(
+    rzip_lib::recursive_file_extract(&target_path, &out_path, &config)
+      .unwrap()
+      .is_empty()
+  );
+  assert!(out_path.is_file());
+}
+
+/// Put a directory in the way of `doc_times.txt` in `out_path`, and a file in the way of `dir`.
+fn write_existing_of_another_type(out_path: &Path) {
+  fs::create_dir_all(out_path.join("doc_times.txt/existing")).unwrap();
+  fs::write(out_path.join("dir"), "existing\n").unwrap();
+}
+
+#[test]
+fn test_every_stage_resolves_entries_of_another_type() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  let stages: [(Stage, &str); 4] = [
+    (unpack::compress_tools_unpack, "times_zip.zip"),
+    (unpack::zip_unpack, "times_zip.zip"),
+    (unpack::seven_z_unpack, "times_7z.7z"),
+    (unpack::tar_unpack, "times_tar.tar"),
+  ];
+
+  for (stage, archive_name) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_data_to(temp_dir.path(), "times", archive_name);
+    let unpack_with = |on_conflict| {
+      let out_path = temp_dir.path().join(format!("{on_conflict}"));
+      write_existing_of_another_type(&out_path);
+      let config = config_with_policy(&target_path, on_conflict);
+      let warnings = stage(&target_path, &out_path, &config).unwrap();
+      (warnings, out_path)
+    };
+
+    // Overwriting replaces either
+    let (warnings, out_path) = unpack_with(ConflictPolicy::Overwrite);
+    assert!(warnings.is_empty(), "{archive_name}");
+    assert_eq!(
+      fs::read_to_string(out_path.join("doc_times.txt")).unwrap(),
+      "doc_times\n",
+      "{archive_name}"
+    );
+    assert!(
+      out_path.join("dir/doc_nested.txt").is_file(),
+      "{archive_name}"
+    );
+
+    // Skipping keeps both, along with everything in the skipped directory
+    let (warnings, out_path) = unpack_with(ConflictPolicy::Skip);
+    assert_eq!(warnings.len(), 2, "{archive_name}");
+    assert!(
+      out_path.join("doc_times.txt/existing").is_dir(),
+      "{archive_name}"
+    );
+    assert!(out_path.join("dir").is_file(), "{archive_name}");
+
+    // Renaming unpacks next to them, with a renamed directory's files inside of it
+    let (warnings, out_path) = unpack_with(ConflictPolicy::Rename);
+    assert!(warnings.is_empty(), "{archive_name}");
+    assert!(
+      out_path.join("doc_times.txt/existing").is_dir(),
+      "{archive_name}"
+    );
+    assert!(
+      out_path.join("doc_times (1).txt").is_file(),
+      "{archive_name}"
+    );
+    assert!(out_path.join("dir").is_file(), "{archive_name}");
+    assert!(
+      out_path.join("dir (1)/doc_nested.txt").is_file(),
+      "{archive_name}"
+    );
+  }
+}
+
+#[test]
+fn test_staged_entries_of_another_type() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "times", "times_zip.zip");
+  let extract_with = |on_conflict| {
+    let out_path = temp_dir.path().join(format!("{on_conflict}"));
+    write_existing_of_another_type(&out_path);
+    let config = config_with_policy(&target_path, on_conflict);
+    let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+    (warnings, out_path)
+  };
+
+  // Overwriting replaces either as the output is merged into place
+  let (warnings, out_path) = extract_with(ConflictPolicy::Overwrite);
+  assert!(warnings.is_empty());
+  assert_eq!(
+    fs::read_to_string(out_path.join("doc_times.txt")).unwrap(),
+    "doc_times\n"
+  );
+  assert!(out_path.join("dir/doc_nested.txt").is_file());
+
+  // Merging keeps both, as they're newer
+  let (warnings, out_path) = extract_with(ConflictPolicy::Merge);
+  assert_eq!(warnings.len(), 2);
+  assert!(out_path.join("doc_times.txt/existing").is_dir());
+  assert_eq!(
+    fs::read_to_string(out_path.join("dir")).unwrap(),
+    "existing\n"
+  );
+}
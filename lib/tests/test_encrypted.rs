@@ -0,0 +1,147 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{unpack, ConflictPolicy, RZipError, RZipExtractConfig, RZipWarning};
+use tempfile::TempDir;
+
+fn get_encrypted_data_root() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/encrypted")
+}
+
+/// Copies an encrypted archive to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// encrypted_zip.zip (rzip)
+/// └── doc_encrypted_zip.txt
+/// encrypted_7z.7z (inner)
+/// └── doc_encrypted_7z.txt
+/// nested_encrypted.zip (outer)
+/// └── encrypted_7z.7z (inner)
+///     └── doc_encrypted_7z.txt
+/// ```
+fn copy_encrypted_data_to(temp_dir: &Path, archive_name: &str) {
+  let data_root = get_encrypted_data_root();
+
+  // Copy the item to temporary directory
+  fs::copy(data_root.join(archive_name), temp_dir.join(archive_name)).unwrap();
+}
+
+fn extract_with_passwords(
+  target_path: PathBuf,
+  passwords: &[&str],
+) -> Result<Vec<RZipWarning>, RZipError> {
+  let config = RZipExtractConfig {
+    target_path,
+    out_dir: None,
+    delete_after_extracting: false,
+    passwords: passwords
+      .iter()
+      .map(|password| password.to_string())
+      .collect(),
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config)?;
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config)
+}
+
+#[test]
+fn test_extract_encrypted_zip() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_encrypted_data_to(temp_dir.path(), "encrypted_zip.zip");
+
+  extract_with_passwords(
+    temp_dir.path().join("encrypted_zip.zip"),
+    &["wrong", "rzip"],
+  )
+  .unwrap();
+
+  // Test expected files
+  let doc = temp_dir.path().join("encrypted_zip/doc_encrypted_zip.txt");
+  assert_eq!(fs::read_to_string(doc).unwrap(), "doc_encrypted_zip\n");
+}
+
+#[test]
+fn test_extract_encrypted_7z() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_encrypted_data_to(temp_dir.path(), "encrypted_7z.7z");
+
+  extract_with_passwords(temp_dir.path().join("encrypted_7z.7z"), &["wrong", "inner"]).unwrap();
+
+  // Test expected files
+  let doc = temp_dir.path().join("encrypted_7z/doc_encrypted_7z.txt");
+  assert_eq!(fs::read_to_string(doc).unwrap(), "doc_encrypted_7z\n");
+}
+
+#[test]
+fn test_extract_nested_encrypted() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_encrypted_data_to(temp_dir.path(), "nested_encrypted.zip");
+
+  extract_with_passwords(
+    temp_dir.path().join("nested_encrypted.zip"),
+    &["inner", "outer"],
+  )
+  .unwrap();
+
+  // Test expected files
+  let doc = temp_dir
+    .path()
+    .join("nested_encrypted/encrypted_7z/doc_encrypted_7z.txt");
+  assert_eq!(fs::read_to_string(doc).unwrap(), "doc_encrypted_7z\n");
+}
+
+#[test]
+fn test_extract_encrypted_without_matching_password() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_encrypted_data_to(temp_dir.path(), "encrypted_zip.zip");
+  copy_encrypted_data_to(temp_dir.path(), "encrypted_7z.7z");
+
+  for archive_name in ["encrypted_zip.zip", "encrypted_7z.7z"] {
+    let target_path = temp_dir.path().join(archive_name);
+    for passwords in [&[][..], &["wrong"][..]] {
+      let res = extract_with_passwords(target_path.clone(), passwords);
+      assert!(
+        matches!(&res, Err(RZipError::Encrypted(path)) if *path == target_path),
+        "{archive_name}: {res:?}"
+      );
+    }
+  }
+}
+
+#[test]
+fn test_wrong_passwords_leave_nothing_behind() {
+  for on_conflict in [
+    ConflictPolicy::Overwrite,
+    ConflictPolicy::Skip,
+    ConflictPolicy::Rename,
+  ] {
+    let temp_dir = TempDir::new().unwrap();
+    copy_encrypted_data_to(temp_dir.path(), "encrypted_7z.7z");
+    let target_path = temp_dir.path().join("encrypted_7z.7z");
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      passwords: vec!["wrong".to_string(), "inner".to_string()],
+      on_conflict,
+      ..Default::default()
+    };
+
+    // Only the attempt with the right password is kept, so nothing it unpacks conflicts with what
+    // the wrong one left
+    let out_path = temp_dir.path().join("out");
+    let warnings = unpack::seven_z_unpack(&target_path, &out_path, &config).unwrap();
+    assert!(warnings.is_empty(), "{on_conflict}: {warnings:?}");
+    let names: Vec<_> = fs::read_dir(&out_path)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect();
+    assert_eq!(names, ["doc_encrypted_7z.txt"], "{on_conflict}");
+    assert_eq!(
+      fs::read_to_string(out_path.join("doc_encrypted_7z.txt")).unwrap(),
+      "doc_encrypted_7z\n"
+    );
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+  }
+}
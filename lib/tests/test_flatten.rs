@@ -0,0 +1,183 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{flatten::INDEX_FILE_NAME, ConflictPolicy, OutputLayout, RZipExtractConfig};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set to the input `temp_dir`, then unpacks it flat. The nested
+/// data has the structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip
+/// │   ├── doc1.txt
+/// │   ├── doc2.txt
+/// │   └── doc3.txt
+/// ├── doc_set2.zip
+/// │   ├── doc4.txt
+/// │   ├── doc5.txt
+/// │   └── doc6.txt
+/// ├── doc_set3.zip
+/// │   ├── doc7.txt
+/// │   ├── doc8.txt
+/// │   └── doc9.txt
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// ```
+fn extract_flat(temp_dir: &Path, data_set: &str, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    layout: OutputLayout::Flat,
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  out_path
+}
+
+#[test]
+fn test_flatten_nested_archives() {
+  let temp_dir = TempDir::new().unwrap();
+  let out_path = extract_flat(temp_dir.path(), "nested", "nested.zip");
+
+  // Only the files are left, with the later of two that share a name numbered
+  let mut names: Vec<_> = fs::read_dir(&out_path)
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+    .collect();
+  names.sort();
+  let mut expected = vec![INDEX_FILE_NAME.to_string()];
+  for n in 1..=9 {
+    expected.push(format!("doc{n}.txt"));
+  }
+  for n in 1..=3 {
+    expected.push(format!("doc{n} (1).txt"));
+  }
+  expected.sort();
+  assert_eq!(names, expected);
+
+  let index = fs::read_to_string(out_path.join(INDEX_FILE_NAME)).unwrap();
+  let lines: Vec<_> = index.lines().collect();
+  assert_eq!(lines.len(), 12);
+  assert_eq!(lines[0], "doc1.txt\tnested.zip\tdoc1.txt");
+  assert!(lines.contains(&"doc1 (1).txt\tnested.zip\tdoc_set1.zip\tdoc1.txt"));
+  assert!(lines.contains(&"doc9.txt\tnested.zip\tdoc_set3.zip\tdoc9.txt"));
+
+  // The same archive always flattens the same way
+  let other_dir = TempDir::new().unwrap();
+  let other_out_path = extract_flat(other_dir.path(), "nested", "nested.zip");
+  assert_eq!(
+    fs::read_to_string(other_out_path.join(INDEX_FILE_NAME)).unwrap(),
+    index
+  );
+}
+
+#[test]
+fn test_flatten_keeps_paths_in_index() {
+  let temp_dir = TempDir::new().unwrap();
+  let out_path = extract_flat(temp_dir.path(), "collapse", "project.zip");
+
+  assert_eq!(
+    fs::read_to_string(out_path.join(INDEX_FILE_NAME)).unwrap(),
+    "doc_project.txt\tproject.zip\tproject/doc_project.txt\n\
+     doc_inner.txt\tproject.zip\tproject/inner.tar.gz\tinner/inner/doc_inner.txt\n"
+  );
+  assert_eq!(fs::read_dir(&out_path).unwrap().count(), 3);
+}
+
+/// The link data has the structure:
+///
+/// ```bash
+/// links.tar
+/// ├── docs/
+/// │   ├── doc.txt
+/// │   ├── sub/
+/// │   │   └── link_up -> ../doc.txt
+/// │   └── link_dir -> sub
+/// └── link_chain -> docs/sub/link_up
+/// ```
+#[cfg(unix)]
+#[test]
+fn test_flatten_retargets_links() {
+  use rzip_lib::{RZipWarning, SpecialEntryKind};
+
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join("links.tar");
+  fs::copy(get_data_root("flat_links").join("links.tar"), &target_path).unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    layout: OutputLayout::Flat,
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+  // Links lead to the flattened file rather than out of the output, and a link to a directory
+  // that's no longer there is left out
+  for link in ["link_up", "link_chain"] {
+    let link_path = out_path.join(link);
+    assert_eq!(fs::read_link(&link_path).unwrap(), Path::new("doc.txt"));
+    assert_eq!(fs::read_to_string(&link_path).unwrap(), "doc\n");
+  }
+  assert!(fs::symlink_metadata(out_path.join("link_dir")).is_err());
+  assert_eq!(fs::read_dir(&out_path).unwrap().count(), 4);
+  assert!(matches!(
+    &warnings[..],
+    [RZipWarning::SpecialEntry(archive, entry_name, SpecialEntryKind::Symlink)]
+      if *archive == target_path && entry_name == "docs/link_dir"
+  ));
+}
+
+#[test]
+fn test_flatten_leaves_existing_output() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join("nested.zip");
+  fs::copy(get_data_root("nested").join("nested.zip"), &target_path).unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    layout: OutputLayout::Flat,
+    on_conflict: ConflictPolicy::Merge,
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+
+  // Files that were there before, including one named like a file in the archive
+  fs::create_dir_all(out_path.join("mine")).unwrap();
+  fs::write(out_path.join("mine/old.txt"), "old").unwrap();
+  fs::write(out_path.join("mine/doc1.txt"), "mine").unwrap();
+  fs::write(out_path.join("doc9.txt"), "mine").unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+  // They stay where they were, and the archive's files are named around them
+  assert_eq!(
+    fs::read_to_string(out_path.join("mine/old.txt")).unwrap(),
+    "old"
+  );
+  assert_eq!(
+    fs::read_to_string(out_path.join("mine/doc1.txt")).unwrap(),
+    "mine"
+  );
+  assert_eq!(
+    fs::read_to_string(out_path.join("doc9.txt")).unwrap(),
+    "mine"
+  );
+  assert!(out_path.join("doc9 (1).txt").exists());
+  assert!(!out_path.join("old.txt").exists());
+  assert_eq!(fs::read_dir(&out_path).unwrap().count(), 15);
+
+  let index = fs::read_to_string(out_path.join(INDEX_FILE_NAME)).unwrap();
+  assert_eq!(index.lines().count(), 12);
+  assert!(!index.contains("old.txt"));
+  assert!(index.contains("doc9 (1).txt\tnested.zip\tdoc_set3.zip\tdoc9.txt\n"));
+}
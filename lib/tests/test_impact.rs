@@ -0,0 +1,127 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{
+  preview, ConflictPolicy, DiskUsage, ExtractionImpact, RZipError, RZipExtractConfig,
+};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies the nested archive to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip
+/// │   ├── doc1.txt
+/// │   ├── doc2.txt
+/// │   └── doc3.txt
+/// ├── doc_set2.zip
+/// │   ├── doc4.txt
+/// │   ├── doc5.txt
+/// │   └── doc6.txt
+/// ├── doc_set3.zip
+/// │   ├── doc7.txt
+/// │   ├── doc8.txt
+/// │   └── doc9.txt
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// ```
+fn copy_nested_data_to(temp_dir: &Path) -> PathBuf {
+  let target_path = temp_dir.join("nested.zip");
+  fs::copy(get_data_root("nested").join("nested.zip"), &target_path).unwrap();
+  target_path
+}
+
+fn estimate(target_path: &Path, config: &RZipExtractConfig) -> ExtractionImpact {
+  let out_path = rzip_lib::get_out_path_for_archive(target_path, config).unwrap();
+  let preview = preview::preview_recursive_extract(target_path, &out_path, config).unwrap();
+  ExtractionImpact::estimate(&[preview]).unwrap()
+}
+
+/// The total size of the files at or under `path`.
+fn unpacked_bytes(path: &Path) -> u64 {
+  if !path.is_dir() {
+    return fs::metadata(path).unwrap().len();
+  }
+  fs::read_dir(path)
+    .unwrap()
+    .map(|entry| unpacked_bytes(&entry.unwrap().path()))
+    .sum()
+}
+
+#[test]
+fn test_estimate_matches_extraction() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_nested_data_to(temp_dir.path());
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    ..Default::default()
+  };
+
+  let impact = estimate(&target_path, &config);
+  assert!(impact.overwritten.is_empty());
+  assert!(matches!(
+    &impact.disks[..],
+    [disk] if disk.required_bytes == impact.unpacked_bytes && disk.available_bytes > 0
+  ));
+  impact.check_free_space().unwrap();
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  assert_eq!(impact.unpacked_bytes, unpacked_bytes(&out_path));
+}
+
+#[test]
+fn test_estimate_lists_overwritten_files() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_nested_data_to(temp_dir.path());
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+  // Every file is unpacked again over the one already there
+  let impact = estimate(&target_path, &config);
+  assert_eq!(impact.overwritten.len(), 15);
+  assert!(impact.overwritten.contains(&out_path.join("doc1.txt")));
+  assert!(impact
+    .overwritten
+    .contains(&out_path.join("doc_set1/doc1.txt")));
+
+  // Nothing is replaced when existing output is skipped or renamed around
+  for on_conflict in [ConflictPolicy::Skip, ConflictPolicy::Rename] {
+    let config = RZipExtractConfig {
+      on_conflict,
+      ..config.clone()
+    };
+    assert!(estimate(&target_path, &config).overwritten.is_empty());
+  }
+}
+
+#[test]
+fn test_check_free_space() {
+  let disk = |required_bytes, available_bytes| DiskUsage {
+    path: PathBuf::from("out"),
+    required_bytes,
+    available_bytes,
+  };
+  let impact = ExtractionImpact {
+    unpacked_bytes: 200,
+    overwritten: vec![],
+    disks: vec![disk(100, 100), disk(100, 99)],
+  };
+  assert!(matches!(
+    impact.check_free_space(),
+    Err(RZipError::InsufficientSpace(_, 100, 99))
+  ));
+}
@@ -0,0 +1,137 @@
+use std::{
+  collections::BTreeSet,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{RZipExtractConfig, RZipWarning};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Extract a copy of the nested archive `archive_name` with `config`, returning the warnings and
+/// every path under the output. The nested data has the structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip (406 bytes)
+/// │   ├── doc1.txt
+/// │   ├── doc2.txt
+/// │   └── doc3.txt
+/// ├── doc_set2.zip (406 bytes)
+/// ├── doc_set3.zip (406 bytes)
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// nested_tar_zst.tar.zst
+/// └── packed_zip.zip
+///     └── doc_zip.txt
+/// ```
+fn extract(archive_name: &str, config: RZipExtractConfig) -> (Vec<String>, BTreeSet<PathBuf>) {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join(archive_name);
+  fs::copy(get_data_root("nested").join(archive_name), &target_path).unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    ..config
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  assert!(config.in_memory.paths().is_empty());
+
+  let mut unpacked = BTreeSet::new();
+  collect_paths(&out_path, &out_path, &mut unpacked);
+  (
+    warnings.iter().map(RZipWarning::to_string).collect(),
+    unpacked,
+  )
+}
+
+/// Every path under `path`, relative to `root`.
+fn collect_paths(root: &Path, path: &Path, paths: &mut BTreeSet<PathBuf>) {
+  for entry in fs::read_dir(path).unwrap() {
+    let entry_path = entry.unwrap().path();
+    paths.insert(entry_path.strip_prefix(root).unwrap().to_path_buf());
+    if entry_path.is_dir() {
+      collect_paths(root, &entry_path, paths);
+    }
+  }
+}
+
+#[test]
+fn test_nested_archives_unpack_from_memory() {
+  for archive_name in ["nested.zip", "nested_tar_zst.tar.zst"] {
+    let (_, on_disk) = extract(archive_name, RZipExtractConfig::default());
+    let (warnings, in_memory) = extract(
+      archive_name,
+      RZipExtractConfig {
+        max_in_memory_bytes: Some(1024),
+        ..Default::default()
+      },
+    );
+    assert!(warnings.is_empty());
+
+    // Only the nested archives themselves are missing
+    let nested_archives: BTreeSet<_> = on_disk.difference(&in_memory).collect();
+    assert!(!nested_archives.is_empty());
+    assert!(nested_archives
+      .iter()
+      .all(|path| path.extension().unwrap() == "zip"));
+    assert!(in_memory.is_subset(&on_disk));
+  }
+
+  let (_, in_memory) = extract(
+    "nested.zip",
+    RZipExtractConfig {
+      max_in_memory_bytes: Some(1024),
+      ..Default::default()
+    },
+  );
+  assert!(in_memory.contains(Path::new("doc_set1/doc1.txt")));
+  assert!(!in_memory.contains(Path::new("doc_set1.zip")));
+}
+
+#[test]
+fn test_bigger_archives_are_written_out() {
+  let (_, on_disk) = extract("nested.zip", RZipExtractConfig::default());
+  let (warnings, unpacked) = extract(
+    "nested.zip",
+    RZipExtractConfig {
+      max_in_memory_bytes: Some(100),
+      ..Default::default()
+    },
+  );
+  assert!(warnings.is_empty());
+  assert_eq!(unpacked, on_disk);
+  assert!(unpacked.contains(Path::new("doc_set1.zip")));
+}
+
+#[test]
+fn test_archives_left_packed_are_written_out() {
+  let config = RZipExtractConfig {
+    max_depth: Some(0),
+    ..Default::default()
+  };
+  let (_, on_disk) = extract("nested.zip", config.clone());
+  let (warnings, unpacked) = extract(
+    "nested.zip",
+    RZipExtractConfig {
+      max_in_memory_bytes: Some(1024),
+      ..config
+    },
+  );
+  assert_eq!(unpacked, on_disk);
+  assert!(unpacked.contains(Path::new("doc_set1.zip")));
+  assert_eq!(warnings.len(), 3);
+  for (warning, file_name) in warnings
+    .iter()
+    .zip(["doc_set1.zip", "doc_set2.zip", "doc_set3.zip"])
+  {
+    assert!(warning.contains(file_name));
+  }
+}
@@ -32,6 +32,7 @@ fn test_extract_7z() {
     target_path: temp_dir.path().join("packed_7z.7z"),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -56,6 +57,7 @@ fn test_extract_7z_with_deletion() {
     out_dir: None,
     // Testing deletion
     delete_after_extracting: true,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -94,6 +96,7 @@ fn test_extract_tar() {
     target_path: temp_dir.path().join("packed_tar.tar"),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -131,6 +134,7 @@ fn test_extract_tar_gz() {
     target_path: temp_dir.path().join("packed_tar_gz.tar.gz"),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -138,8 +142,8 @@ fn test_extract_tar_gz() {
 
   // Test expected files
   let packed_tar_gz_tar_gz = temp_dir.path().join("packed_tar_gz.tar.gz");
-  let packed_tar_dir = temp_dir.path().join("packed_tar_gz.tar");
-  let doc_tar_gz = temp_dir.path().join("packed_tar_gz.tar/doc_tar_gz.txt");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_gz");
+  let doc_tar_gz = temp_dir.path().join("packed_tar_gz/doc_tar_gz.txt");
   assert!(packed_tar_gz_tar_gz.exists());
   assert!(packed_tar_dir.exists());
   assert!(doc_tar_gz.exists());
@@ -168,6 +172,7 @@ fn test_extract_tar_xz() {
     target_path: temp_dir.path().join("packed_tar_xz.tar.xz"),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -175,13 +180,257 @@ fn test_extract_tar_xz() {
 
   // Test expected files
   let packed_tar_xz_tar_xz = temp_dir.path().join("packed_tar_xz.tar.xz");
-  let packed_tar_dir = temp_dir.path().join("packed_tar_xz.tar");
-  let doc_tar_xz = temp_dir.path().join("packed_tar_xz.tar/doc_tar_xz.txt");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_xz");
+  let doc_tar_xz = temp_dir.path().join("packed_tar_xz/doc_tar_xz.txt");
   assert!(packed_tar_xz_tar_xz.exists());
   assert!(packed_tar_dir.exists());
   assert!(doc_tar_xz.exists());
 }
 
+/// Copies data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// packed_tar_bz2.tar.bz2
+/// └── doc_tar_bz2.txt
+/// ```
+fn copy_tar_bz2_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let packed_tar_bz2 = data_root.join("packed_tar_bz2.tar.bz2");
+
+  // Copy each item to temporary directory
+  fs::copy(packed_tar_bz2, temp_dir.join("packed_tar_bz2.tar.bz2")).unwrap();
+}
+
+#[test]
+fn test_extract_tar_bz2() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_tar_bz2_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("packed_tar_bz2.tar.bz2"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_tar_bz2_tar_bz2 = temp_dir.path().join("packed_tar_bz2.tar.bz2");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_bz2");
+  let doc_tar_bz2 = temp_dir.path().join("packed_tar_bz2/doc_tar_bz2.txt");
+  assert!(packed_tar_bz2_tar_bz2.exists());
+  assert!(packed_tar_dir.exists());
+  assert!(doc_tar_bz2.exists());
+}
+
+#[test]
+fn test_extract_tbz2() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  let packed_tar_bz2 = data_root.join("packed_tar_bz2.tar.bz2");
+  fs::copy(packed_tar_bz2, temp_dir.path().join("packed_tbz2.tbz2")).unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("packed_tbz2.tbz2"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_tbz2_dir = temp_dir.path().join("packed_tbz2");
+  let doc_tar_bz2 = temp_dir.path().join("packed_tbz2/doc_tar_bz2.txt");
+  assert!(packed_tbz2_dir.exists());
+  assert!(doc_tar_bz2.exists());
+}
+
+/// Copies data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// packed_tar_zst.tar.zst
+/// └── doc_tar_zst.txt
+/// ```
+fn copy_tar_zst_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let packed_tar_zst = data_root.join("packed_tar_zst.tar.zst");
+
+  // Copy each item to temporary directory
+  fs::copy(packed_tar_zst, temp_dir.join("packed_tar_zst.tar.zst")).unwrap();
+}
+
+#[test]
+fn test_extract_tar_zst() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_tar_zst_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("packed_tar_zst.tar.zst"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_tar_zst_tar_zst = temp_dir.path().join("packed_tar_zst.tar.zst");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_zst");
+  let doc_tar_zst = temp_dir.path().join("packed_tar_zst/doc_tar_zst.txt");
+  assert!(packed_tar_zst_tar_zst.exists());
+  assert!(packed_tar_dir.exists());
+  assert!(doc_tar_zst.exists());
+}
+
+/// Copies data to the input `temp_dir`. The data is a single gzip-compressed file:
+///
+/// ```bash
+/// doc_gz.txt.gz
+/// ```
+fn copy_single_gz_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let doc_gz = data_root.join("doc_gz.txt.gz");
+
+  // Copy each item to temporary directory
+  fs::copy(doc_gz, temp_dir.join("doc_gz.txt.gz")).unwrap();
+}
+
+#[test]
+fn test_extract_single_gz() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_single_gz_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("doc_gz.txt.gz"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files, the stream is decompressed to a single file
+  let doc_gz_txt_gz = temp_dir.path().join("doc_gz.txt.gz");
+  let doc_gz = temp_dir.path().join("doc_gz.txt");
+  assert!(doc_gz_txt_gz.exists());
+  assert!(doc_gz.is_file());
+  assert_eq!(fs::read_to_string(doc_gz).unwrap(), "doc_gz\n");
+}
+
+/// Copies data to the input `temp_dir`. The data is a single xz-compressed file:
+///
+/// ```bash
+/// doc_xz.txt.xz
+/// ```
+fn copy_single_xz_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let doc_xz = data_root.join("doc_xz.txt.xz");
+
+  // Copy each item to temporary directory
+  fs::copy(doc_xz, temp_dir.join("doc_xz.txt.xz")).unwrap();
+}
+
+#[test]
+fn test_extract_single_xz() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_single_xz_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("doc_xz.txt.xz"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files, the stream is decompressed to a single file
+  let doc_xz_txt_xz = temp_dir.path().join("doc_xz.txt.xz");
+  let doc_xz = temp_dir.path().join("doc_xz.txt");
+  assert!(doc_xz_txt_xz.exists());
+  assert!(doc_xz.is_file());
+  assert_eq!(fs::read_to_string(doc_xz).unwrap(), "doc_xz\n");
+}
+
+/// Copies data to the input `temp_dir`. The data is a single bzip2-compressed file:
+///
+/// ```bash
+/// doc_bz2.txt.bz2
+/// ```
+fn copy_single_bz2_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let doc_bz2 = data_root.join("doc_bz2.txt.bz2");
+
+  // Copy each item to temporary directory
+  fs::copy(doc_bz2, temp_dir.join("doc_bz2.txt.bz2")).unwrap();
+}
+
+#[test]
+fn test_extract_single_bz2() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_single_bz2_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("doc_bz2.txt.bz2"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files, the stream is decompressed to a single file
+  let doc_bz2_txt_bz2 = temp_dir.path().join("doc_bz2.txt.bz2");
+  let doc_bz2 = temp_dir.path().join("doc_bz2.txt");
+  assert!(doc_bz2_txt_bz2.exists());
+  assert!(doc_bz2.is_file());
+  assert_eq!(fs::read_to_string(doc_bz2).unwrap(), "doc_bz2\n");
+}
+
+/// Copies data to the input `temp_dir`. The data is a single Zstandard-compressed file:
+///
+/// ```bash
+/// doc_zst.txt.zst
+/// ```
+fn copy_single_zst_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let doc_zst = data_root.join("doc_zst.txt.zst");
+
+  // Copy each item to temporary directory
+  fs::copy(doc_zst, temp_dir.join("doc_zst.txt.zst")).unwrap();
+}
+
+#[test]
+fn test_extract_single_zst() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_single_zst_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("doc_zst.txt.zst"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files, the stream is decompressed to a single file
+  let doc_zst_txt_zst = temp_dir.path().join("doc_zst.txt.zst");
+  let doc_zst = temp_dir.path().join("doc_zst.txt");
+  assert!(doc_zst_txt_zst.exists());
+  assert!(doc_zst.is_file());
+  assert_eq!(fs::read_to_string(doc_zst).unwrap(), "doc_zst\n");
+}
+
 /// Copies data to the input `temp_dir`. The data has the structure:
 ///
 /// ```bash
@@ -205,6 +454,7 @@ fn test_extract_zip() {
     target_path: temp_dir.path().join("packed_zip.zip"),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -242,6 +492,7 @@ fn test_extract_rar() {
     target_path: temp_dir.path().join("packed_rar.rar"),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
@@ -255,3 +506,65 @@ fn test_extract_rar() {
   assert!(packed_rar_dir.exists());
   assert!(doc_rar.exists());
 }
+
+#[test]
+fn test_extract_misnamed_zip() {
+  let temp_dir = TempDir::new().unwrap();
+  let temp_dir_path_buf = temp_dir.path().to_path_buf();
+  let data_root = get_individual_data_root();
+  fs::copy(
+    data_root.join("packed_zip.zip"),
+    temp_dir.path().join("packed_zip.bin"),
+  )
+  .unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir_path_buf.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  // The archive is discovered by its contents rather than its extension
+  let archives = rzip_lib::get_archives_in_dir(&temp_dir_path_buf).unwrap();
+  assert_eq!(archives, vec![temp_dir.path().join("packed_zip.bin")]);
+
+  let out_path = rzip_lib::get_out_path_for_archive(&archives[0], &config).unwrap();
+  rzip_lib::recursive_file_extract(&archives[0], &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_zip_dir = temp_dir.path().join("packed_zip");
+  let doc_zip = temp_dir.path().join("packed_zip/doc_zip.txt");
+  assert!(packed_zip_dir.exists());
+  assert!(doc_zip.exists());
+}
+
+#[test]
+fn test_extract_extensionless_zip() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  fs::copy(
+    data_root.join("packed_zip.zip"),
+    temp_dir.path().join("packed_zip"),
+  )
+  .unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("packed_zip"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  assert!(rzip_lib::is_archive_filetype(&config.target_path));
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files, the output directory can't share the archive's name
+  let packed_zip = temp_dir.path().join("packed_zip");
+  let packed_zip_dir = temp_dir.path().join("packed_zip.d");
+  let doc_zip = temp_dir.path().join("packed_zip.d/doc_zip.txt");
+  assert!(packed_zip.is_file());
+  assert!(packed_zip_dir.exists());
+  assert!(doc_zip.exists());
+}
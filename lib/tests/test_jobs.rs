@@ -0,0 +1,138 @@
+use std::{
+  collections::BTreeSet,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{RZipExtractConfig, RZipWarning};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies several copies of each nested archive to the input `temp_dir`, with `nested.zip` nested
+/// once more in a directory. The nested data has the structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip
+/// │   ├── doc1.txt
+/// │   ├── doc2.txt
+/// │   └── doc3.txt
+/// ├── doc_set2.zip
+/// │   ├── doc4.txt
+/// │   ├── doc5.txt
+/// │   └── doc6.txt
+/// ├── doc_set3.zip
+/// │   ├── doc7.txt
+/// │   ├── doc8.txt
+/// │   └── doc9.txt
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// nested_tar_zst.tar.zst
+/// └── packed_zip.zip
+///     └── doc_zip.txt
+/// packed_tar_gz.tar.gz.gz
+/// └── packed_tar_gz.tar.gz
+///     └── doc_tar_gz.txt
+/// ```
+fn copy_many_archives_to(temp_dir: &Path) {
+  for copy in 0..4 {
+    for archive_name in [
+      "nested.zip",
+      "nested_tar_zst.tar.zst",
+      "packed_tar_gz.tar.gz.gz",
+    ] {
+      let data_path = get_data_root("nested").join(archive_name);
+      fs::copy(data_path, temp_dir.join(format!("{copy}_{archive_name}"))).unwrap();
+    }
+  }
+  fs::create_dir(temp_dir.join("dir")).unwrap();
+  let nested_zip = get_data_root("nested").join("nested.zip");
+  fs::copy(nested_zip, temp_dir.join("dir/nested.zip")).unwrap();
+}
+
+/// Extract every archive in a copy of the data `jobs` at a time, returning the archives in the
+/// order they were reported, the warnings and every path unpacked.
+fn extract_with_jobs(
+  jobs: usize,
+  max_depth: Option<usize>,
+) -> (Vec<PathBuf>, Vec<String>, BTreeSet<PathBuf>) {
+  let temp_dir = TempDir::new().unwrap();
+  copy_many_archives_to(temp_dir.path());
+  let config = RZipExtractConfig {
+    target_path: temp_dir.path().to_path_buf(),
+    max_depth,
+    jobs,
+    ..Default::default()
+  };
+  let archives = rzip_lib::get_archives_in_dir(&config.target_path).unwrap();
+  let out_paths = rzip_lib::get_out_paths_for_archives(&archives, &config).unwrap();
+
+  let mut reported = vec![];
+  let mut warnings = vec![];
+  rzip_lib::extract_archives(&archives, &out_paths, &config, |path, result| {
+    let relative_path = path.strip_prefix(temp_dir.path()).unwrap();
+    reported.push(relative_path.to_path_buf());
+    warnings.extend(result.unwrap().iter().map(RZipWarning::to_string));
+  })
+  .unwrap();
+  assert_eq!(reported.len(), archives.len());
+
+  let mut unpacked = BTreeSet::new();
+  collect_paths(temp_dir.path(), temp_dir.path(), &mut unpacked);
+  let root = temp_dir.path().to_string_lossy().into_owned();
+  let warnings = warnings.iter().map(|w| w.replace(&root, "")).collect();
+  (reported, warnings, unpacked)
+}
+
+/// Every path under `path`, relative to `root`.
+fn collect_paths(root: &Path, path: &Path, paths: &mut BTreeSet<PathBuf>) {
+  for entry in fs::read_dir(path).unwrap() {
+    let entry_path = entry.unwrap().path();
+    paths.insert(entry_path.strip_prefix(root).unwrap().to_path_buf());
+    if entry_path.is_dir() {
+      collect_paths(root, &entry_path, paths);
+    }
+  }
+}
+
+#[test]
+fn test_jobs_extract_the_same_as_one() {
+  let (reported, warnings, unpacked) = extract_with_jobs(1, None);
+  assert_eq!(reported.len(), 13);
+  assert!(warnings.is_empty());
+  assert!(unpacked.contains(Path::new("3_nested/doc_set3/doc9.txt")));
+  assert!(unpacked.contains(Path::new("dir/nested/doc_set1/doc1.txt")));
+
+  // Archives are reported in the order they're found in, which is path order
+  let mut sorted = reported.clone();
+  sorted.sort();
+  assert_eq!(reported, sorted);
+
+  assert_eq!(extract_with_jobs(4, None), (reported, warnings, unpacked));
+}
+
+#[test]
+fn test_jobs_report_warnings_in_order() {
+  let (reported, warnings, unpacked) = extract_with_jobs(1, Some(0));
+  let nested_warnings: Vec<_> = warnings
+    .iter()
+    .filter(|warning| warning.contains("0_nested") && warning.contains("doc_set"))
+    .collect();
+  assert_eq!(nested_warnings.len(), 3);
+  assert!(nested_warnings[0].contains("doc_set1.zip"));
+  assert!(nested_warnings[2].contains("doc_set3.zip"));
+
+  for _ in 0..4 {
+    let parallel = extract_with_jobs(8, Some(0));
+    assert_eq!(
+      parallel,
+      (reported.clone(), warnings.clone(), unpacked.clone())
+    );
+  }
+}
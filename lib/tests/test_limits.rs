@@ -0,0 +1,241 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{
+  unpack, ExtractLimits, LimitViolation, RZipError, RZipExtractConfig, RZipProcessingError,
+  RZipWarning,
+};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set to the input `temp_dir`. The limits data has the
+/// structure:
+///
+/// ```bash
+/// zeros.zip
+/// └── zeros.bin (1 MiB of zeros)
+/// nested_zeros.zip
+/// └── zeros.zip
+///     └── zeros.bin (1 MiB of zeros)
+/// ```
+fn copy_data_to(temp_dir: &Path, data_set: &str, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+fn extract_with_limits(
+  target_path: &Path,
+  limits: ExtractLimits,
+) -> Result<Vec<RZipWarning>, RZipError> {
+  let config = RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    limits,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config)?;
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config)
+}
+
+#[test]
+fn test_ratio_limit() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "limits", "zeros.zip");
+
+  let res = extract_with_limits(
+    &target_path,
+    ExtractLimits {
+      max_ratio: Some(100),
+      ..Default::default()
+    },
+  );
+  assert!(matches!(
+    res,
+    Err(RZipError::LimitExceeded(path, LimitViolation::Ratio(100))) if path == target_path
+  ));
+
+  // Nothing is left behind
+  assert!(!temp_dir.path().join("zeros").exists());
+}
+
+#[test]
+fn test_archive_bytes_limit() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "limits", "zeros.zip");
+
+  let res = extract_with_limits(
+    &target_path,
+    ExtractLimits {
+      max_archive_bytes: Some(1 << 19),
+      ..Default::default()
+    },
+  );
+  assert!(matches!(
+    res,
+    Err(RZipError::LimitExceeded(_, LimitViolation::ArchiveBytes(_)))
+  ));
+  assert!(!temp_dir.path().join("zeros").exists());
+
+  // The archive unpacks within a large enough limit
+  extract_with_limits(
+    &target_path,
+    ExtractLimits {
+      max_archive_bytes: Some(1 << 20),
+      ..Default::default()
+    },
+  )
+  .unwrap();
+  assert_eq!(
+    fs::metadata(temp_dir.path().join("zeros/zeros.bin"))
+      .unwrap()
+      .len(),
+    1 << 20
+  );
+}
+
+#[test]
+fn test_entries_limit() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "nested", "nested.zip");
+
+  let res = extract_with_limits(
+    &target_path,
+    ExtractLimits {
+      max_entries: Some(5),
+      ..Default::default()
+    },
+  );
+  assert!(matches!(
+    res,
+    Err(RZipError::LimitExceeded(_, LimitViolation::Entries(5)))
+  ));
+  assert!(!temp_dir.path().join("nested").exists());
+}
+
+#[test]
+fn test_nested_limit() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "limits", "nested_zeros.zip");
+
+  // The outer archive is stored, so only the nested one exceeds the ratio
+  let res = extract_with_limits(
+    &target_path,
+    ExtractLimits {
+      max_ratio: Some(100),
+      ..Default::default()
+    },
+  );
+  let nested_path = temp_dir.path().join("nested_zeros/zeros.zip");
+  assert!(matches!(
+    res,
+    Err(RZipError::LimitExceeded(path, LimitViolation::Ratio(100))) if path == nested_path
+  ));
+  assert!(nested_path.exists());
+  assert!(!temp_dir.path().join("nested_zeros/zeros").exists());
+}
+
+#[test]
+fn test_run_bytes_limit() {
+  let temp_dir = TempDir::new().unwrap();
+  let first_path = copy_data_to(temp_dir.path(), "limits", "zeros.zip");
+  let second_path = temp_dir.path().join("more_zeros.zip");
+  fs::copy(&first_path, &second_path).unwrap();
+
+  // Both archives are extracted with the same config, so they share the run's limit
+  let config = RZipExtractConfig {
+    target_path: temp_dir.path().to_path_buf(),
+    limits: ExtractLimits {
+      max_run_bytes: Some(3 << 19),
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&first_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&first_path, &out_path, &config).unwrap();
+  assert_eq!(config.usage.bytes(), 1 << 20);
+
+  let out_path = rzip_lib::get_out_path_for_archive(&second_path, &config).unwrap();
+  let res = rzip_lib::recursive_file_extract(&second_path, &out_path, &config);
+  assert!(matches!(
+    res,
+    Err(RZipError::LimitExceeded(_, LimitViolation::RunBytes(_)))
+  ));
+  assert!(!out_path.exists());
+}
+
+#[test]
+fn test_every_stage_enforces_limits() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  let stages: [(Stage, &str, &str); 13] = [
+    (unpack::compress_tools_unpack, "limits", "zeros.zip"),
+    (unpack::zip_unpack, "limits", "zeros.zip"),
+    (unpack::seven_z_unpack, "encrypted", "encrypted_7z.7z"),
+    (unpack::flake2_unpack, "indiv", "packed_tar_gz.tar.gz"),
+    (unpack::bzip2_unpack, "indiv", "packed_tar_bz2.tar.bz2"),
+    (unpack::zstd_unpack, "indiv", "packed_tar_zst.tar.zst"),
+    (unpack::xz_unpack, "indiv", "packed_tar_xz.tar.xz"),
+    (unpack::tar_unpack, "indiv", "packed_tar.tar"),
+    (unpack::compress_tools_decompress, "indiv", "doc_gz.txt.gz"),
+    (unpack::flake2_decompress, "indiv", "doc_gz.txt.gz"),
+    (unpack::bzip2_decompress, "indiv", "doc_bz2.txt.bz2"),
+    (unpack::xz_decompress, "indiv", "doc_xz.txt.xz"),
+    (unpack::zstd_decompress, "indiv", "doc_zst.txt.zst"),
+  ];
+
+  for (stage, data_set, archive_name) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_data_to(temp_dir.path(), data_set, archive_name);
+    let out_path = temp_dir.path().join("out");
+
+    // Every fixture unpacks to more than 4 bytes
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      passwords: vec!["inner".to_string()],
+      limits: ExtractLimits {
+        max_archive_bytes: Some(4),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let res = stage(&target_path, &out_path, &config);
+    assert!(
+      matches!(
+        res,
+        Err(RZipProcessingError::LimitExceeded(
+          LimitViolation::ArchiveBytes(4)
+        ))
+      ),
+      "{archive_name}: {res:?}"
+    );
+
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      passwords: vec!["inner".to_string()],
+      limits: ExtractLimits {
+        max_entries: Some(0),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let res = stage(&target_path, &out_path, &config);
+    assert!(
+      matches!(
+        res,
+        Err(RZipProcessingError::LimitExceeded(LimitViolation::Entries(
+          0
+        )))
+      ),
+      "{archive_name}: {res:?}"
+    );
+  }
+}
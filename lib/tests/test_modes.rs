@@ -0,0 +1,219 @@
+// Permissions and owners are only set on unix
+#![cfg(unix)]
+
+use std::{
+  fs,
+  os::unix::fs::{MetadataExt, PermissionsExt},
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{unpack, OwnershipPolicy, RZipExtractConfig, RZipProcessingError, RZipWarning};
+use tempfile::TempDir;
+
+fn get_modes_data_root() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/modes")
+}
+
+/// Copies an archive with unusual permissions to the input `temp_dir`. Each archive has the
+/// entries:
+///
+/// ```bash
+/// setuid.sh (4755)
+/// private/ (700)
+/// private/doc_modes.txt (640)
+/// shared/ (1777)
+/// ```
+///
+/// The tarballs record every entry as owned by uid and gid 1234.
+fn copy_modes_data_to(temp_dir: &Path, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_modes_data_root().join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+/// The permission bits of each entry in the modes archives.
+fn entry_modes(out_path: &Path) -> Vec<u32> {
+  ["setuid.sh", "private", "private/doc_modes.txt", "shared"]
+    .iter()
+    .map(|name| {
+      fs::metadata(out_path.join(name))
+        .unwrap()
+        .permissions()
+        .mode()
+        & 0o7777
+    })
+    .collect()
+}
+
+#[test]
+fn test_every_stage_normalises_modes() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  let stages: [(Stage, &str); 11] = [
+    (unpack::compress_tools_unpack, "modes_zip.zip"),
+    (unpack::compress_tools_unpack, "modes_7z.7z"),
+    (unpack::compress_tools_unpack, "modes_tar.tar"),
+    (unpack::compress_tools_unpack, "modes_tar_gz.tar.gz"),
+    (unpack::zip_unpack, "modes_zip.zip"),
+    (unpack::seven_z_unpack, "modes_7z.7z"),
+    (unpack::flake2_unpack, "modes_tar_gz.tar.gz"),
+    (unpack::bzip2_unpack, "modes_tar_bz2.tar.bz2"),
+    (unpack::zstd_unpack, "modes_tar_zst.tar.zst"),
+    (unpack::xz_unpack, "modes_tar_xz.tar.xz"),
+    (unpack::tar_unpack, "modes_tar.tar"),
+  ];
+
+  for (stage, archive_name) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_modes_data_to(temp_dir.path(), archive_name);
+
+    // Setuid, setgid and sticky bits are cleared whatever the umask
+    for (umask, expected) in [
+      (0o000, [0o755, 0o700, 0o640, 0o777]),
+      (0o022, [0o755, 0o700, 0o640, 0o755]),
+      (0o077, [0o700, 0o700, 0o600, 0o700]),
+    ] {
+      let out_path = temp_dir.path().join(format!("out_{umask:o}"));
+      let config = RZipExtractConfig {
+        target_path: target_path.clone(),
+        umask,
+        ..Default::default()
+      };
+      stage(&target_path, &out_path, &config).unwrap();
+      assert_eq!(
+        entry_modes(&out_path),
+        expected,
+        "{archive_name} with umask {umask:o}"
+      );
+    }
+  }
+}
+
+#[test]
+fn test_ownership() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_modes_data_to(temp_dir.path(), "modes_tar.tar");
+  let owner = |out_path: &Path| {
+    let metadata = fs::metadata(out_path.join("private/doc_modes.txt")).unwrap();
+    (metadata.uid(), metadata.gid())
+  };
+  let unpack_with = |ownership, out_name| {
+    let out_path = temp_dir.path().join(out_name);
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      ownership,
+      ..Default::default()
+    };
+    unpack::tar_unpack(&target_path, &out_path, &config).unwrap();
+    owner(&out_path)
+  };
+
+  // Files belong to whoever unpacks them by default, like the copied archive
+  let metadata = fs::metadata(&target_path).unwrap();
+  let current = (metadata.uid(), metadata.gid());
+  assert_eq!(unpack_with(OwnershipPolicy::Ignore, "ignored"), current);
+
+  // Anyone can give files to themselves
+  let remap = OwnershipPolicy::Remap {
+    uid: Some(current.0),
+    gid: Some(current.1),
+  };
+  assert_eq!(unpack_with(remap, "remapped"), current);
+
+  // Only root can give them to anyone else, and anyone else keeps them with a warning
+  if current.0 != 0 {
+    let out_path = temp_dir.path().join("kept");
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      ownership: OwnershipPolicy::Preserve,
+      ..Default::default()
+    };
+    let warnings = unpack::tar_unpack(&target_path, &out_path, &config).unwrap();
+    assert!(matches!(
+      &warnings[..],
+      [RZipWarning::KeptOwners(path)] if *path == target_path
+    ));
+    assert_eq!(owner(&out_path), current);
+    return;
+  }
+  assert_eq!(
+    unpack_with(OwnershipPolicy::Preserve, "preserved"),
+    (1234, 1234)
+  );
+
+  // An owner that isn't given is left as it is
+  let remap = OwnershipPolicy::Remap {
+    uid: Some(4321),
+    gid: None,
+  };
+  assert_eq!(unpack_with(remap, "remapped_uid"), (4321, current.1));
+}
+
+/// Give the directories under `path` write permission again, so that they can be cleaned up.
+fn unlock_dirs(path: &Path) {
+  for dir in [path.join("locked/sub"), path.join("locked")] {
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+  }
+}
+
+#[test]
+fn test_read_only_directories_are_placed() {
+  for out_path_exists in [false, true] {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_modes_data_to(temp_dir.path(), "readonly.tar");
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      ..Default::default()
+    };
+    let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+    if out_path_exists {
+      fs::create_dir(&out_path).unwrap();
+      fs::write(out_path.join("other.txt"), "other\n").unwrap();
+    }
+
+    // The directories are only made read-only once they're in place, so even someone who isn't
+    // root can move them out of the staging path
+    rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+    for dir in ["locked", "locked/sub"] {
+      let mode = fs::metadata(out_path.join(dir))
+        .unwrap()
+        .permissions()
+        .mode();
+      assert_eq!(mode & 0o7777, 0o555, "{dir}");
+    }
+    let doc = fs::read_to_string(out_path.join("locked/sub/doc_sub.txt")).unwrap();
+    assert_eq!(doc, "sub\n");
+    assert_eq!(out_path.join("other.txt").exists(), out_path_exists);
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    unlock_dirs(&out_path);
+  }
+}
+
+#[test]
+fn test_read_only_root_collapses() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_modes_data_to(temp_dir.path(), "readonly.tar");
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    collapse_single_root: true,
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+
+  // The root's contents move up even though it's read-only, and its mode goes to the output
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  let mut names: Vec<_> = fs::read_dir(&out_path)
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name())
+    .collect();
+  names.sort();
+  assert_eq!(names, ["doc_locked.txt", "sub"]);
+  for dir in [out_path.clone(), out_path.join("sub")] {
+    let mode = fs::metadata(&dir).unwrap().permissions().mode();
+    assert_eq!(mode & 0o7777, 0o555, "{}", dir.display());
+  }
+  assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+  for dir in [out_path.join("sub"), out_path] {
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+  }
+}
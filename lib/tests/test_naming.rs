@@ -0,0 +1,133 @@
+use std::{
+  fs::{self, File},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use rzip_lib::{NameTemplate, RZipExtractConfig};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set into `dir` under the input `temp_dir`, then unpacks it
+/// with the output names from `name_template`. The nested data has the structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip
+/// │   ├── doc1.txt
+/// │   ├── doc2.txt
+/// │   └── doc3.txt
+/// ├── doc_set2.zip
+/// │   ├── doc4.txt
+/// │   ├── doc5.txt
+/// │   └── doc6.txt
+/// ├── doc_set3.zip
+/// │   ├── doc7.txt
+/// │   ├── doc8.txt
+/// │   └── doc9.txt
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// ```
+fn extract_with_template(
+  temp_dir: &Path,
+  dir: &str,
+  data_set: &str,
+  archive_name: &str,
+  name_template: &str,
+) -> PathBuf {
+  fs::create_dir_all(temp_dir.join(dir)).unwrap();
+  let target_path = temp_dir.join(dir).join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  File::options()
+    .write(true)
+    .open(&target_path)
+    .unwrap()
+    .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+    .unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    name_template: name_template.parse().unwrap(),
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  out_path
+}
+
+#[test]
+fn test_name_templates() {
+  let temp_dir = TempDir::new().unwrap();
+  let root = temp_dir.path();
+
+  let out_path =
+    extract_with_template(root, "ext", "indiv", "packed_tar_gz.tar.gz", "{stem}_{ext}");
+  assert_eq!(out_path, root.join("ext/packed_tar_gz_tar.gz"));
+  assert!(out_path.join("doc_tar_gz.txt").exists());
+
+  let out_path = extract_with_template(root, "date", "indiv", "packed_zip.zip", "{stem}-{date}");
+  assert_eq!(out_path, root.join("date/packed_zip-2001-09-09"));
+
+  let out_path = extract_with_template(root, "case", "indiv", "packed_7z.7z", "{parent}/{stem}");
+  assert_eq!(out_path, root.join("case/case/packed_7z"));
+  assert!(out_path.join("doc_7z.txt").exists());
+
+  // The default is the archive's stem, with `.d` added when that's the archive's own name
+  let out_path = extract_with_template(root, "plain", "indiv", "packed_zip.zip", "{stem}");
+  assert_eq!(out_path, root.join("plain/packed_zip"));
+  assert_eq!(
+    NameTemplate::default()
+      .render(Path::new("plain/packed_zip"))
+      .unwrap(),
+    Path::new("packed_zip.d")
+  );
+}
+
+#[test]
+fn test_name_template_applies_to_nested_archives() {
+  let temp_dir = TempDir::new().unwrap();
+  let hash8 = |path: &Path| {
+    let hash = Sha256::digest(fs::read(path).unwrap());
+    hash[..4]
+      .iter()
+      .map(|b| format!("{b:02x}"))
+      .collect::<String>()
+  };
+
+  let out_path = extract_with_template(temp_dir.path(), "hash", "nested", "nested.zip", "{hash8}");
+  let nested_zip = get_data_root("nested").join("nested.zip");
+  assert_eq!(
+    out_path,
+    temp_dir.path().join("hash").join(hash8(&nested_zip))
+  );
+  let doc_set1_zip = get_data_root("series").join("doc_set1.zip");
+  assert!(out_path
+    .join(hash8(&doc_set1_zip))
+    .join("doc1.txt")
+    .exists());
+}
+
+#[test]
+fn test_dot_stems_stay_in_output() {
+  let temp_dir = TempDir::new().unwrap();
+  let root = temp_dir.path();
+
+  // Each nested `...zip` has a stem of `..`, so its output is named after the whole archive
+  let out_path = extract_with_template(root, "a/b/work", "dot_names", "outer.zip", "{stem}");
+  assert_eq!(out_path, root.join("a/b/work/outer"));
+  assert!(out_path.join("...zip.d/...zip.d/evil.txt").exists());
+  assert!(!root.join("a/b/evil.txt").exists());
+  assert!(!root.join("a/b/work/evil.txt").exists());
+
+  for template in ["{stem}", "{stem}/{ext}", "x/{stem}"] {
+    let template: NameTemplate = template.parse().unwrap();
+    let output_name = template.render(Path::new("work/...zip")).unwrap();
+    assert_eq!(output_name, Path::new("...zip.d"));
+  }
+}
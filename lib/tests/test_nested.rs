@@ -3,6 +3,7 @@ use std::{
   path::{Path, PathBuf},
 };
 
+use rzip_lib::RZipWarning;
 use tempfile::TempDir;
 
 /// Copies "nested" data from test data to the `temp_dir` provided. The data has this structure:
@@ -42,6 +43,7 @@ fn test_nested_no_out_dir() {
     target_path: target_path.clone(),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
@@ -99,6 +101,7 @@ fn test_nested_with_deletion() {
     target_path: target_path.clone(),
     out_dir: None,
     delete_after_extracting: true,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
@@ -160,6 +163,7 @@ fn test_nested_with_out_dir() {
     target_path: target_path.clone(),
     out_dir: Some(out_dir_buf.clone()),
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
@@ -206,3 +210,169 @@ fn test_nested_with_out_dir() {
   assert!(doc2.exists());
   assert!(doc3.exists());
 }
+
+/// Copies nested Zstandard data from test data to the `temp_dir` provided. The data has this
+/// structure:
+///
+/// ```bash
+/// nested_tar_zst.tar.zst
+/// └── packed_zip.zip
+///     └── doc_zip.txt
+/// ```
+fn copy_nested_tar_zst_data_to(temp_dir: &Path) {
+  let series_data_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/nested");
+  let nested = series_data_root.join("nested_tar_zst.tar.zst");
+
+  // Copy each item to temporary directory
+  fs::copy(nested, temp_dir.join("nested_tar_zst.tar.zst")).unwrap();
+}
+
+#[test]
+fn test_nested_tar_zst() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join("nested_tar_zst.tar.zst");
+  copy_nested_tar_zst_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: true,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+  // Test the existence of each expected file
+  let nested = temp_dir.path().join("nested_tar_zst");
+  let packed_zip_zip = nested.join("packed_zip.zip");
+  let packed_zip = nested.join("packed_zip");
+  let doc_zip = nested.join("packed_zip/doc_zip.txt");
+  // Do assertions
+  // These files should have been deleted
+  assert!(!target_path.exists());
+  assert!(!packed_zip_zip.exists());
+  // These should still exist
+  assert!(nested.exists());
+  assert!(packed_zip.exists());
+  assert!(doc_zip.exists());
+}
+
+/// Copies a doubly compressed tarball from test data to the `temp_dir` provided. The data has this
+/// structure:
+///
+/// ```bash
+/// packed_tar_gz.tar.gz.gz
+/// └── packed_tar_gz.tar.gz
+///     └── doc_tar_gz.txt
+/// ```
+fn copy_nested_tar_gz_gz_data_to(temp_dir: &Path) {
+  let series_data_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/nested");
+  let nested = series_data_root.join("packed_tar_gz.tar.gz.gz");
+
+  // Copy each item to temporary directory
+  fs::copy(nested, temp_dir.join("packed_tar_gz.tar.gz.gz")).unwrap();
+}
+
+#[test]
+fn test_nested_tar_gz_gz() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join("packed_tar_gz.tar.gz.gz");
+  copy_nested_tar_gz_gz_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: true,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+  // Test the existence of each expected file
+  let packed_tar_gz_tar_gz = temp_dir.path().join("packed_tar_gz.tar.gz");
+  let packed_tar_gz = temp_dir.path().join("packed_tar_gz");
+  let doc_tar_gz = temp_dir.path().join("packed_tar_gz/doc_tar_gz.txt");
+  // Do assertions
+  // These files should have been deleted
+  assert!(!target_path.exists());
+  assert!(!packed_tar_gz_tar_gz.exists());
+  // These should still exist
+  assert!(packed_tar_gz.exists());
+  assert!(doc_tar_gz.exists());
+}
+
+#[test]
+fn test_nested_with_max_depth() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = temp_dir.path().join("nested.zip");
+  copy_nested_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: true,
+    max_depth: Some(0),
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+  // The nested archives are left packed, and kept despite the deletion setting
+  let nested = temp_dir.path().join("nested");
+  assert_eq!(warnings.len(), 3);
+  for doc_set in ["doc_set1", "doc_set2", "doc_set3"] {
+    let doc_set_zip = nested.join(format!("{doc_set}.zip"));
+    assert!(warnings
+      .iter()
+      .any(|w| matches!(w, RZipWarning::MaxDepth(path, 0) if *path == doc_set_zip)));
+    assert!(doc_set_zip.exists());
+    assert!(!nested.join(doc_set).exists());
+  }
+  assert!(!target_path.exists());
+  assert!(nested.join("doc1.txt").exists());
+}
+
+#[test]
+fn test_nested_undecodable_left_packed() {
+  for max_in_memory_bytes in [None, Some(1 << 20)] {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = temp_dir.path().join("undecodable.zip");
+    let data_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/nested");
+    fs::copy(data_root.join("undecodable.zip"), &target_path).unwrap();
+
+    let config = rzip_lib::RZipExtractConfig {
+      target_path: target_path.clone(),
+      delete_after_extracting: true,
+      max_in_memory_bytes,
+      ..Default::default()
+    };
+    let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+    let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+
+    // Files that only look like archives are kept as they are, and packages are never unpacked
+    let broken = out_path.join("broken.zip");
+    assert!(
+      matches!(&warnings[..], [RZipWarning::Undecodable(path)] if *path == broken),
+      "{warnings:?}"
+    );
+    assert_eq!(
+      fs::read(broken).unwrap(),
+      b"PK\x03\x04 but no zip after all\n"
+    );
+    assert_eq!(
+      fs::read_to_string(out_path.join("notes.gz")).unwrap(),
+      "plain notes\n"
+    );
+    assert!(!out_path.join("notes").exists());
+    assert!(out_path.join("app.ipa").is_file());
+    assert!(!out_path.join("app").exists());
+    assert_eq!(
+      fs::read_to_string(out_path.join("doc.txt")).unwrap(),
+      "doc\n"
+    );
+    assert!(!target_path.exists());
+  }
+}
@@ -0,0 +1,122 @@
+use std::{
+  fs::{self, File},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use rzip_lib::{ExtractionPlan, OutputLayout, RZipError, RZipExtractConfig};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies the nested data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip
+/// ├── doc_set2.zip
+/// ├── doc_set3.zip
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// nested_tar_zst.tar.zst
+/// └── packed_zip.zip
+///     └── doc_zip.txt
+/// packed_tar_gz.tar.gz.gz
+/// └── packed_tar_gz.tar.gz
+///     └── doc_tar_gz.txt
+/// ```
+fn copy_nested_data_to(temp_dir: &Path) {
+  for archive_name in [
+    "nested.zip",
+    "nested_tar_zst.tar.zst",
+    "packed_tar_gz.tar.gz.gz",
+  ] {
+    let data_path = get_data_root("nested").join(archive_name);
+    fs::copy(data_path, temp_dir.join(archive_name)).unwrap();
+  }
+}
+
+#[test]
+fn test_plan_round_trip() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_nested_data_to(temp_dir.path());
+  let config = RZipExtractConfig {
+    target_path: temp_dir.path().to_path_buf(),
+    passwords: vec!["hunter2".to_string()],
+    layout: OutputLayout::Flat,
+    name_template: "{stem}-{hash8}".parse().unwrap(),
+    ..Default::default()
+  };
+
+  let plan = ExtractionPlan::new(&config).unwrap();
+  let archives: Vec<_> = plan.archives.iter().map(|a| a.path.clone()).collect();
+  let out_paths: Vec<_> = plan.archives.iter().map(|a| a.out_path.clone()).collect();
+  assert_eq!(archives.len(), 3);
+  assert_eq!(
+    out_paths,
+    rzip_lib::get_out_paths_for_archives(&archives, &config).unwrap()
+  );
+
+  // Passwords are left out of the plan
+  let json = plan.to_json().unwrap();
+  assert!(!json.contains("hunter2"));
+  let read_plan = ExtractionPlan::from_json(&json).unwrap();
+  assert_eq!(read_plan.archives, plan.archives);
+  assert_eq!(read_plan.config.target_path, config.target_path);
+  assert_eq!(read_plan.config.layout, config.layout);
+  assert_eq!(read_plan.config.name_template, config.name_template);
+  assert!(read_plan.config.passwords.is_empty());
+  read_plan.verify().unwrap();
+
+  assert!(matches!(
+    ExtractionPlan::from_json("{\"archives\": 1}"),
+    Err(RZipError::Plan(_))
+  ));
+}
+
+#[test]
+fn test_plan_detects_changed_archives() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_nested_data_to(temp_dir.path());
+  let config = RZipExtractConfig {
+    target_path: temp_dir.path().to_path_buf(),
+    ..Default::default()
+  };
+  let nested_zip = temp_dir.path().join("nested.zip");
+  let set_modified = |path: &Path, modified: SystemTime| {
+    let file = File::options().write(true).open(path).unwrap();
+    file.set_modified(modified).unwrap();
+  };
+  let changed = |plan: &ExtractionPlan, path: &Path| match plan.verify() {
+    Err(RZipError::ArchiveChanged(changed)) => changed == path,
+    _ => false,
+  };
+
+  // A new modification time alone is a change
+  let plan = ExtractionPlan::new(&config).unwrap();
+  set_modified(
+    &nested_zip,
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000),
+  );
+  assert!(changed(&plan, &nested_zip));
+
+  // So are new contents of the same size and modification time
+  let plan = ExtractionPlan::new(&config).unwrap();
+  let modified = fs::metadata(&nested_zip).unwrap().modified().unwrap();
+  let mut contents = fs::read(&nested_zip).unwrap();
+  *contents.last_mut().unwrap() ^= 0xff;
+  fs::write(&nested_zip, contents).unwrap();
+  set_modified(&nested_zip, modified);
+  assert!(changed(&plan, &nested_zip));
+
+  // And a missing archive
+  let plan = ExtractionPlan::new(&config).unwrap();
+  let nested_tar_zst = temp_dir.path().join("nested_tar_zst.tar.zst");
+  fs::remove_file(&nested_tar_zst).unwrap();
+  assert!(changed(&plan, &nested_tar_zst));
+}
@@ -0,0 +1,213 @@
+use std::{
+  collections::BTreeSet,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{
+  preview::{self, ArchivePreview, PreviewKind},
+  ConflictPolicy, ExtractLimits, LimitViolation, OutputLayout, RZipError, RZipExtractConfig,
+  RZipWarning,
+};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set to the input `temp_dir`. The nested data has the
+/// structure:
+///
+/// ```bash
+/// nested.zip
+/// ├── doc_set1.zip
+/// │   ├── doc1.txt
+/// │   ├── doc2.txt
+/// │   └── doc3.txt
+/// ├── doc_set2.zip
+/// │   ├── doc4.txt
+/// │   ├── doc5.txt
+/// │   └── doc6.txt
+/// ├── doc_set3.zip
+/// │   ├── doc7.txt
+/// │   ├── doc8.txt
+/// │   └── doc9.txt
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// nested_tar_zst.tar.zst
+/// └── packed_zip.zip
+///     └── doc_zip.txt
+/// packed_tar_gz.tar.gz.gz
+/// └── packed_tar_gz.tar.gz
+///     └── doc_tar_gz.txt
+/// ```
+fn copy_data_to(temp_dir: &Path, data_set: &str, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+/// Every file a preview says would be unpacked, including nested archives and what they unpack.
+fn previewed_files(preview: &ArchivePreview, files: &mut BTreeSet<PathBuf>) {
+  for entry in &preview.entries {
+    match &entry.kind {
+      PreviewKind::Directory => continue,
+      PreviewKind::Archive(_, nested) => previewed_files(nested, files),
+      _ => (),
+    }
+    files.insert(entry.path.clone());
+  }
+}
+
+/// Every file at or under `path`.
+fn unpacked_files(path: &Path, files: &mut BTreeSet<PathBuf>) {
+  if !path.is_dir() {
+    files.insert(path.to_path_buf());
+    return;
+  }
+  for entry in fs::read_dir(path).unwrap() {
+    unpacked_files(&entry.unwrap().path(), files);
+  }
+}
+
+/// Preview an archive, check that nothing was written, then extract it and check that the preview
+/// matched what was unpacked.
+fn assert_preview_matches(data_set: &str, archive_name: &str, config: RZipExtractConfig) {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), data_set, archive_name);
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    ..config
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+
+  let preview = preview::preview_recursive_extract(&target_path, &out_path, &config).unwrap();
+  assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+  let mut expected = BTreeSet::new();
+  previewed_files(&preview, &mut expected);
+
+  rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  // A decompressed archive is unpacked beside its own output, so everything but the archive counts
+  let mut unpacked = BTreeSet::new();
+  unpacked_files(temp_dir.path(), &mut unpacked);
+  unpacked.remove(&target_path);
+  assert_eq!(expected, unpacked, "{archive_name}");
+}
+
+#[test]
+fn test_preview_matches_extraction() {
+  for archive_name in [
+    "nested.zip",
+    "nested_tar_zst.tar.zst",
+    "packed_tar_gz.tar.gz.gz",
+  ] {
+    assert_preview_matches("nested", archive_name, Default::default());
+  }
+  assert_preview_matches("collide", "collide.zip", Default::default());
+  assert_preview_matches(
+    "collapse",
+    "project.zip",
+    RZipExtractConfig {
+      collapse_single_root: true,
+      ..Default::default()
+    },
+  );
+  assert_preview_matches(
+    "nested",
+    "nested.zip",
+    RZipExtractConfig {
+      layout: OutputLayout::Flat,
+      ..Default::default()
+    },
+  );
+  assert_preview_matches(
+    "nested",
+    "nested.zip",
+    RZipExtractConfig {
+      max_depth: Some(0),
+      ..Default::default()
+    },
+  );
+}
+
+#[test]
+fn test_preview_warnings_and_deletions() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "nested", "packed_tar_gz.tar.gz.gz");
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    delete_after_extracting: true,
+    max_depth: Some(0),
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  let preview = preview::preview_recursive_extract(&target_path, &out_path, &config).unwrap();
+
+  // The decompressed tarball is too deep to unpack, so only the outer archive would be deleted
+  assert_eq!(preview.deleted, [target_path]);
+  assert_eq!(preview.warnings.len(), 1);
+  assert!(matches!(
+    &preview.entries[..],
+    [entry] if entry.path == out_path && matches!(entry.kind, PreviewKind::File(_))
+  ));
+}
+
+#[test]
+fn test_preview_applies_limits() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "limits", "nested_zeros.zip");
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    limits: ExtractLimits {
+      max_ratio: Some(100),
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+
+  // The nested archive fails the preview as it fails an extraction, rather than going unlisted
+  let res = preview::preview_recursive_extract(&target_path, &out_path, &config);
+  let nested_path = out_path.join("zeros.zip");
+  assert!(matches!(
+    res,
+    Err(RZipError::LimitExceeded(path, LimitViolation::Ratio(100))) if path == nested_path
+  ));
+  assert!(!out_path.exists());
+}
+
+#[test]
+fn test_preview_merges_nested_outputs() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "nested", "nested.zip");
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    on_conflict: ConflictPolicy::Merge,
+    ..Default::default()
+  };
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  // A file newer than the nested archive where it would unpack to
+  let existing_path = out_path.join("doc_set1");
+  fs::create_dir_all(&out_path).unwrap();
+  fs::write(&existing_path, "existing\n").unwrap();
+
+  // The nested archive is compared by its listed time, and left packed as it is when unpacking
+  let preview = preview::preview_recursive_extract(&target_path, &out_path, &config).unwrap();
+  let nested_path = out_path.join("doc_set1.zip");
+  let nested = preview
+    .entries
+    .iter()
+    .find_map(|entry| match &entry.kind {
+      PreviewKind::Archive(_, nested) if entry.path == nested_path => Some(nested),
+      _ => None,
+    })
+    .unwrap();
+  assert!(matches!(
+    &nested.warnings[..],
+    [RZipWarning::OutputExists(path, existing)] if *path == nested_path && *existing == existing_path
+  ));
+  assert!(nested.entries.is_empty());
+}
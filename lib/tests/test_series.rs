@@ -43,13 +43,14 @@ fn test_series_no_out_dir() {
     target_path: temp_dir_path_buf.clone(),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let archives = rzip_lib::get_archives_in_dir(&temp_dir_path_buf).unwrap();
 
   for archive in archives {
     let out_path = rzip_lib::get_out_path_for_archive(&archive, &config).unwrap();
-    rzip_lib::recursive_file_extract(&archive, &out_path, &config).unwrap()
+    rzip_lib::recursive_file_extract(&archive, &out_path, &config).unwrap();
   }
 
   // Test the existence of each expected file
@@ -96,13 +97,14 @@ fn test_series_with_deletion() {
     target_path: temp_dir_path_buf.clone(),
     out_dir: None,
     delete_after_extracting: true,
+    ..Default::default()
   };
 
   let archives = rzip_lib::get_archives_in_dir(&temp_dir_path_buf).unwrap();
 
   for archive in archives {
     let out_path = rzip_lib::get_out_path_for_archive(&archive, &config).unwrap();
-    rzip_lib::recursive_file_extract(&archive, &out_path, &config).unwrap()
+    rzip_lib::recursive_file_extract(&archive, &out_path, &config).unwrap();
   }
 
   // Test the existence of each expected file
@@ -153,13 +155,14 @@ fn test_series_with_out_dir() {
     target_path: temp_dir_path_buf.clone(),
     out_dir: Some(out_dir_path.clone()),
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let archives = rzip_lib::get_archives_in_dir(&temp_dir_path_buf).unwrap();
 
   for archive in archives {
     let out_path = rzip_lib::get_out_path_for_archive(&archive, &config).unwrap();
-    rzip_lib::recursive_file_extract(&archive, &out_path, &config).unwrap()
+    rzip_lib::recursive_file_extract(&archive, &out_path, &config).unwrap();
   }
 
   // Test the existence of each expected file
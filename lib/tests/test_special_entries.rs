@@ -0,0 +1,270 @@
+// Links and special files are unpacked as on unix
+#![cfg(unix)]
+
+use std::{
+  fs,
+  os::unix::fs::FileTypeExt,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{
+  unpack, RZipError, RZipExtractConfig, RZipProcessingError, RZipWarning, SpecialEntryKind,
+  SpecialEntryPolicy, UnsafePathPolicy,
+};
+use tempfile::TempDir;
+
+fn get_special_data_root() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/special")
+}
+
+/// Copies an archive with links and special files to a directory in the input `temp_dir`, so that
+/// anything escaping the output directory still lands inside `temp_dir`. The data has the
+/// structure:
+///
+/// ```bash
+/// special_tar.tar (and .tar.gz, .tar.bz2, .tar.xz, .tar.zst)
+/// ├── doc_special.txt
+/// ├── link_doc -> doc_special.txt
+/// ├── hard_doc (hard link to doc_special.txt)
+/// └── fifo
+/// special_zip.zip, special_7z.7z
+/// ├── doc_special.txt
+/// └── link_doc -> doc_special.txt
+/// escape_tar.tar, escape_zip.zip
+/// ├── escape -> ../..
+/// ├── escape/evil.txt
+/// ├── dir/
+/// ├── inner -> dir
+/// └── inner/doc_inner.txt
+/// escape_7z.7z
+/// ├── escape -> ../..
+/// └── escape/evil.txt
+/// ```
+fn copy_special_data_to(temp_dir: &Path, archive_name: &str) -> PathBuf {
+  let work_dir = temp_dir.join("work");
+  fs::create_dir_all(&work_dir).unwrap();
+  let target_path = work_dir.join(archive_name);
+  fs::copy(get_special_data_root().join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+fn config_with_policy(
+  target_path: &Path,
+  special_entries: SpecialEntryPolicy,
+) -> RZipExtractConfig {
+  RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    special_entries,
+    ..Default::default()
+  }
+}
+
+/// The links and special files in an archive, in order.
+type SpecialEntries = &'static [(&'static str, SpecialEntryKind)];
+
+const TAR_SPECIAL_ENTRIES: SpecialEntries = &[
+  ("link_doc", SpecialEntryKind::Symlink),
+  ("hard_doc", SpecialEntryKind::Hardlink),
+  ("fifo", SpecialEntryKind::Fifo),
+];
+const LINK_SPECIAL_ENTRIES: SpecialEntries = &[("link_doc", SpecialEntryKind::Symlink)];
+
+#[test]
+fn test_every_stage_applies_special_entry_policy() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  // libarchive's entry iterator can't create links, so it leaves them to the other stages
+  let stages: [(Stage, &str, SpecialEntries, bool); 10] = [
+    (
+      unpack::compress_tools_unpack,
+      "special_tar.tar",
+      TAR_SPECIAL_ENTRIES,
+      false,
+    ),
+    (
+      unpack::compress_tools_unpack,
+      "special_zip.zip",
+      LINK_SPECIAL_ENTRIES,
+      false,
+    ),
+    (
+      unpack::compress_tools_unpack,
+      "special_7z.7z",
+      LINK_SPECIAL_ENTRIES,
+      false,
+    ),
+    (
+      unpack::zip_unpack,
+      "special_zip.zip",
+      LINK_SPECIAL_ENTRIES,
+      true,
+    ),
+    (
+      unpack::seven_z_unpack,
+      "special_7z.7z",
+      LINK_SPECIAL_ENTRIES,
+      true,
+    ),
+    (
+      unpack::flake2_unpack,
+      "special_tar_gz.tar.gz",
+      TAR_SPECIAL_ENTRIES,
+      true,
+    ),
+    (
+      unpack::bzip2_unpack,
+      "special_tar_bz2.tar.bz2",
+      TAR_SPECIAL_ENTRIES,
+      true,
+    ),
+    (
+      unpack::zstd_unpack,
+      "special_tar_zst.tar.zst",
+      TAR_SPECIAL_ENTRIES,
+      true,
+    ),
+    (
+      unpack::xz_unpack,
+      "special_tar_xz.tar.xz",
+      TAR_SPECIAL_ENTRIES,
+      true,
+    ),
+    (
+      unpack::tar_unpack,
+      "special_tar.tar",
+      TAR_SPECIAL_ENTRIES,
+      true,
+    ),
+  ];
+
+  for (stage, archive_name, special_entries, creates_links) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_special_data_to(temp_dir.path(), archive_name);
+    let out_path = temp_dir.path().join("work/out");
+    let unpack_with = |policy| {
+      let res = stage(
+        &target_path,
+        &out_path,
+        &config_with_policy(&target_path, policy),
+      );
+      let entries = special_entries
+        .iter()
+        .map(|(name, _)| (*name, fs::symlink_metadata(out_path.join(name)).ok()))
+        .collect::<Vec<_>>();
+      if out_path.exists() {
+        assert_eq!(
+          fs::read_to_string(out_path.join("doc_special.txt")).unwrap(),
+          "doc_special\n"
+        );
+        fs::remove_dir_all(&out_path).unwrap();
+      }
+      (res, entries)
+    };
+
+    // Rejecting stops at the first special entry
+    let (res, _) = unpack_with(SpecialEntryPolicy::Reject);
+    assert!(
+      matches!(
+        &res,
+        Err(RZipProcessingError::SpecialEntry(name, SpecialEntryKind::Symlink)) if name == "link_doc"
+      ),
+      "{archive_name}: {res:?}"
+    );
+
+    // Skipping reports each special entry
+    let (res, entries) = unpack_with(SpecialEntryPolicy::Skip);
+    let skipped: Vec<_> = res
+      .unwrap()
+      .into_iter()
+      .map(|w| match w {
+        RZipWarning::SpecialEntry(path, name, kind) if path == target_path => (name, kind),
+        w => panic!("{archive_name}: unexpected warning {w}"),
+      })
+      .collect();
+    let expected: Vec<_> = special_entries
+      .iter()
+      .map(|(name, kind)| (name.to_string(), *kind))
+      .collect();
+    assert_eq!(skipped, expected, "{archive_name}");
+    assert!(entries.iter().all(|(_, metadata)| metadata.is_none()));
+
+    // Backends that can't create links leave them to the others
+    let (res, entries) = unpack_with(SpecialEntryPolicy::Allow);
+    if !creates_links {
+      assert!(
+        matches!(&res, Err(RZipProcessingError::UnsupportedEntry(name)) if name == "link_doc"),
+        "{archive_name}: {res:?}"
+      );
+      continue;
+    }
+    assert!(res.unwrap().is_empty(), "{archive_name}");
+    for (name, metadata) in entries {
+      let file_type = metadata.unwrap().file_type();
+      match name {
+        "link_doc" => assert!(file_type.is_symlink()),
+        "hard_doc" => assert!(file_type.is_file()),
+        _ => assert!(file_type.is_fifo()),
+      }
+    }
+
+    // Materializing turns each into a regular file
+    let temp_out = temp_dir.path().join("materialized");
+    let res = stage(
+      &target_path,
+      &temp_out,
+      &config_with_policy(&target_path, SpecialEntryPolicy::Materialize),
+    );
+    assert!(res.unwrap().is_empty(), "{archive_name}");
+    for (name, _) in special_entries {
+      let entry_path = temp_out.join(name);
+      assert!(fs::symlink_metadata(&entry_path).unwrap().is_file());
+      let expected = if *name == "fifo" { "" } else { "doc_special\n" };
+      assert_eq!(fs::read_to_string(entry_path).unwrap(), expected);
+    }
+  }
+}
+
+#[test]
+fn test_symlink_escape() {
+  for archive_name in ["escape_tar.tar", "escape_zip.zip", "escape_7z.7z"] {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_special_data_to(temp_dir.path(), archive_name);
+    let config = config_with_policy(&target_path, SpecialEntryPolicy::Allow);
+    let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+
+    // A link out of the output directory is an unsafe path
+    let res = rzip_lib::recursive_file_extract(&target_path, &out_path, &config);
+    assert!(
+      matches!(&res, Err(RZipError::UnsafePath(_, name)) if name == "escape"),
+      "{archive_name}: {res:?}"
+    );
+    assert!(!out_path.exists());
+
+    // Once it's skipped, what was meant to be written through it stays inside
+    let config = RZipExtractConfig {
+      unsafe_paths: UnsafePathPolicy::Skip,
+      ..config
+    };
+    let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+    assert!(
+      matches!(&warnings[..], [RZipWarning::UnsafePath(_, name)] if name == "escape"),
+      "{archive_name}: {warnings:?}"
+    );
+    assert!(!temp_dir.path().join("evil.txt").exists());
+    assert!(fs::symlink_metadata(out_path.join("escape"))
+      .unwrap()
+      .is_dir());
+    assert_eq!(
+      fs::read_to_string(out_path.join("escape/evil.txt")).unwrap(),
+      "evil\n"
+    );
+
+    // Links that stay inside can be written through
+    if archive_name != "escape_7z.7z" {
+      assert_eq!(
+        fs::read_to_string(out_path.join("dir/doc_inner.txt")).unwrap(),
+        "doc_inner\n"
+      );
+    }
+  }
+}
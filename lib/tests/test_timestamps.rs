@@ -0,0 +1,193 @@
+use std::{
+  fs::{self, File},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use rzip_lib::{unpack, RZipExtractConfig, RZipProcessingError, RZipWarning, TimestampPolicy};
+use tempfile::TempDir;
+
+fn get_times_data_root() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/times")
+}
+
+/// Copies an archive with recorded timestamps to the input `temp_dir`. Each archive has the
+/// entries, recording the same modification and access times:
+///
+/// ```bash
+/// doc_times.txt
+/// dir/
+/// dir/doc_nested.txt
+/// ```
+///
+/// The tarballs also record the extended attribute `user.rzip=times` on `doc_times.txt`.
+/// `xattrs_tar.tar` holds `doc_xattrs.txt` alone, recording `user.rzip=kept`,
+/// `trusted.rzip=withheld` and a `user.` attribute with a name too long to set.
+fn copy_times_data_to(temp_dir: &Path, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_times_data_root().join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+const ENTRY_NAMES: [&str; 3] = ["doc_times.txt", "dir", "dir/doc_nested.txt"];
+
+/// The modification and access times recorded in the tarball's headers, in entry order.
+fn recorded_times() -> Vec<(SystemTime, SystemTime)> {
+  let tar = File::open(get_times_data_root().join("times_tar.tar")).unwrap();
+  let mut archive = tar::Archive::new(tar);
+  let seconds = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+  archive
+    .entries()
+    .unwrap()
+    .map(|entry| {
+      let mut entry = entry.unwrap();
+      let mtime = seconds(entry.header().mtime().unwrap());
+      let atime = entry
+        .pax_extensions()
+        .unwrap()
+        .unwrap()
+        .map(|extension| extension.unwrap())
+        .find(|extension| extension.key().unwrap() == "atime")
+        .map(|extension| seconds(extension.value().unwrap().parse().unwrap()))
+        .unwrap();
+      (mtime, atime)
+    })
+    .collect()
+}
+
+/// The modification and access times of each unpacked entry. Reading the files would change their
+/// access times, so only their metadata is looked at.
+fn unpacked_times(out_path: &Path) -> Vec<(SystemTime, SystemTime)> {
+  ENTRY_NAMES
+    .iter()
+    .map(|name| {
+      let metadata = fs::metadata(out_path.join(name)).unwrap();
+      (metadata.modified().unwrap(), metadata.accessed().unwrap())
+    })
+    .collect()
+}
+
+fn config_with_policy(target_path: &Path, timestamps: TimestampPolicy) -> RZipExtractConfig {
+  RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    timestamps,
+    ..Default::default()
+  }
+}
+
+#[test]
+fn test_every_stage_applies_timestamp_policy() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  let stages: [(Stage, &str); 11] = [
+    (unpack::compress_tools_unpack, "times_zip.zip"),
+    (unpack::compress_tools_unpack, "times_7z.7z"),
+    (unpack::compress_tools_unpack, "times_tar.tar"),
+    (unpack::compress_tools_unpack, "times_tar_gz.tar.gz"),
+    (unpack::zip_unpack, "times_zip.zip"),
+    (unpack::seven_z_unpack, "times_7z.7z"),
+    (unpack::flake2_unpack, "times_tar_gz.tar.gz"),
+    (unpack::bzip2_unpack, "times_tar_bz2.tar.bz2"),
+    (unpack::zstd_unpack, "times_tar_zst.tar.zst"),
+    (unpack::xz_unpack, "times_tar_xz.tar.xz"),
+    (unpack::tar_unpack, "times_tar.tar"),
+  ];
+  let recorded = recorded_times();
+
+  for (stage, archive_name) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_times_data_to(temp_dir.path(), archive_name);
+
+    // Directories keep their times even though entries were unpacked into them
+    let out_path = temp_dir.path().join("preserved");
+    let config = config_with_policy(&target_path, TimestampPolicy::Preserve);
+    stage(&target_path, &out_path, &config).unwrap();
+    assert_eq!(unpacked_times(&out_path), recorded, "{archive_name}");
+
+    // Otherwise everything is as new as the extraction
+    let started = SystemTime::now() - Duration::from_secs(2);
+    let out_path = temp_dir.path().join("now");
+    let config = config_with_policy(&target_path, TimestampPolicy::Now);
+    stage(&target_path, &out_path, &config).unwrap();
+    for (mtime, _) in unpacked_times(&out_path) {
+      assert!(mtime > started, "{archive_name}");
+    }
+  }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_preserve_xattrs() {
+  let temp_dir = TempDir::new().unwrap();
+  let unpack_with = |archive_name, preserve_xattrs| {
+    let target_path = copy_times_data_to(temp_dir.path(), archive_name);
+    let out_path = temp_dir
+      .path()
+      .join(format!("{archive_name}_{preserve_xattrs}"));
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      preserve_xattrs,
+      ..Default::default()
+    };
+    unpack::unpack_file(&target_path, &out_path, &config).unwrap();
+    xattr::get(out_path.join("doc_times.txt"), "user.rzip").unwrap()
+  };
+
+  for archive_name in ["times_tar.tar", "times_tar_gz.tar.gz"] {
+    assert_eq!(
+      unpack_with(archive_name, true).as_deref(),
+      Some(&b"times"[..]),
+      "{archive_name}"
+    );
+    assert_eq!(unpack_with(archive_name, false), None, "{archive_name}");
+  }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_xattr_namespaces() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_times_data_to(temp_dir.path(), "xattrs_tar.tar");
+  let unpack_with = |all_xattrs| {
+    let out_path = temp_dir.path().join(format!("xattrs_{all_xattrs}"));
+    let config = RZipExtractConfig {
+      target_path: target_path.clone(),
+      preserve_xattrs: true,
+      all_xattrs,
+      ..Default::default()
+    };
+    let warnings = unpack::unpack_file(&target_path, &out_path, &config).unwrap();
+    (warnings, out_path.join("doc_xattrs.txt"))
+  };
+
+  // Only the user namespace is restored by default, and an attribute that can't be set is
+  // reported without giving up on the others
+  let long_name = format!("user.{}", "x".repeat(300));
+  let (warnings, doc_path) = unpack_with(false);
+  assert!(matches!(
+    &warnings[..],
+    [RZipWarning::Xattr(path, entry_name, name, _)]
+      if *path == target_path && entry_name == "doc_xattrs.txt" && *name == long_name
+  ));
+  assert_eq!(
+    xattr::get(&doc_path, "user.rzip").unwrap().as_deref(),
+    Some(&b"kept"[..])
+  );
+  assert_eq!(xattr::get(&doc_path, "trusted.rzip").unwrap(), None);
+
+  // Other namespaces need privileges to set as well as opting in
+  let (warnings, doc_path) = unpack_with(true);
+  if unsafe { libc::geteuid() } == 0 {
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(
+      xattr::get(&doc_path, "trusted.rzip").unwrap().as_deref(),
+      Some(&b"withheld"[..])
+    );
+  } else {
+    assert_eq!(warnings.len(), 2);
+  }
+  assert_eq!(
+    xattr::get(&doc_path, "user.rzip").unwrap().as_deref(),
+    Some(&b"kept"[..])
+  );
+}
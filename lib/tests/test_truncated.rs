@@ -0,0 +1,149 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{unpack, ConflictPolicy, ExtractLimits, RZipError, RZipExtractConfig, RZipWarning};
+use tempfile::TempDir;
+
+fn get_data_root(data_set: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/data")
+    .join(data_set)
+}
+
+/// Copies an archive from a test data set to the input `temp_dir`. The truncated data has the
+/// structure, cut off partway through `doc2.txt`:
+///
+/// ```bash
+/// truncated_tar.tar (and .tar.gz)
+/// ├── doc1.txt
+/// ├── doc2.txt
+/// └── doc3.txt
+/// ```
+fn copy_data_to(temp_dir: &Path, data_set: &str, archive_name: &str) -> PathBuf {
+  let target_path = temp_dir.join(archive_name);
+  fs::copy(get_data_root(data_set).join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+/// The names of the entries in `dir`, sorted.
+fn dir_names(dir: &Path) -> Vec<String> {
+  let mut names: Vec<_> = fs::read_dir(dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+    .collect();
+  names.sort();
+  names
+}
+
+#[test]
+fn test_truncated_archive_leaves_nothing() {
+  for archive_name in ["truncated_tar.tar", "truncated_tar_gz.tar.gz"] {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_data_to(temp_dir.path(), "truncated", archive_name);
+    let config = RZipExtractConfig::default();
+
+    // Every backend fails partway through, and none of them leave anything behind
+    let out_path = temp_dir.path().join("out");
+    let res = unpack::unpack_file(&target_path, &out_path, &config);
+    assert!(
+      matches!(&res, Err(RZipError::ProcessingErrors(errors)) if errors.len() == 2),
+      "{archive_name}: {res:?}"
+    );
+    assert_eq!(dir_names(temp_dir.path()), [archive_name], "{archive_name}");
+
+    // An output that was already there is left as it was
+    fs::create_dir(&out_path).unwrap();
+    fs::write(out_path.join("doc1.txt"), "existing\n").unwrap();
+    assert!(unpack::unpack_file(&target_path, &out_path, &config).is_err());
+    assert_eq!(dir_names(&out_path), ["doc1.txt"], "{archive_name}");
+    assert_eq!(
+      fs::read_to_string(out_path.join("doc1.txt")).unwrap(),
+      "existing\n"
+    );
+  }
+}
+
+#[test]
+fn test_failed_unpack_leaves_no_staging() {
+  let temp_dir = TempDir::new().unwrap();
+  let config_with = |target_path: &Path| RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    limits: ExtractLimits {
+      max_archive_bytes: Some(15),
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+
+  // Each archive stops at its second entry, after the first was already staged
+  for archive_name in ["times_zip.zip", "times_tar.tar", "times_tar_gz.tar.gz"] {
+    let target_path = copy_data_to(temp_dir.path(), "times", archive_name);
+    let out_path = temp_dir.path().join("out");
+    let res = unpack::unpack_file(&target_path, &out_path, &config_with(&target_path));
+    assert!(
+      matches!(res, Err(RZipError::LimitExceeded(..))),
+      "{archive_name}: {res:?}"
+    );
+    assert_eq!(dir_names(temp_dir.path()), [archive_name], "{archive_name}");
+    fs::remove_file(target_path).unwrap();
+  }
+}
+
+#[test]
+fn test_staging_is_next_to_output() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "packed_tar.tar");
+  let out_path = temp_dir.path().join("packed_tar");
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    ..Default::default()
+  };
+
+  // Output is staged in its own directory, so it's renamed into place on the same filesystem. A
+  // staging path that's already taken is left alone for another name.
+  fs::write(
+    temp_dir.path().join(".packed_tar.rzip-staging"),
+    "existing\n",
+  )
+  .unwrap();
+  unpack::unpack_file(&target_path, &out_path, &config).unwrap();
+  assert_eq!(
+    dir_names(temp_dir.path()),
+    [".packed_tar.rzip-staging", "packed_tar", "packed_tar.tar"]
+  );
+  assert_eq!(
+    fs::read_to_string(temp_dir.path().join(".packed_tar.rzip-staging")).unwrap(),
+    "existing\n"
+  );
+  assert_eq!(dir_names(&out_path), ["doc_tar.txt"]);
+}
+
+#[test]
+fn test_staged_output_merges_into_existing() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_data_to(temp_dir.path(), "indiv", "packed_tar.tar");
+  let out_path = temp_dir.path().join("packed_tar");
+  fs::create_dir(&out_path).unwrap();
+  fs::write(out_path.join("doc_tar.txt"), "existing\n").unwrap();
+  fs::write(out_path.join("other.txt"), "other\n").unwrap();
+  let config = RZipExtractConfig {
+    target_path: target_path.clone(),
+    on_conflict: ConflictPolicy::Skip,
+    ..Default::default()
+  };
+
+  // The conflict policy still applies to what was already there
+  let warnings = unpack::unpack_file(&target_path, &out_path, &config).unwrap();
+  assert!(matches!(
+    &warnings[..],
+    [RZipWarning::Conflict(path, name)] if *path == target_path && name == "doc_tar.txt"
+  ));
+  assert_eq!(dir_names(&out_path), ["doc_tar.txt", "other.txt"]);
+  assert_eq!(
+    fs::read_to_string(out_path.join("doc_tar.txt")).unwrap(),
+    "existing\n"
+  );
+  assert_eq!(dir_names(temp_dir.path()), ["packed_tar", "packed_tar.tar"]);
+}
@@ -33,10 +33,11 @@ fn test_compress_tools_unpack() {
     target_path: target_path.clone(),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
-  rzip_lib::unpack::compress_tools_unpack(&target_path, &out_path).unwrap();
+  rzip_lib::unpack::compress_tools_unpack(&target_path, &out_path, &config).unwrap();
 
   // Test expected files
   let packed_zip_zip = temp_dir.path().join("packed_zip.zip");
@@ -47,6 +48,29 @@ fn test_compress_tools_unpack() {
   assert!(doc_zip.exists());
 }
 
+#[test]
+fn test_zip_unpack() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_zip_data_to(temp_dir.path());
+  let target_path = temp_dir.path().join("packed_zip.zip");
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::zip_unpack(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_zip_dir = temp_dir.path().join("packed_zip");
+  let doc_zip = temp_dir.path().join("packed_zip/doc_zip.txt");
+  assert!(packed_zip_dir.exists());
+  assert!(doc_zip.exists());
+}
+
 /// Copies data to the input `temp_dir`. The data has the structure:
 ///
 /// ```bash
@@ -71,10 +95,11 @@ fn test_seven_z_unpack() {
     target_path: target_path.clone(),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
-  rzip_lib::unpack::seven_z_unpack(&target_path, &out_path).unwrap();
+  rzip_lib::unpack::seven_z_unpack(&target_path, &out_path, &config).unwrap();
 
   // Test expected files
   let packed_7z_7z = temp_dir.path().join("packed_7z.7z");
@@ -109,20 +134,99 @@ fn test_flake2_unpack() {
     target_path: target_path.clone(),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
-  rzip_lib::unpack::flake2_unpack(&target_path, &out_path).unwrap();
+  rzip_lib::unpack::flake2_unpack(&target_path, &out_path, &config).unwrap();
 
   // Test expected files
   let packed_tar_gz_tar_gz = temp_dir.path().join("packed_tar_gz.tar.gz");
-  let packed_tar_dir = temp_dir.path().join("packed_tar_gz.tar");
-  let doc_tar_gz = temp_dir.path().join("packed_tar_gz.tar/doc_tar_gz.txt");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_gz");
+  let doc_tar_gz = temp_dir.path().join("packed_tar_gz/doc_tar_gz.txt");
   assert!(packed_tar_gz_tar_gz.exists());
   assert!(packed_tar_dir.exists());
   assert!(doc_tar_gz.exists());
 }
 
+/// Copies data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// packed_tar_bz2.tar.bz2
+/// └── doc_tar_bz2.txt
+/// ```
+fn copy_tar_bz2_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let packed_tar_bz2 = data_root.join("packed_tar_bz2.tar.bz2");
+
+  // Copy each item to temporary directory
+  fs::copy(packed_tar_bz2, temp_dir.join("packed_tar_bz2.tar.bz2")).unwrap();
+}
+
+#[test]
+fn test_bzip2_unpack() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_tar_bz2_data_to(temp_dir.path());
+  let target_path = temp_dir.path().join("packed_tar_bz2.tar.bz2");
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::bzip2_unpack(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_tar_bz2_tar_bz2 = temp_dir.path().join("packed_tar_bz2.tar.bz2");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_bz2");
+  let doc_tar_bz2 = temp_dir.path().join("packed_tar_bz2/doc_tar_bz2.txt");
+  assert!(packed_tar_bz2_tar_bz2.exists());
+  assert!(packed_tar_dir.exists());
+  assert!(doc_tar_bz2.exists());
+}
+
+/// Copies data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// packed_tar_zst.tar.zst
+/// └── doc_tar_zst.txt
+/// ```
+fn copy_tar_zst_data_to(temp_dir: &Path) {
+  let data_root = get_individual_data_root();
+  let packed_tar_zst = data_root.join("packed_tar_zst.tar.zst");
+
+  // Copy each item to temporary directory
+  fs::copy(packed_tar_zst, temp_dir.join("packed_tar_zst.tar.zst")).unwrap();
+}
+
+#[test]
+fn test_zstd_unpack() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_tar_zst_data_to(temp_dir.path());
+  let target_path = temp_dir.path().join("packed_tar_zst.tar.zst");
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::zstd_unpack(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let packed_tar_zst_tar_zst = temp_dir.path().join("packed_tar_zst.tar.zst");
+  let packed_tar_dir = temp_dir.path().join("packed_tar_zst");
+  let doc_tar_zst = temp_dir.path().join("packed_tar_zst/doc_tar_zst.txt");
+  assert!(packed_tar_zst_tar_zst.exists());
+  assert!(packed_tar_dir.exists());
+  assert!(doc_tar_zst.exists());
+}
+
 /// Copies data to the input `temp_dir`. The data has the structure:
 ///
 /// ```bash
@@ -147,10 +251,11 @@ fn test_tar_unpack() {
     target_path: target_path.clone(),
     out_dir: None,
     delete_after_extracting: false,
+    ..Default::default()
   };
 
   let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
-  rzip_lib::unpack::tar_unpack(&target_path, &out_path).unwrap();
+  rzip_lib::unpack::tar_unpack(&target_path, &out_path, &config).unwrap();
 
   // Test expected files
   let packed_tar_tar = temp_dir.path().join("packed_tar.tar");
@@ -160,3 +265,118 @@ fn test_tar_unpack() {
   assert!(packed_tar_dir.exists());
   assert!(doc_tar.exists());
 }
+
+#[test]
+fn test_flake2_decompress() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  let target_path = temp_dir.path().join("doc_gz.txt.gz");
+  fs::copy(data_root.join("doc_gz.txt.gz"), &target_path).unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::flake2_decompress(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let doc_gz = temp_dir.path().join("doc_gz.txt");
+  assert!(target_path.exists());
+  assert_eq!(fs::read_to_string(doc_gz).unwrap(), "doc_gz\n");
+}
+
+#[test]
+fn test_bzip2_decompress() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  let target_path = temp_dir.path().join("doc_bz2.txt.bz2");
+  fs::copy(data_root.join("doc_bz2.txt.bz2"), &target_path).unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::bzip2_decompress(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let doc_bz2 = temp_dir.path().join("doc_bz2.txt");
+  assert!(target_path.exists());
+  assert_eq!(fs::read_to_string(doc_bz2).unwrap(), "doc_bz2\n");
+}
+
+#[test]
+fn test_zstd_decompress() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  let target_path = temp_dir.path().join("doc_zst.txt.zst");
+  fs::copy(data_root.join("doc_zst.txt.zst"), &target_path).unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::zstd_decompress(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let doc_zst = temp_dir.path().join("doc_zst.txt");
+  assert!(target_path.exists());
+  assert_eq!(fs::read_to_string(doc_zst).unwrap(), "doc_zst\n");
+}
+
+#[test]
+fn test_compress_tools_decompress() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  let target_path = temp_dir.path().join("doc_xz.txt.xz");
+  fs::copy(data_root.join("doc_xz.txt.xz"), &target_path).unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::compress_tools_decompress(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let doc_xz = temp_dir.path().join("doc_xz.txt");
+  assert!(target_path.exists());
+  assert_eq!(fs::read_to_string(doc_xz).unwrap(), "doc_xz\n");
+}
+
+#[test]
+fn test_xz_decompress() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_individual_data_root();
+  let target_path = temp_dir.path().join("doc_xz.txt.xz");
+  fs::copy(data_root.join("doc_xz.txt.xz"), &target_path).unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: target_path.clone(),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::unpack::xz_decompress(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let doc_xz = temp_dir.path().join("doc_xz.txt");
+  assert!(target_path.exists());
+  assert_eq!(fs::read_to_string(doc_xz).unwrap(), "doc_xz\n");
+}
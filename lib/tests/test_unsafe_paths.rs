@@ -0,0 +1,147 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::{
+  unpack, RZipError, RZipExtractConfig, RZipProcessingError, RZipWarning, UnsafePathPolicy,
+};
+use tempfile::TempDir;
+
+fn get_unsafe_data_root() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/unsafe")
+}
+
+/// Copies an archive with unsafe entry paths to a directory in the input `temp_dir`, so that
+/// anything escaping the output directory still lands inside `temp_dir`. Each archive has the
+/// entries:
+///
+/// ```bash
+/// doc_unsafe.txt
+/// ../evil_parent.txt
+/// /evil_absolute.txt
+/// dir/../../evil_nested.txt
+/// dir/doc_after.txt
+/// ```
+fn copy_unsafe_data_to(temp_dir: &Path, archive_name: &str) -> PathBuf {
+  let work_dir = temp_dir.join("work");
+  fs::create_dir_all(&work_dir).unwrap();
+  let target_path = work_dir.join(archive_name);
+  fs::copy(get_unsafe_data_root().join(archive_name), &target_path).unwrap();
+  target_path
+}
+
+const UNSAFE_ENTRIES: [&str; 3] = [
+  "../evil_parent.txt",
+  "/evil_absolute.txt",
+  "dir/../../evil_nested.txt",
+];
+
+/// Check that only the safe entries were unpacked to `out_path`.
+fn assert_only_safe_entries(temp_dir: &Path, out_path: &Path) {
+  assert_eq!(
+    fs::read_to_string(out_path.join("doc_unsafe.txt")).unwrap(),
+    "doc_unsafe\n"
+  );
+  assert_eq!(
+    fs::read_to_string(out_path.join("dir/doc_after.txt")).unwrap(),
+    "doc_after\n"
+  );
+  for evil_path in [
+    temp_dir.join("evil_parent.txt"),
+    temp_dir.join("work/evil_parent.txt"),
+    temp_dir.join("work/evil_nested.txt"),
+    out_path.join("evil_absolute.txt"),
+    PathBuf::from("/evil_absolute.txt"),
+  ] {
+    assert!(!evil_path.exists(), "{}", evil_path.display());
+  }
+}
+
+fn config_with_policy(target_path: &Path, unsafe_paths: UnsafePathPolicy) -> RZipExtractConfig {
+  RZipExtractConfig {
+    target_path: target_path.to_path_buf(),
+    unsafe_paths,
+    ..Default::default()
+  }
+}
+
+#[test]
+fn test_every_stage_guards_paths() {
+  type Stage =
+    fn(&Path, &Path, &RZipExtractConfig) -> Result<Vec<RZipWarning>, RZipProcessingError>;
+  let stages: [(Stage, &str); 12] = [
+    (unpack::compress_tools_unpack, "unsafe_zip.zip"),
+    (unpack::compress_tools_unpack, "unsafe_7z.7z"),
+    (unpack::compress_tools_unpack, "unsafe_tar.tar"),
+    (unpack::compress_tools_unpack, "unsafe_tar_gz.tar.gz"),
+    (unpack::zip_unpack, "unsafe_zip.zip"),
+    (unpack::seven_z_unpack, "unsafe_7z.7z"),
+    (unpack::flake2_unpack, "unsafe_tar_gz.tar.gz"),
+    (unpack::bzip2_unpack, "unsafe_tar_bz2.tar.bz2"),
+    (unpack::zstd_unpack, "unsafe_tar_zst.tar.zst"),
+    (unpack::xz_unpack, "unsafe_tar_xz.tar.xz"),
+    (unpack::tar_unpack, "unsafe_tar.tar"),
+    (unpack::compress_tools_unpack, "unsafe_tar_xz.tar.xz"),
+  ];
+
+  for (stage, archive_name) in stages {
+    let temp_dir = TempDir::new().unwrap();
+    let target_path = copy_unsafe_data_to(temp_dir.path(), archive_name);
+    let out_path = temp_dir.path().join("work/out");
+
+    // Rejecting stops at the first unsafe entry
+    let config = config_with_policy(&target_path, UnsafePathPolicy::Reject);
+    let res = stage(&target_path, &out_path, &config);
+    assert!(
+      matches!(&res, Err(RZipProcessingError::UnsafePath(name)) if name == UNSAFE_ENTRIES[0]),
+      "{archive_name}: {res:?}"
+    );
+    // Stages that unpack each attempt to a fresh directory leave nothing behind
+    if out_path.exists() {
+      fs::remove_dir_all(&out_path).unwrap();
+    }
+
+    // Skipping unpacks everything else and reports each unsafe entry
+    let config = config_with_policy(&target_path, UnsafePathPolicy::Skip);
+    let warnings = stage(&target_path, &out_path, &config).unwrap();
+    let skipped: Vec<_> = warnings
+      .iter()
+      .map(|w| match w {
+        RZipWarning::UnsafePath(path, name) if *path == target_path => name.as_str(),
+        w => panic!("{archive_name}: unexpected warning {w}"),
+      })
+      .collect();
+    assert_eq!(skipped, UNSAFE_ENTRIES, "{archive_name}");
+    assert_only_safe_entries(temp_dir.path(), &out_path);
+  }
+}
+
+#[test]
+fn test_reject_unsafe_paths() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_unsafe_data_to(temp_dir.path(), "unsafe_tar_gz.tar.gz");
+  let config = config_with_policy(&target_path, UnsafePathPolicy::Reject);
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  let res = rzip_lib::recursive_file_extract(&target_path, &out_path, &config);
+  assert!(matches!(
+    res,
+    Err(RZipError::UnsafePath(path, name)) if path == target_path && name == UNSAFE_ENTRIES[0]
+  ));
+
+  // Nothing is left behind
+  assert!(!out_path.exists());
+}
+
+#[test]
+fn test_skip_unsafe_paths() {
+  let temp_dir = TempDir::new().unwrap();
+  let target_path = copy_unsafe_data_to(temp_dir.path(), "unsafe_zip.zip");
+  let config = config_with_policy(&target_path, UnsafePathPolicy::Skip);
+
+  let out_path = rzip_lib::get_out_path_for_archive(&target_path, &config).unwrap();
+  let warnings = rzip_lib::recursive_file_extract(&target_path, &out_path, &config).unwrap();
+  assert_eq!(warnings.len(), UNSAFE_ENTRIES.len());
+  assert_only_safe_entries(temp_dir.path(), &out_path);
+}
@@ -0,0 +1,197 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use rzip_lib::RZipError;
+use tempfile::TempDir;
+
+fn get_volumes_data_root() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/volumes")
+}
+
+/// Copies split 7z data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// split_7z.7z.001 + split_7z.7z.002
+/// └── doc_7z.txt
+/// ```
+fn copy_split_7z_data_to(temp_dir: &Path) {
+  let data_root = get_volumes_data_root();
+
+  // Copy each item to temporary directory
+  for volume in ["split_7z.7z.001", "split_7z.7z.002"] {
+    fs::copy(data_root.join(volume), temp_dir.join(volume)).unwrap();
+  }
+}
+
+/// Copies split RAR data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// split_rar.part1.rar + split_rar.part2.rar + split_rar.part3.rar
+/// └── LibarchiveAddingTest.html
+/// ```
+fn copy_split_rar_data_to(temp_dir: &Path) {
+  let data_root = get_volumes_data_root();
+
+  // Copy each item to temporary directory
+  for volume in [
+    "split_rar.part1.rar",
+    "split_rar.part2.rar",
+    "split_rar.part3.rar",
+  ] {
+    fs::copy(data_root.join(volume), temp_dir.join(volume)).unwrap();
+  }
+}
+
+/// Copies split zip data to the input `temp_dir`. The data has the structure:
+///
+/// ```bash
+/// split_zip.z01 + split_zip.zip
+/// └── doc_split_zip.txt
+/// ```
+fn copy_split_zip_data_to(temp_dir: &Path) {
+  let data_root = get_volumes_data_root();
+
+  // Copy each item to temporary directory
+  for volume in ["split_zip.z01", "split_zip.zip"] {
+    fs::copy(data_root.join(volume), temp_dir.join(volume)).unwrap();
+  }
+}
+
+#[test]
+fn test_volumes_discovered_once() {
+  let temp_dir = TempDir::new().unwrap();
+  let temp_dir_path_buf = temp_dir.path().to_path_buf();
+  copy_split_7z_data_to(temp_dir.path());
+  copy_split_zip_data_to(temp_dir.path());
+
+  let mut archives = rzip_lib::get_archives_in_dir(&temp_dir_path_buf).unwrap();
+  archives.sort();
+  assert_eq!(
+    archives,
+    vec![
+      temp_dir.path().join("split_7z.7z.001"),
+      temp_dir.path().join("split_zip.z01"),
+    ]
+  );
+}
+
+#[test]
+fn test_extract_split_7z() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_split_7z_data_to(temp_dir.path());
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("split_7z.7z.001"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let split_7z_dir = temp_dir.path().join("split_7z");
+  let doc_7z = temp_dir.path().join("split_7z/doc_7z.txt");
+  assert!(temp_dir.path().join("split_7z.7z.001").exists());
+  assert!(temp_dir.path().join("split_7z.7z.002").exists());
+  assert!(split_7z_dir.exists());
+  assert!(doc_7z.exists());
+}
+
+#[test]
+fn test_seven_z_unpack_split() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_split_7z_data_to(temp_dir.path());
+  let target_path = temp_dir.path().join("split_7z.7z.001");
+  let out_path = temp_dir.path().join("split_7z");
+
+  let config = rzip_lib::RZipExtractConfig::default();
+  rzip_lib::unpack::seven_z_unpack(&target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let doc_7z = temp_dir.path().join("split_7z/doc_7z.txt");
+  assert!(doc_7z.exists());
+}
+
+#[test]
+fn test_extract_split_rar() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_split_rar_data_to(temp_dir.path());
+
+  // Targeting a middle volume previews and extracts the whole set
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("split_rar.part2.rar"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  let preview =
+    rzip_lib::preview::preview_recursive_extract(&config.target_path, &out_path, &config).unwrap();
+  assert_eq!(preview.entries.len(), 1);
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // The file spans all three volumes
+  let html = fs::read_to_string(out_path.join("LibarchiveAddingTest.html")).unwrap();
+  assert_eq!(html.len(), 20111);
+  assert!(html.trim_end().ends_with("</HTML>"));
+}
+
+#[test]
+fn test_extract_split_zip_with_deletion() {
+  let temp_dir = TempDir::new().unwrap();
+  copy_split_zip_data_to(temp_dir.path());
+
+  // Targeting the trailing volume extracts the whole set
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("split_zip.zip"),
+    out_dir: None,
+    delete_after_extracting: true,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config).unwrap();
+
+  // Test expected files
+  let split_zip_dir = temp_dir.path().join("split_zip");
+  let doc_split_zip = temp_dir.path().join("split_zip/doc_split_zip.txt");
+  // Every volume should have been deleted
+  assert!(!temp_dir.path().join("split_zip.z01").exists());
+  assert!(!temp_dir.path().join("split_zip.zip").exists());
+  assert!(split_zip_dir.exists());
+  assert!(doc_split_zip.exists());
+  assert_eq!(fs::metadata(doc_split_zip).unwrap().len(), 89158);
+}
+
+#[test]
+fn test_extract_missing_volume() {
+  let temp_dir = TempDir::new().unwrap();
+  let data_root = get_volumes_data_root();
+  // Only the second volume is present
+  fs::copy(
+    data_root.join("split_7z.7z.002"),
+    temp_dir.path().join("split_7z.7z.002"),
+  )
+  .unwrap();
+
+  let config = rzip_lib::RZipExtractConfig {
+    target_path: temp_dir.path().join("split_7z.7z.002"),
+    out_dir: None,
+    delete_after_extracting: false,
+    ..Default::default()
+  };
+
+  let out_path = rzip_lib::get_out_path_for_archive(&config.target_path, &config).unwrap();
+  let res = rzip_lib::recursive_file_extract(&config.target_path, &out_path, &config);
+  match res {
+    Err(RZipError::MissingVolumes(_, missing)) => {
+      assert_eq!(missing, vec![temp_dir.path().join("split_7z.7z.001")]);
+    }
+    res => panic!("Expected missing volumes, got {res:?}"),
+  }
+}